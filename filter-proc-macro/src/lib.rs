@@ -103,12 +103,23 @@ fn derive_args_impl(ast: &DeriveInput) -> TokenStream {
 ///
 /// Note that patterns are not supported beyond `(mut)? x: T`
 ///
-/// The return type must be `scrapelect_filter_types::Result<PValue<'doc>>`
+/// Use `#[filter_fn(impure)]` for a filter that is not pure (see
+/// `scrapelect_filter_types::Filter::PURE`), like one that binds a variable or
+/// fetches a document.
+///
+/// The return type must be `scrapelect_filter_types::Result<PValue<'doc>>`.  The
+/// function can be an `async fn` if the filter needs to wait on something, like
+/// a timer or a request.
 ///
 /// # Panics
 /// Panics if the token stream is not valid or the function signature is not as specified.
 #[proc_macro_attribute]
-pub fn filter_fn(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn filter_fn(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let pure = match attr.to_string().as_str() {
+        "" => None,
+        "impure" => Some(quote! { const PURE: bool = false; }),
+        other => panic!("unknown #[filter_fn] option `{other}` (expected `impure`)"),
+    };
     let func: syn::ItemFn = syn::parse(item).expect("token stream should be valid");
     let inner = func.clone();
     let name = func.sig.ident;
@@ -146,6 +157,8 @@ pub fn filter_fn(_attr: TokenStream, item: TokenStream) -> TokenStream {
         (None, None)
     };
 
+    let call_await = func.sig.asyncness.map(|_| quote! { .await });
+
     let call_args = std::iter::once(value.clone().into_token_stream())
         .chain(arg.clone().map(|arg| quote! {args.#arg}))
         .chain(ctx.clone().into_iter().map(|x| quote! {#x }));
@@ -165,8 +178,9 @@ pub fn filter_fn(_attr: TokenStream, item: TokenStream) -> TokenStream {
             impl scrapelect_filter_types::Filter for Filter {
                 type Args<'doc> = Args<'doc>;
                 type Value<'doc> = #vty;
+                #pure
 
-                fn apply<'ast, 'ctx, E: scrapelect_filter_types::ElementContextView<'ast, 'ctx> + ?Sized>(
+                async fn apply<'ast, 'ctx, E: scrapelect_filter_types::ElementContextView<'ast, 'ctx> + ?Sized>(
                     #value: Self::Value<'ctx>,
                     args: Self::Args<'ctx>,
                     #[allow(unused)]
@@ -178,7 +192,7 @@ pub fn filter_fn(_attr: TokenStream, item: TokenStream) -> TokenStream {
                     #[allow(clippy::needless_lifetimes, clippy::needless_pass_by_value, clippy::unnecessary_wraps)]
                     #inner
 
-                    #name (#(#call_args),*)
+                    #name (#(#call_args),*) #call_await
                 }
             }
 
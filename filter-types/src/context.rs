@@ -1,9 +1,18 @@
-use std::{borrow::Cow, collections::BTreeMap, sync::Arc};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    fmt,
+    future::Future,
+    hash::{Hash, Hasher},
+    pin::Pin,
+    sync::Arc,
+};
 
 use serde::Serialize;
 use url::Url;
 
-use crate::{Data, EValue, Element, MessageExt, Result, Value};
+use crate::{Data, Diagnostic, Diagnostics, EValue, Element, MessageExt, Result, Seeds, Value};
 
 /// A reference to a parsed HTML element.
 pub use scraper::ElementRef;
@@ -25,7 +34,7 @@ pub trait ElementContextView<'ast, 'ctx> {
     /// Returns an `Err` if `name` cannot be rebound (e.g., it is `"element"`).
     fn set(&mut self, name: Cow<'ast, str>, value: EValue<'ctx>) -> Result<()> {
         match &*name {
-            immutable @ "element" => {
+            immutable @ ("element" | "document") => {
                 bail!("assignment to immutable binding `{immutable}`")
             }
             _ => self.set_inner(name, value),
@@ -40,7 +49,7 @@ pub trait ElementContextView<'ast, 'ctx> {
     fn set_inner(&mut self, name: Cow<'ast, str>, value: EValue<'ctx>) -> Result<()>;
 
     /// Gets the binding with name `id`, if it is present. Handles
-    /// retrieving special bindings like `element`.  Looks in this
+    /// retrieving special bindings like `element` and `document`.  Looks in this
     /// context and all parent contexts, starting innermost first.
     ///
     /// Implementors should not implement this and instead use [`get_inner`](Self::get_inner).
@@ -52,6 +61,7 @@ pub trait ElementContextView<'ast, 'ctx> {
     fn get(&self, id: &str) -> Result<EValue<'ctx>> {
         match id {
             "element" => Ok(self.element().into()),
+            "document" => self.document(),
             _ => self.get_inner(id),
         }
     }
@@ -65,6 +75,40 @@ pub trait ElementContextView<'ast, 'ctx> {
     /// scope or any parent scopes.
     fn get_inner(&self, id: &str) -> Result<EValue<'ctx>>;
 
+    /// Removes the binding with name `name` from this context (not from any
+    /// parent contexts) and returns its value, if it was bound here.  This is for
+    /// filters that bind a variable only while they run, like `each`.
+    ///
+    /// The default implementation does not support removing bindings, and
+    /// returns `None`.
+    fn remove_inner(&mut self, name: &str) -> Option<EValue<'ctx>> {
+        let _ = name;
+        None
+    }
+
+    /// Appends `value` to the list bound to `name`, in the context that it is
+    /// bound in, or binds `name` to a new list with just `value` in this
+    /// context if it is not bound yet.
+    ///
+    /// The default implementation [gets](Self::get) the list, and [sets](Self::set)
+    /// a copy with `value` appended in this context.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if `name` is bound to something other than a list, or
+    /// cannot be rebound.
+    fn append(&mut self, name: Cow<'ast, str>, value: EValue<'ctx>) -> Result<()> {
+        let list = match self.get(&name) {
+            Ok(Value::List(mut list)) => {
+                list.push(value);
+                list
+            }
+            Ok(other) => bail!("can't append to `${name}`, which is not a list: {other}"),
+            Err(_) => vec![value],
+        };
+        self.set(name, Value::List(list))
+    }
+
     /// Returns a [reference](ElementRef) to the root element of this block.
     #[must_use]
     fn element(&self) -> ElementRef<'ctx>;
@@ -72,6 +116,241 @@ pub trait ElementContextView<'ast, 'ctx> {
     /// Returns a reference to the URL of the document that this element is in.
     #[must_use]
     fn url(&self) -> &Url;
+
+    /// Returns a reference to the URL that relative URLs in this context are
+    /// resolved against.
+    ///
+    /// The default implementation returns the [URL of the document](Self::url).
+    #[must_use]
+    fn base_url(&self) -> &Url {
+        self.url()
+    }
+
+    /// Reports a non-fatal problem, like a filter skipping invalid input.
+    ///
+    /// The default implementation prints `message` to standard error.
+    fn warn(&self, message: &str) {
+        eprintln!("warning: {message}");
+    }
+
+    /// Whether debugging output, like from the `dbg` filter, should not be
+    /// printed.
+    ///
+    /// The default implementation returns `false`.
+    #[must_use]
+    fn quiet(&self) -> bool {
+        false
+    }
+
+    /// Returns a seed for a filter that makes a random choice, like `sample`,
+    /// or `None` if the choice should be random every run.
+    ///
+    /// The default implementation returns `None`.
+    fn next_seed(&self) -> Option<u64> {
+        None
+    }
+
+    /// Fetches the document at `url`, sending the extra `(name, value)` request
+    /// `headers`, and returns a reference to its root element that lives as long
+    /// as this context.
+    ///
+    /// The default implementation does not support fetching.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if fetching is not supported in this context, or the
+    /// document cannot be fetched.
+    fn fetch(&self, url: Url, headers: Vec<(String, String)>) -> FetchFuture<'ctx> {
+        let _ = headers;
+        Box::pin(std::future::ready(Err(other!(
+            "fetching `{url}` is not supported here"
+        ))))
+    }
+
+    /// Returns the `(name, value)` cookies that the last response from `url` set
+    /// in its `Set-Cookie` headers.
+    ///
+    /// The default implementation does not support fetching.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if fetching is not supported in this context, or nothing
+    /// was fetched from `url` yet.
+    fn response_cookies(&self, url: &Url) -> Result<Vec<(String, String)>> {
+        bail!("fetching `{url}` is not supported here")
+    }
+
+    /// Returns the `Content-Type` header of the last response from `url`, or
+    /// `None` if it had none or was not fetched over HTTP.
+    ///
+    /// The default implementation does not support fetching.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if fetching is not supported in this context.
+    fn content_type(&self, url: &Url) -> Result<Option<String>> {
+        bail!("fetching `{url}` is not supported here")
+    }
+
+    /// Returns the parsed JSON of the [current page](Self::url), which is bound
+    /// to `$document`, if the page is a JSON document.
+    ///
+    /// The default implementation does not support JSON documents.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the current page is not a JSON document.
+    fn document(&self) -> Result<EValue<'ctx>> {
+        bail!("`{}` is not a JSON document", self.url())
+    }
+
+    /// Returns how many pages were followed from the starting page to reach the
+    /// [page of this context](Self::url), which is `0` on the starting page.
+    ///
+    /// The default implementation returns `0`.
+    #[must_use]
+    fn depth(&self) -> usize {
+        0
+    }
+
+    /// Returns the maximum [depth](Self::depth) of a page that can be followed
+    /// or fetched from this context, or `None` if there is no limit.
+    ///
+    /// The default implementation returns `None`.
+    #[must_use]
+    fn max_depth(&self) -> Option<usize> {
+        None
+    }
+
+    /// Whether the calls of pure filters in this context are memoized with
+    /// [`memoized`](Self::memoized) and [`memoize`](Self::memoize).
+    ///
+    /// The default implementation returns `false`.
+    #[must_use]
+    fn memoizes(&self) -> bool {
+        false
+    }
+
+    /// Returns the result of an earlier call of the filter `name` on `value`
+    /// with `args` in this context (not in any parent contexts), if it was
+    /// [memoized](Self::memoize).
+    ///
+    /// The default implementation returns `None`.
+    fn memoized(
+        &self,
+        name: &str,
+        value: &EValue<'ctx>,
+        args: &BTreeMap<&'ast str, EValue<'ctx>>,
+    ) -> Option<EValue<'ctx>> {
+        let _ = (name, value, args);
+        None
+    }
+
+    /// Records that `call` returned `result` in this context, to be reused by
+    /// later equal calls.
+    ///
+    /// The default implementation does nothing.
+    fn memoize(&mut self, call: FilterCall<'ast, 'ctx>, result: EValue<'ctx>) {
+        let _ = (call, result);
+    }
+}
+
+/// A call of a pure filter, by its `name`, with its input `value` and `args`,
+/// for [memoizing](ElementContextView::memoize) its result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterCall<'ast, 'ctx> {
+    /// The name of the filter.
+    pub name: &'ast str,
+    /// The value the filter is called on.
+    pub value: EValue<'ctx>,
+    /// The arguments the filter is called with.
+    pub args: BTreeMap<&'ast str, EValue<'ctx>>,
+}
+
+impl<'ast, 'ctx> FilterCall<'ast, 'ctx> {
+    /// Whether this is a call of the filter `name` on `value` with `args`.
+    fn is(
+        &self,
+        name: &str,
+        value: &EValue<'ctx>,
+        args: &BTreeMap<&'ast str, EValue<'ctx>>,
+    ) -> bool {
+        self.name == name && &self.value == value && &self.args == args
+    }
+}
+
+/// Hashes a call of the filter `name` on `value` with `args`, so that equal
+/// calls have equal hashes.
+fn hash_call(name: &str, value: &EValue, args: &BTreeMap<&str, EValue>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    hash_value(value, &mut hasher);
+    args.len().hash(&mut hasher);
+    for (name, value) in args {
+        name.hash(&mut hasher);
+        hash_value(value, &mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Hashes `value` consistently with its `==`, which [`Value`] can't implement
+/// [`Hash`] for because it has floats.
+fn hash_value(value: &EValue, hasher: &mut impl Hasher) {
+    std::mem::discriminant(value).hash(hasher);
+    match value {
+        Value::Null => {}
+        // `0.0 == -0.0`, so they hash the same
+        Value::Float(x) if *x == 0.0 => 0_u64.hash(hasher),
+        Value::Float(x) => x.to_bits().hash(hasher),
+        Value::Int(x) => x.hash(hasher),
+        Value::Bool(x) => x.hash(hasher),
+        Value::String(x) => x.hash(hasher),
+        Value::List(xs) => {
+            xs.len().hash(hasher);
+            for x in xs {
+                hash_value(x, hasher);
+            }
+        }
+        Value::Structure(xs) => {
+            xs.len().hash(hasher);
+            for (name, x) in xs {
+                name.hash(hasher);
+                hash_value(x, hasher);
+            }
+        }
+        Value::Extra(Element::Element(element)) => element.id().hash(hasher),
+    }
+}
+
+/// The values appended to a list in [`Linked::appended`], grouped by where they
+/// were appended, in document order.
+pub type Appended = BTreeMap<Vec<u64>, Vec<Value>>;
+
+/// The future returned by [`ElementContextView::fetch`] and [`Fetch::fetch`].
+pub type FetchFuture<'a> = Pin<Box<dyn Future<Output = Result<ElementRef<'a>>> + 'a>>;
+
+/// Fetches documents for [`Linked`] contexts, keeping each document alive for as
+/// long as the fetcher.
+pub trait Fetch: fmt::Debug {
+    /// Fetches the document at `url`, sending the extra `(name, value)` request
+    /// `headers`, and returns a reference to its root element.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the document cannot be fetched.
+    fn fetch(&self, url: Url, headers: Vec<(String, String)>) -> FetchFuture<'_>;
+
+    /// Returns the `(name, value)` cookies that the last response from `url` set
+    /// in its `Set-Cookie` headers, or `None` if nothing was fetched from `url`.
+    fn response_cookies(&self, url: &Url) -> Option<Vec<(String, String)>>;
+
+    /// Returns the `Content-Type` header of the last response from `url`, or
+    /// `None` if it had none or nothing was fetched from `url` over HTTP.
+    fn content_type(&self, url: &Url) -> Option<String>;
+
+    /// Returns the parsed JSON of the document at `url`, or `None` if it is not
+    /// a JSON document.
+    fn document(&self, url: &Url) -> Option<Value>;
 }
 
 /// An expansion of [`ElementContextView`] for interacting with entering and exiting
@@ -117,6 +396,45 @@ pub struct Linked<'ast, 'ctx> {
     /// The URL of this context, set by URL recursion if indicated, or the parent's
     /// URL if not.
     pub url: Url,
+    /// Where to collect warnings, shared with the parent scope.  If `None`,
+    /// warnings are printed to standard error.
+    pub diagnostics: Option<&'ctx Diagnostics>,
+    /// How to fetch documents, shared with the parent scope.  If `None`,
+    /// fetching is not supported.
+    pub fetcher: Option<&'ctx dyn Fetch>,
+    /// The URL to resolve relative URLs against, shared with the parent scope.
+    /// If `None`, they are resolved against `url`.
+    pub base_url: Option<&'ctx Url>,
+    /// Whether to suppress debugging output and printed warnings, shared with
+    /// the parent scope.  Warnings are still collected into `diagnostics`.
+    pub quiet: bool,
+    /// The seeds for random choices in this context.  A nested context derives
+    /// its seeds from its parent's and its position in it.  If `None`, random
+    /// choices are different every run.
+    pub seeds: Option<Seeds>,
+    /// How many random choices were seeded in this context.
+    seeded: Cell<u64>,
+    /// How many contexts were nested in this context.
+    nested: Cell<u64>,
+    /// The index of this context among the contexts nested in its parent.
+    index: u64,
+    /// The results of the pure filter calls in this context (and not its parent
+    /// or nested contexts), if they are memoized.  Nested contexts memoize if
+    /// the parent does.  The calls are grouped by their hash.
+    pub memo: Option<HashMap<u64, Vec<(FilterCall<'ast, 'ctx>, EValue<'ctx>)>>>,
+    /// How many pages were followed from the starting page to reach `url`.  A
+    /// nested context is one deeper than its parent if it has a different URL.
+    pub depth: usize,
+    /// The maximum depth of a page that can be followed or fetched, shared with
+    /// the parent scope.  If `None`, there is no limit.
+    pub max_depth: Option<usize>,
+    /// The values that nested contexts [appended](ElementContextView::append)
+    /// to lists bound in this context, that are not moved into `bindings` yet.
+    /// They are keyed by where they were appended, so they are moved in
+    /// document order, even if the nested blocks finish in a different order.
+    /// They are data, because elements don't outlive the nested block they are
+    /// from.
+    pub appended: RefCell<BTreeMap<Arc<str>, Appended>>,
 }
 
 /// Holds a mapping of named bindings to [`Value`]s.
@@ -189,18 +507,111 @@ impl<'ast, 'ctx> Linked<'ast, 'ctx> {
     /// Creates an empty [`Bindings`] map for this context.
     #[must_use]
     pub fn new(element: ElementRef<'ctx>, parent: Option<&'ctx Self>, url: Url) -> Self {
+        // sibling contexts are nested in document order, even if they then run
+        // at the same time
+        let index = parent.map_or(0, |x| x.nested.replace(x.nested.get() + 1));
+        let seeds = parent
+            .and_then(|x| x.seeds)
+            .map(|seeds| seeds.derive((index, url.as_str(), element.id())));
+
         Self {
             bindings: Bindings::new(),
             element,
             parent,
             url,
+            diagnostics: parent.and_then(|x| x.diagnostics),
+            fetcher: parent.and_then(|x| x.fetcher),
+            base_url: parent.and_then(|x| x.base_url),
+            quiet: parent.is_some_and(|x| x.quiet),
+            seeds,
+            seeded: Cell::new(0),
+            nested: Cell::new(0),
+            index,
+            memo: parent.and_then(|x| x.memo.as_ref()).map(|_| HashMap::new()),
+            depth: parent.map_or(0, |x| x.depth),
+            max_depth: parent.and_then(|x| x.max_depth),
+            appended: RefCell::default(),
         }
     }
+
+    /// Moves the values that nested contexts appended to the list bound to
+    /// `name` into it.
+    fn flush_appended(&mut self, name: &str) {
+        let Some(appended) = self.appended.get_mut().remove(name) else {
+            return;
+        };
+
+        if let Some(Value::List(list)) = self.bindings.0.get_mut(name) {
+            list.extend(appended.into_values().flatten().map(Value::from_data));
+        }
+    }
+
+    /// Collects the warnings reported in this context and its nested contexts
+    /// into `diagnostics`.
+    #[must_use]
+    pub const fn with_diagnostics(mut self, diagnostics: &'ctx Diagnostics) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    /// Fetches documents with `fetcher` in this context and its nested contexts.
+    #[must_use]
+    pub fn with_fetcher(mut self, fetcher: &'ctx dyn Fetch) -> Self {
+        self.fetcher = Some(fetcher);
+        self
+    }
+
+    /// Resolves relative URLs against `base_url` instead of the document URL in
+    /// this context and its nested contexts.
+    #[must_use]
+    pub const fn with_base_url(mut self, base_url: &'ctx Url) -> Self {
+        self.base_url = Some(base_url);
+        self
+    }
+
+    /// Suppresses debugging output and printed warnings in this context and
+    /// its nested contexts, if `quiet` is `true`.
+    #[must_use]
+    pub const fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Seeds the random choices in this context and its nested contexts from
+    /// `seeds`.
+    #[must_use]
+    pub const fn with_seeds(mut self, seeds: Seeds) -> Self {
+        self.seeds = Some(seeds);
+        self
+    }
+
+    /// Stops following or fetching pages deeper than `max_depth` in this context
+    /// and its nested contexts, if it is `Some`.
+    #[must_use]
+    pub const fn with_max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Memoizes the calls of pure filters in this context and its nested
+    /// contexts, each with its own results, if `memoize` is `true`.
+    #[must_use]
+    pub fn with_memo(mut self, memoize: bool) -> Self {
+        self.memo = memoize.then(HashMap::new);
+        self
+    }
 }
 
 impl<'ast, 'ctx> ElementContextView<'ast, 'ctx> for Linked<'ast, 'ctx> {
     fn get_inner(&self, id: &str) -> Result<EValue<'ctx>> {
         match self.bindings.0.get(id) {
+            Some(Value::List(list)) => {
+                let mut list = list.clone();
+                if let Some(appended) = self.appended.borrow().get(id) {
+                    list.extend(appended.values().flatten().cloned().map(Value::from_data));
+                }
+                Ok(Value::List(list))
+            }
             Some(id) => Ok(id.clone()),
             None => self
                 .parent
@@ -211,19 +622,173 @@ impl<'ast, 'ctx> ElementContextView<'ast, 'ctx> for Linked<'ast, 'ctx> {
 
     #[inline]
     fn set_inner(&mut self, name: Cow<'ast, str>, value: EValue<'ctx>) -> Result<()> {
+        self.appended.get_mut().remove(&*name);
         self.bindings.0.insert(name, value);
         Ok(())
     }
 
+    #[inline]
+    fn remove_inner(&mut self, name: &str) -> Option<EValue<'ctx>> {
+        self.flush_appended(name);
+        self.bindings.0.remove(name)
+    }
+
+    fn append(&mut self, name: Cow<'ast, str>, value: EValue<'ctx>) -> Result<()> {
+        if let immutable @ ("element" | "document") = &*name {
+            bail!("assignment to immutable binding `{immutable}`");
+        }
+
+        self.flush_appended(&name);
+        match self.bindings.0.get_mut(&name) {
+            Some(Value::List(list)) => {
+                list.push(value);
+                return Ok(());
+            }
+            Some(other) => bail!("can't append to `${name}`, which is not a list: {other}"),
+            None => {}
+        }
+
+        // where the value is in document order, from the outermost context:
+        // the index of each nested context (odd), and then how many contexts
+        // this one nested before appending (even), so the value comes after
+        // the values from the blocks before it, and before the ones after it
+        let mut position = vec![2 * self.nested.get()];
+        let mut index = self.index;
+        let mut parent = self.parent;
+        while let Some(scope) = parent {
+            position.push(2 * index + 1);
+            match scope.bindings.0.get(&name) {
+                Some(Value::List(_)) => {
+                    let value = value.into_data().with_msg(|| {
+                        format!("can't append an element to `${name}` from a nested block")
+                    })?;
+                    position.reverse();
+                    scope
+                        .appended
+                        .borrow_mut()
+                        .entry(Arc::from(&*name))
+                        .or_default()
+                        .entry(position)
+                        .or_default()
+                        .push(value);
+                    return Ok(());
+                }
+                Some(other) => bail!("can't append to `${name}`, which is not a list: {other}"),
+                None => {
+                    index = scope.index;
+                    parent = scope.parent;
+                }
+            }
+        }
+
+        self.bindings.0.insert(name, Value::List(vec![value]));
+        Ok(())
+    }
+
     #[inline]
     fn url(&self) -> &Url {
         &self.url
     }
 
+    #[inline]
+    fn base_url(&self) -> &Url {
+        self.base_url.unwrap_or(&self.url)
+    }
+
     #[inline]
     fn element(&self) -> ElementRef<'ctx> {
         self.element
     }
+
+    fn warn(&self, message: &str) {
+        let diagnostic = Diagnostic::new(message.to_owned(), self.url.clone(), self.element);
+        match self.diagnostics {
+            Some(diagnostics) => diagnostics.push(diagnostic),
+            None if self.quiet => (),
+            None => eprintln!("{diagnostic}"),
+        }
+    }
+
+    #[inline]
+    fn quiet(&self) -> bool {
+        self.quiet
+    }
+
+    fn next_seed(&self) -> Option<u64> {
+        let n = self.seeded.replace(self.seeded.get() + 1);
+        self.seeds.map(|seeds| seeds.nth(n))
+    }
+
+    fn fetch(&self, url: Url, headers: Vec<(String, String)>) -> FetchFuture<'ctx> {
+        match self.fetcher {
+            Some(fetcher) => match self.max_depth {
+                Some(max) if self.depth >= max => Box::pin(std::future::ready(Err(other!(
+                    "not fetching `{url}`: it is deeper than the maximum depth of {max}"
+                )))),
+                _ => fetcher.fetch(url, headers),
+            },
+            None => Box::pin(std::future::ready(Err(other!(
+                "fetching `{url}` is not supported here"
+            )))),
+        }
+    }
+
+    fn response_cookies(&self, url: &Url) -> Result<Vec<(String, String)>> {
+        self.fetcher
+            .with_msg(|| format!("fetching `{url}` is not supported here"))?
+            .response_cookies(url)
+            .with_msg(|| format!("no response was received from `{url}` yet"))
+    }
+
+    fn content_type(&self, url: &Url) -> Result<Option<String>> {
+        Ok(self
+            .fetcher
+            .with_msg(|| format!("fetching `{url}` is not supported here"))?
+            .content_type(url))
+    }
+
+    fn document(&self) -> Result<EValue<'ctx>> {
+        self.fetcher
+            .and_then(|x| x.document(&self.url))
+            .map(Value::from_data)
+            .with_msg(|| format!("`{}` is not a JSON document", self.url))
+    }
+
+    #[inline]
+    fn depth(&self) -> usize {
+        self.depth
+    }
+
+    #[inline]
+    fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    #[inline]
+    fn memoizes(&self) -> bool {
+        self.memo.is_some()
+    }
+
+    fn memoized(
+        &self,
+        name: &str,
+        value: &EValue<'ctx>,
+        args: &BTreeMap<&'ast str, EValue<'ctx>>,
+    ) -> Option<EValue<'ctx>> {
+        self.memo
+            .as_ref()?
+            .get(&hash_call(name, value, args))?
+            .iter()
+            .find_map(|(call, result)| call.is(name, value, args).then(|| result.clone()))
+    }
+
+    fn memoize(&mut self, call: FilterCall<'ast, 'ctx>, result: EValue<'ctx>) {
+        if let Some(memo) = &mut self.memo {
+            memo.entry(hash_call(call.name, &call.value, &call.args))
+                .or_default()
+                .push((call, result));
+        }
+    }
 }
 
 impl<'ast, 'ctx> ElementContext<'ast, 'ctx> for Linked<'ast, 'ctx> {
@@ -235,7 +800,11 @@ impl<'ast, 'ctx> ElementContext<'ast, 'ctx> for Linked<'ast, 'ctx> {
     }
 
     #[inline]
-    fn into_bindings(self) -> Bindings<'ast> {
+    fn into_bindings(mut self) -> Bindings<'ast> {
+        let names: Vec<_> = self.appended.get_mut().keys().cloned().collect();
+        for name in names {
+            self.flush_appended(&name);
+        }
         self.bindings.into_data()
     }
 
@@ -244,6 +813,9 @@ impl<'ast, 'ctx> ElementContext<'ast, 'ctx> for Linked<'ast, 'ctx> {
         url: Option<Url>,
         element: ElementRef<'inner>,
     ) -> Self::Nested<'inner> {
-        Linked::new(element, Some(self), url.unwrap_or_else(|| self.url.clone()))
+        let depth = self.depth + usize::from(url.is_some());
+        let mut nested = Linked::new(element, Some(self), url.unwrap_or_else(|| self.url.clone()));
+        nested.depth = depth;
+        nested
     }
 }
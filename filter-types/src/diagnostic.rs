@@ -0,0 +1,85 @@
+//! Non-fatal problems reported while running a program, like a filter that
+//! skips invalid input.
+
+use std::{fmt, sync::Mutex};
+
+use scraper::ElementRef;
+use url::Url;
+
+/// A warning reported with [`ElementContextView::warn`](crate::ElementContextView::warn).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The warning message.
+    pub message: String,
+    /// The URL of the document that was being read.
+    pub url: Url,
+    /// A selector describing the element of the current element context block,
+    /// like `li#first.item`.
+    pub element: String,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic with `message`, reported in the element context
+    /// block of `element` in the document at `url`.
+    #[must_use]
+    pub fn new(message: String, url: Url, element: ElementRef<'_>) -> Self {
+        let element = element.value();
+
+        let mut selector = element.name().to_owned();
+        if let Some(id) = element.id() {
+            selector.push('#');
+            selector.push_str(id);
+        }
+        for class in element.classes() {
+            selector.push('.');
+            selector.push_str(class);
+        }
+
+        Self {
+            message,
+            url,
+            element: selector,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "warning: {} (in `{}` at {})",
+            self.message, self.element, self.url
+        )
+    }
+}
+
+/// A buffer that collects the [`Diagnostic`]s reported while running a program.
+#[derive(Debug, Default)]
+pub struct Diagnostics(Mutex<Vec<Diagnostic>>);
+
+impl Diagnostics {
+    /// Creates a new, empty buffer.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `diagnostic` to the end of the buffer.
+    pub fn push(&self, diagnostic: Diagnostic) {
+        self.0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(diagnostic);
+    }
+
+    /// Removes and returns all of the diagnostics in the buffer, in the order
+    /// they were reported.
+    pub fn take(&self) -> Vec<Diagnostic> {
+        std::mem::take(
+            &mut *self
+                .0
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner),
+        )
+    }
+}
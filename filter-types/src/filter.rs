@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, future::Future, pin::Pin};
 
 use super::{
     value::{EValue, PValue, Pipeline, TryFromValue},
@@ -49,18 +49,28 @@ pub trait Filter {
     /// The type of the arguments for this filter; see [`Args`]
     type Args<'doc>: Args<'doc>;
 
+    /// Whether this filter is **pure**: called on equal values with equal arguments
+    /// in the same element context, it returns equal results and has no other
+    /// effects, so its calls can be [memoized](crate::ElementContextView::memoized).
+    ///
+    /// Filters that bind variables, fetch documents, report warnings, or make random
+    /// choices should set this to `false`.  With `filter_fn`, use `#[filter_fn(impure)]`.
+    const PURE: bool = true;
+
     /// Call this filter with the given `value`, `args`, and `ctx`, returning
-    /// the modified value by the filter call or an error.
+    /// a future that resolves to the modified value by the filter call or an error.
+    ///
+    /// This can be implemented with an `async fn`.
     ///
     /// # Errors
     ///
-    /// An implementor should return `Err` if the filter was called with invalid
+    /// An implementor should resolve to an `Err` if the filter was called with invalid
     /// arguments, or if the filter cannot be called for some reason.
     fn apply<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
         value: Self::Value<'doc>,
         args: Self::Args<'doc>,
         ctx: &mut E,
-    ) -> Result<PValue<'doc>>;
+    ) -> impl Future<Output = Result<PValue<'doc>>>;
 }
 
 /// An object-safe version of [`Filter`]. All `F: Filter` implement this trait,
@@ -75,27 +85,47 @@ pub trait Filter {
 ///    can be called from anywhere in the program, and you will have to reason out the
 ///    soundness of having the state.
 pub trait FilterDyn {
-    /// Call this filter with the given `value`, `args`, and `ctx`.
+    /// Call this filter with the given `value`, `args`, and `ctx`, returning a
+    /// future that resolves to the result of the filter call.
     ///
     /// # Errors
     ///
-    /// Implementors should return an `Err` if the filter call is invalid.
-    fn apply<'ast, 'doc>(
-        &self,
+    /// Implementors should resolve to an `Err` if the filter call is invalid.
+    fn apply<'a, 'ast: 'a, 'doc: 'a>(
+        &'a self,
         value: PValue<'doc>,
         args: BTreeMap<&'ast str, EValue<'doc>>,
-        ctx: &mut dyn ElementContextView<'ast, 'doc>,
-    ) -> Result<PValue<'doc>>;
+        ctx: &'a mut dyn ElementContextView<'ast, 'doc>,
+    ) -> FilterFuture<'a, 'doc>;
+
+    /// Whether this filter is pure, like [`Filter::PURE`].
+    ///
+    /// The default implementation returns `false`, so calls are never memoized.
+    /// The implementation for all `F: Filter` returns `F::PURE`.
+    fn is_pure(&self) -> bool {
+        false
+    }
 }
 
+/// The future returned by [`FilterDyn::apply`], which borrows from the filter
+/// and context for `'a`.
+pub type FilterFuture<'a, 'doc> = Pin<Box<dyn Future<Output = Result<PValue<'doc>>> + 'a>>;
+
 impl<F: Filter> FilterDyn for F {
     #[inline]
-    fn apply<'ast, 'doc>(
-        &self,
+    fn apply<'a, 'ast: 'a, 'doc: 'a>(
+        &'a self,
         value: PValue<'doc>,
         args: BTreeMap<&'ast str, EValue<'doc>>,
-        ctx: &mut dyn ElementContextView<'ast, 'doc>,
-    ) -> Result<PValue<'doc>> {
-        F::apply(value.try_unwrap()?, F::Args::try_deserialize(args)?, ctx)
+        ctx: &'a mut dyn ElementContextView<'ast, 'doc>,
+    ) -> FilterFuture<'a, 'doc> {
+        Box::pin(async move {
+            F::apply(value.try_unwrap()?, F::Args::try_deserialize(args)?, ctx).await
+        })
+    }
+
+    #[inline]
+    fn is_pure(&self) -> bool {
+        F::PURE
     }
 }
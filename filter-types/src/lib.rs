@@ -17,7 +17,8 @@
 //!
 //! Provides the [`ElementContext`] and [`ElementContextView`] traits for reading and manipulating state in
 //! the scope of an element context block, as well as the [`Linked`] struct that implements
-//! this trait as a borrowed linked list
+//! this trait as a borrowed linked list.  Warnings reported by filters are collected
+//! as [`Diagnostic`]s, and random choices are seeded from [`Seeds`].
 //!
 //! # Filters
 //!
@@ -28,12 +29,18 @@
 #[macro_use]
 mod error;
 mod context;
+mod diagnostic;
 mod filter;
+mod seed;
 mod value;
 
-pub use context::{Bindings, ElementContext, ElementContextView, Linked};
+pub use context::{
+    Appended, Bindings, ElementContext, ElementContextView, Fetch, FetchFuture, FilterCall, Linked,
+};
+pub use diagnostic::{Diagnostic, Diagnostics};
 pub use error::{Error, MessageExt, Result, WrapExt};
-pub use filter::{filter_fn, Args, Filter, FilterDyn};
+pub use filter::{filter_fn, Args, Filter, FilterDyn, FilterFuture};
+pub use seed::Seeds;
 pub use value::{
     Data, EValue, Element, ListIter, Number, PValue, Pipeline, StructIter, TryFromValue, Value,
 };
@@ -0,0 +1,72 @@
+//! Seeds for the random choices made while running a program, so that they
+//! can be reproduced.
+
+use std::hash::{Hash, Hasher};
+
+/// The seeds for filters that make random choices, like `sample`, in one
+/// context, so that a run started from the same seed makes the same choices.
+///
+/// The seeds of a nested context are [derived](Self::derive) from the seeds of
+/// its parent and its position in it, and the [`nth`](Self::nth) choice in a
+/// context is seeded by its index, so the choices don't depend on the order
+/// that concurrent blocks or pages finish in.  The numbers are mixed with
+/// [SplitMix64](https://prng.di.unimi.it/splitmix64.c).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Seeds(u64);
+
+impl Seeds {
+    /// Creates the seeds of a top-level context from `seed`.
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Returns the seeds of a context at `position` in this one, like the
+    /// index, URL, and element of a nested block.
+    #[must_use]
+    pub fn derive(self, position: impl Hash) -> Self {
+        let mut mixer = Mixer(self.0);
+        position.hash(&mut mixer);
+        Self(mixer.finish())
+    }
+
+    /// Returns the seed of the `n`th random choice in this context.
+    #[must_use]
+    pub const fn nth(self, n: u64) -> u64 {
+        mix(self.0 ^ mix(n))
+    }
+}
+
+/// A [`Hasher`] that mixes each value into its state with [`mix`], so that its
+/// output is the same on every platform and Rust version.
+struct Mixer(u64);
+
+impl Hasher for Mixer {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.write_u64(u64::from_le_bytes(word));
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.0 = mix(self.0 ^ i);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+}
+
+/// One step of SplitMix64 from the state `z`.
+const fn mix(z: u64) -> u64 {
+    let mut z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -354,6 +354,27 @@ where
     }
 }
 
+/// Converts parsed JSON into a [`Value`].  Numbers become `Int`s if they fit
+/// in an `i64`, and `Float`s otherwise.
+impl<X> From<serde_json::Value> for Value<X> {
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => Self::Null,
+            serde_json::Value::Bool(b) => Self::Bool(b),
+            serde_json::Value::Number(n) => n
+                .as_i64()
+                .map_or_else(|| Self::Float(n.as_f64().unwrap_or(f64::NAN)), Self::Int),
+            serde_json::Value::String(s) => Self::String(s.into()),
+            serde_json::Value::Array(a) => Self::List(a.into_iter().map(Self::from).collect()),
+            serde_json::Value::Object(o) => Self::Structure(
+                o.into_iter()
+                    .map(|(k, v)| (Arc::from(k), Self::from(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
 impl<'a, X> From<&'a str> for Value<X> {
     fn from(value: &'a str) -> Self {
         Self::String(Arc::from(value))
@@ -0,0 +1,172 @@
+//! The kinds of failures that running a program can end with, for library users
+//! that need to handle them differently, like retrying after a timeout.
+
+use core::fmt;
+use std::error::Error as StdError;
+
+use scrapelect_filter_types::Error;
+
+use crate::frontend::ParseError;
+
+/// An error from [`run`](crate::run), sorted into what went wrong.
+///
+/// Each variant keeps the full [`Error`], with its notes and sources.  To sort
+/// an error from [`Interpreter::interpret`](crate::interpreter::Interpreter::interpret),
+/// use [`ScrapeError::from`].
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum ScrapeError {
+    /// A request took longer than the client's timeout.
+    Timeout(Error),
+    /// A request could not be sent or its response could not be read, or the
+    /// response had an error status code (`4xx` or `5xx`) that isn't allowed.
+    Network(Error),
+    /// The program could not be parsed, or its head has an invalid directive.
+    Parse(Error),
+    /// Anything else that failed while the program was running, like a filter
+    /// that doesn't exist or got an argument of the wrong type.
+    Runtime(Error),
+}
+
+impl ScrapeError {
+    /// Returns the error, regardless of its kind.
+    #[must_use]
+    pub fn inner(&self) -> &Error {
+        match self {
+            Self::Timeout(e) | Self::Network(e) | Self::Parse(e) | Self::Runtime(e) => e,
+        }
+    }
+
+    /// Returns the error, regardless of its kind.
+    #[must_use]
+    pub fn into_inner(self) -> Error {
+        match self {
+            Self::Timeout(e) | Self::Network(e) | Self::Parse(e) | Self::Runtime(e) => e,
+        }
+    }
+}
+
+impl fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.inner())
+    }
+}
+
+impl StdError for ScrapeError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(self.inner())
+    }
+}
+
+impl From<ParseError> for ScrapeError {
+    fn from(e: ParseError) -> Self {
+        Self::Parse(Error::other("parse error".to_owned(), Box::new(e)))
+    }
+}
+
+/// Sorts `error` by the first `reqwest` or parse error in its sources.  Errors
+/// with neither are [`Runtime`](ScrapeError::Runtime) errors.
+impl From<Error> for ScrapeError {
+    fn from(error: Error) -> Self {
+        match Kind::of(&error) {
+            Some(Kind::Timeout) => Self::Timeout(error),
+            Some(Kind::Network) => Self::Network(error),
+            Some(Kind::Parse) => Self::Parse(error),
+            None => Self::Runtime(error),
+        }
+    }
+}
+
+enum Kind {
+    Timeout,
+    Network,
+    Parse,
+}
+
+impl Kind {
+    fn of(error: &Error) -> Option<Self> {
+        match error {
+            Error::Wrapped { inner, .. } => Self::of(inner),
+            Error::Other { source, .. } => {
+                let mut source = source.as_deref().map(|x| x as &(dyn StdError + 'static));
+                while let Some(e) = source {
+                    if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+                        return Some(if e.is_timeout() {
+                            Self::Timeout
+                        } else {
+                            Self::Network
+                        });
+                    } else if e.is::<ParseError>() {
+                        return Some(Self::Parse);
+                    } else if let Some(e) = e.downcast_ref::<Error>() {
+                        // `Error` doesn't report its own sources
+                        return Self::of(e);
+                    }
+                    source = e.source();
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::interpreter::{
+        client::mock::{MockServer, Response},
+        Interpreter,
+    };
+
+    use super::ScrapeError;
+
+    #[tokio::test]
+    async fn error_kinds() {
+        let server = MockServer::start(|req| match &*req.path {
+            "/slow" => Response::html("<p>hi</p>").with_delay(Duration::from_secs(2)),
+            "/missing" => Response::status(404),
+            _ => Response::html("<p>hi</p>"),
+        })
+        .await;
+
+        let result = crate::run("a: p { x: $element | kitty(); };", server.url("/")).await;
+        assert!(matches!(result, Err(ScrapeError::Runtime(_))), "{result:?}");
+
+        let result = crate::run("a: p {", server.url("/")).await;
+        assert!(matches!(result, Err(ScrapeError::Parse(_))), "{result:?}");
+
+        let result = crate::run("@kitty: 1;\na: p { };", server.url("/")).await;
+        assert!(matches!(result, Err(ScrapeError::Parse(_))), "{result:?}");
+
+        let result = crate::run("a: p { };", server.url("/missing")).await;
+        assert!(matches!(result, Err(ScrapeError::Network(_))), "{result:?}");
+
+        // nothing listens on a port after its listener is dropped
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|x| x.local_addr())
+            .expect("failed to bind")
+            .port();
+        let url = format!("http://127.0.0.1:{port}/")
+            .parse()
+            .expect("valid URL");
+        let result = crate::run("a: p { };", url).await;
+        assert!(matches!(result, Err(ScrapeError::Network(_))), "{result:?}");
+
+        let interpreter = Interpreter::with_client(
+            reqwest::Client::builder()
+                .timeout(Duration::from_millis(100))
+                .build()
+                .expect("invalid client"),
+        );
+        let program = crate::frontend::Parser::new("a: p { };")
+            .parse()
+            .expect("parse error");
+        let result = interpreter
+            .interpret(&program.statements, server.url("/slow"))
+            .await
+            .map_err(ScrapeError::from);
+        assert!(matches!(result, Err(ScrapeError::Timeout(_))), "{result:?}");
+    }
+}
@@ -129,6 +129,24 @@ pub struct Statement<'a> {
     pub filters: Vec<Filter<'a>>,
 }
 
+/// A parsed `scrapelect` program: the directives in its head, followed by
+/// its statements.
+#[derive(Debug, Clone)]
+pub struct Program<'a> {
+    pub head: Vec<Directive<'a>>,
+    pub statements: Vec<Statement<'a>>,
+}
+
+/// A directive in the head of a program, like `@concurrency: 4;`, which
+/// configures how the program is run.
+#[derive(Debug, Clone)]
+pub struct Directive<'a> {
+    pub id: &'a str,
+    /// The value of the directive.  A bare word, like `true` in
+    /// `@dedup_requests: true;`, is parsed as a [`Leaf::String`].
+    pub value: Leaf<'a>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Element<'a> {
     pub url: Option<Inline<'a>>,
@@ -3,8 +3,9 @@ use std::borrow::Cow;
 
 use super::{
     ast::{
-        Arg, Element, Filter, FilterCall, FilterSelect, FilterType, Inline, Leaf, NamedSelector,
-        NonEmpty, Qualifier, RValue, Selector, SelectorCombinator, SelectorFragment, Statement,
+        Arg, Directive, Element, Filter, FilterCall, FilterSelect, FilterType, Inline, Leaf,
+        NamedSelector, NonEmpty, Program, Qualifier, RValue, Selector, SelectorCombinator,
+        SelectorFragment, Statement,
     },
     scanner::{Lexeme, Scanner, Span, Token},
 };
@@ -69,10 +70,40 @@ impl<'a> Parser<'a> {
         }
     }
 
-    pub fn parse(mut self) -> Result<Vec<Statement<'a>>> {
+    pub fn parse(mut self) -> Result<Program<'a>> {
+        let head = self.parse_head()?;
         let statements = self.parse_statement_list()?;
         self.try_eat(Token::Eof)?;
-        Ok(statements)
+        Ok(Program { head, statements })
+    }
+
+    fn parse_head(&mut self) -> Result<Vec<Directive<'a>>> {
+        let mut vec = Vec::new();
+        while let Token::At = self.scanner.peek_non_whitespace().1.token {
+            vec.push(self.parse_directive()?);
+        }
+        Ok(vec)
+    }
+
+    fn parse_directive(&mut self) -> Result<Directive<'a>> {
+        self.try_eat(Token::At)?;
+        let id = self.try_eat(Token::Id)?.value;
+        self.try_eat(Token::Colon)?;
+        let value = match self.scanner.peek_non_whitespace() {
+            (
+                _,
+                Lexeme {
+                    token: Token::Id,
+                    value,
+                },
+            ) => {
+                self.scanner.eat_token();
+                Leaf::String(Cow::Borrowed(value))
+            }
+            _ => self.parse_leaf()?,
+        };
+        self.try_eat(Token::Semi)?;
+        Ok(Directive { id, value })
     }
 
     pub fn parse_statement_list(&mut self) -> Result<Vec<Statement<'a>>> {
@@ -473,7 +504,7 @@ mod tests {
                 };
             };"#;
         let parser = Parser::new(string);
-        let stmts = parser.parse().expect("parsing failed");
+        let stmts = parser.parse().expect("parsing failed").statements;
 
         let stmt = &stmts[0];
 
@@ -548,6 +579,43 @@ mod tests {
         assert_eq!(element.selector.to_string(), "h2#x > .cat");
     }
 
+    #[test]
+    fn test_parse_head() {
+        let string = r#"
+            @concurrency: 4;
+            @ user_agent: "kitty";
+            @dedup_requests: true;
+
+            a: $b;
+            "#;
+        let program = Parser::new(string).parse().expect("parsing failed");
+
+        assert!(
+            matches!(
+                &program.head[..],
+                [
+                    Directive {
+                        id: "concurrency",
+                        value: Leaf::Int(4),
+                    },
+                    Directive {
+                        id: "user_agent",
+                        value: Leaf::String(Cow::Borrowed("kitty")),
+                    },
+                    Directive {
+                        id: "dedup_requests",
+                        value: Leaf::String(Cow::Borrowed("true")),
+                    },
+                ]
+            ),
+            "found {:?}",
+            program.head
+        );
+        assert_eq!(program.statements.len(), 1);
+
+        assert!(Parser::new("a: $b; @concurrency: 4;").parse().is_err());
+    }
+
     #[test]
     fn test_escape_strings() {
         assert_eq!(Parser::parse_string_literal(r#""""#), "");
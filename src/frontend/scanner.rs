@@ -53,6 +53,8 @@ pub enum Token {
     /// A single-line comment that begins with two forward slashes '//' and
     /// spans the rest of the line
     Comment,
+    /// an at sign `@` to indicate a directive in the head of a program
+    At,
     /// special token to indicate the end of the file
     Eof,
     /// special token to indicate unknown token
@@ -112,6 +114,7 @@ mod statics {
             BracketOpen <- r"\["
             BracketClose <- r"\]"
             Comment <- r"//[^\n]*"
+            At <- "@"
         };
     }
 }
@@ -283,6 +286,7 @@ mod tests {
             Pipe => "|"
             BracketOpen => "["
             BracketClose => "]"
+            At => "@"
         }
     }
 
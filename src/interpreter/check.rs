@@ -0,0 +1,166 @@
+//! Checking a program for mistakes that would make it fail, without running it.
+
+use crate::frontend::ast::{Element, Filter, FilterType, Inline, Leaf, Program, RValue, Statement};
+
+use super::{filter::builtin::FILTERS, Config};
+
+/// Returns every problem found in `program`, in the order they appear: invalid
+/// or repeated head directives, calls to filters that don't exist, literal
+/// `filter`, `then`, and `filters` arguments (like those of `try`, `each`, and
+/// `when_present`) that aren't names of filters, and literal `selector`
+/// arguments that aren't valid CSS selectors.  An empty list means the program looks valid.
+#[must_use]
+pub fn check(program: &Program<'_>) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    // read each directive on its own, so that one mistake doesn't hide the rest
+    for (i, directive) in program.head.iter().enumerate() {
+        if let Err(e) = Config::from_head(std::slice::from_ref(directive)) {
+            problems.push(e.to_string());
+        } else if program.head[..i].iter().any(|x| x.id == directive.id) {
+            problems.push(format!(
+                "directive `@{}` is set more than once",
+                directive.id
+            ));
+        }
+    }
+
+    let mut checker = Checker {
+        path: Vec::new(),
+        problems,
+    };
+    checker.statements(&program.statements);
+    checker.problems
+}
+
+struct Checker<'a> {
+    /// The names of the statements around the one being checked.
+    path: Vec<&'a str>,
+    problems: Vec<String>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, problem: String) {
+        self.problems
+            .push(format!("{problem} (in `{}`)", self.path.join(".")));
+    }
+
+    fn statements(&mut self, statements: &'a [Statement<'a>]) {
+        for statement in statements {
+            self.path.push(statement.id);
+            match &statement.value {
+                RValue::Leaf(_) => (),
+                RValue::Element(element) => self.element(element),
+            }
+            self.filters(&statement.filters);
+            self.path.pop();
+        }
+    }
+
+    fn element(&mut self, element: &'a Element<'a>) {
+        if let Some(url) = &element.url {
+            self.inline(url);
+        }
+        self.statements(&element.statements);
+    }
+
+    fn inline(&mut self, inline: &'a Inline<'a>) {
+        self.filters(&inline.filters);
+    }
+
+    fn filters(&mut self, filters: &'a [Filter<'a>]) {
+        for filter in filters {
+            match &filter.filter {
+                FilterType::Call(call) => {
+                    if !FILTERS.contains_key(call.id) {
+                        self.report(format!("unrecognized filter `{}`", call.id));
+                    }
+
+                    for arg in &call.args {
+                        // only a literal can be checked before the program runs
+                        match (arg.id, &arg.value.value) {
+                            ("selector", Leaf::String(selector)) => {
+                                if let Err(e) = scraper::Selector::parse(selector) {
+                                    self.report(format!("invalid selector `{selector}`: {e}"));
+                                }
+                            }
+                            ("filter" | "then" | "filters", Leaf::String(name))
+                                if !FILTERS.contains_key(&**name) =>
+                            {
+                                self.report(format!(
+                                    "unrecognized filter `{name}` in argument `{}` of `{}`",
+                                    arg.id, call.id
+                                ));
+                            }
+                            _ => (),
+                        }
+                        self.inline(&arg.value);
+                    }
+                }
+                FilterType::Select(select) => self.inline(&select.value),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frontend::Parser;
+
+    use super::check;
+
+    #[test]
+    fn reports_problems() {
+        let program = Parser::new(
+            r#"
+            @kitty: 1;
+            @concurrency: 0;
+            @memoize: true;
+            @memoize: false;
+            title: h1 {
+                text: $element | txt();
+                items: $element | texts(selector: "li[") | nth(i: 0);
+            };
+            links: $element | attrs_of(selector: "a", name: "href");
+            num: "12" | try(filter: "integer");
+            names: $links | each(filter: "strip_all");
+            sold: $element | when_present(selector: "p", then: "atrs");
+            ok: "12" | try(filter: "int");
+            "#,
+        )
+        .parse()
+        .expect("parse error");
+
+        let problems = check(&program);
+        assert_eq!(problems.len(), 8, "{problems:#?}");
+        assert!(problems[0].contains("unknown directive `@kitty`"));
+        assert!(problems[1].contains("expected a positive integer"));
+        assert!(problems[1].contains("`@concurrency`"));
+        assert_eq!(problems[2], "directive `@memoize` is set more than once");
+        assert_eq!(problems[3], "unrecognized filter `txt` (in `title.text`)");
+        assert!(problems[4].starts_with("invalid selector `li[`"));
+        assert!(problems[4].ends_with("(in `title.items`)"));
+        assert_eq!(
+            problems[5],
+            "unrecognized filter `integer` in argument `filter` of `try` (in `num`)"
+        );
+        assert!(problems[6].starts_with("unrecognized filter `strip_all`"));
+        assert!(problems[7].starts_with("unrecognized filter `atrs` in argument `then`"));
+    }
+
+    #[test]
+    fn valid_program() {
+        let program = Parser::new(
+            r#"
+            @dedup_requests: true;
+            title: h1 {
+                text: $element | text() | strip();
+            };
+            "#,
+        )
+        .parse()
+        .expect("parse error");
+
+        assert_eq!(check(&program), Vec::<String>::new());
+    }
+}
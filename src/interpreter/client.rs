@@ -0,0 +1,1182 @@
+//! The HTTP client that the interpreter uses to fetch web pages, which
+//! enforces the request limits set in a [`Config`] and respects each site's
+//! `robots.txt`.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use encoding_rs::Encoding;
+use reqwest::{
+    header::{CONTENT_TYPE, COOKIE, LOCATION, SET_COOKIE},
+    Url,
+};
+use scrapelect_filter_types::{bail, other};
+use tokio::{
+    sync::{OnceCell, Semaphore},
+    time::Instant,
+};
+use tracing::Instrument as _;
+use url::Origin;
+
+use super::{config::Config, MessageExt as _, Result};
+
+use cookies::CookieJar;
+use robots::Robots;
+
+mod charset;
+mod cookies;
+#[cfg(test)]
+pub(crate) mod mock;
+mod robots;
+
+/// The `robots.txt` rules for each host, which are fetched the first time a
+/// page is requested from that host.
+type RobotsCache = Mutex<HashMap<Origin, Arc<OnceCell<Robots>>>>;
+
+/// The cookie names and values set by the last response from each URL.
+type ResponseCookies = Mutex<HashMap<Url, Vec<(String, String)>>>;
+
+/// Totals of the requests that have been sent in a run, shown by `--stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of requests sent, including requests for `robots.txt`.
+    pub requests: u64,
+    /// The number of those requests that failed.
+    pub failed: u64,
+    /// The total size of the response bodies that were read, in bytes.
+    pub bytes: u64,
+    /// The number of pages that were reused instead of fetched again.  This
+    /// is always zero for the [`HttpClient::stats`], which doesn't cache pages.
+    pub cache_hits: u64,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} requests ({} failed), {} cache hits, {} bytes fetched",
+            self.requests, self.failed, self.cache_hits, self.bytes
+        )
+    }
+}
+
+/// A function that is called with the client's [`Stats`] each time a page
+/// request finishes (successfully or not), like for `--progress`.
+#[derive(Clone)]
+pub struct ProgressHook(Arc<dyn Fn(Stats) + Send + Sync>);
+
+impl ProgressHook {
+    /// Creates a hook that calls `f`.
+    pub fn new(f: impl Fn(Stats) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for ProgressHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressHook").finish_non_exhaustive()
+    }
+}
+
+/// The counters behind [`Stats`], shared between clones of an [`HttpClient`].
+#[derive(Debug, Default)]
+struct Counters {
+    requests: AtomicU64,
+    failed: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl Counters {
+    fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+/// A wrapper around a [`reqwest::Client`] that limits how many requests
+/// can be in flight at once, and how often requests are sent to the same host.
+///
+/// Cloning an `HttpClient` shares its limits with the clone.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    permits: Arc<Semaphore>,
+    delay: Duration,
+    /// The time that the next request to each host is allowed to start.
+    next_slot: Arc<Mutex<HashMap<Origin, Instant>>>,
+    /// `None` if `robots.txt` is ignored.
+    robots: Option<Arc<RobotsCache>>,
+    /// The encoding to decode every page with, instead of the one it declares.
+    encoding: Option<&'static Encoding>,
+    /// The cookies to send with requests, including those set by responses.
+    cookies: Arc<Mutex<CookieJar>>,
+    /// The proxy that `client` sends requests through, for error messages.
+    proxy: Option<Url>,
+    /// The maximum size of a response body, in bytes.
+    max_response_size: Option<u64>,
+    /// The status codes outside of `2xx` whose responses are read anyway.
+    allow_status: Arc<[u16]>,
+    counters: Arc<Counters>,
+    /// The `(name, value)` cookies set by the last response from each URL.
+    response_cookies: Arc<ResponseCookies>,
+    /// The `Content-Type` of the last response from each URL, if it had one.
+    content_types: Arc<Mutex<HashMap<Url, String>>>,
+    progress: Option<ProgressHook>,
+}
+
+impl HttpClient {
+    /// The maximum number of requests in flight at once, if it is not set
+    /// in the [`Config`].
+    pub const DEFAULT_CONCURRENCY: usize = 4;
+
+    /// Creates a new `HttpClient` that sends requests with `client` and is
+    /// limited by the options in `config`.
+    #[must_use]
+    pub fn new(client: reqwest::Client, config: &Config) -> Self {
+        Self {
+            client,
+            permits: Arc::new(Semaphore::new(
+                config
+                    .concurrency
+                    .map_or(Self::DEFAULT_CONCURRENCY, usize::from),
+            )),
+            delay: config.delay.unwrap_or_default(),
+            next_slot: Arc::default(),
+            robots: (!config.ignore_robots.unwrap_or_default()).then(Arc::default),
+            encoding: config.input_encoding,
+            cookies: Arc::default(),
+            proxy: config.proxy.clone(),
+            max_response_size: config.max_response_size,
+            allow_status: config.allow_status.as_deref().unwrap_or_default().into(),
+            counters: Arc::default(),
+            response_cookies: Arc::default(),
+            content_types: Arc::default(),
+            progress: None,
+        }
+    }
+
+    /// Calls `hook` with the [`stats`](Self::stats) after each page request
+    /// finishes.  Requests for `robots.txt` are counted in the stats, but don't
+    /// call `hook`.
+    #[must_use]
+    pub fn with_progress(mut self, hook: ProgressHook) -> Self {
+        self.progress = Some(hook);
+        self
+    }
+
+    /// The name that `scrapelect` looks for in the `User-agent` lines of
+    /// `robots.txt`.
+    pub const ROBOTS_AGENT: &'static str = env!("CARGO_PKG_NAME");
+
+    /// How many bytes of `robots.txt` are read and parsed, at most, as allowed
+    /// by RFC 9309.  The rest of the file is ignored.  It is also limited by
+    /// `--max-response-size`.
+    pub const ROBOTS_MAX_SIZE: u64 = 500 * 1024;
+
+    /// Creates the default [`reqwest::ClientBuilder`], which identifies
+    /// itself as `scrapelect` in the user agent.
+    pub fn default_builder() -> reqwest::ClientBuilder {
+        reqwest::Client::builder().user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            " v",
+            env!("CARGO_PKG_VERSION")
+        ))
+    }
+
+    /// Sends a GET request to `url` and returns the text of the response body,
+    /// [decoded](Self::decode) with the encoding that the response declares.
+    ///
+    /// If a request to the same host was started less than the configured
+    /// delay ago, this waits until the delay has passed.  Then, if the maximum
+    /// number of requests are already in flight, this waits until one of them
+    /// finishes before sending the request.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the request fails, the response has a status code
+    /// outside of `2xx` that isn't allowed, the body cannot be read or is larger
+    /// than the maximum response size, or the site's `robots.txt` disallows
+    /// fetching `url`.
+    pub async fn get(&self, url: &Url) -> Result<String> {
+        self.get_with_headers(url, &[]).await
+    }
+
+    /// Like [`get`](Self::get), but also sends the `(name, value)` request `headers`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` in the same cases as [`get`](Self::get), or if a header
+    /// name or value is invalid.
+    pub async fn get_with_headers(
+        &self,
+        url: &Url,
+        headers: &[(String, String)],
+    ) -> Result<String> {
+        async {
+            if !self.is_allowed(url).await? {
+                bail!(
+                    "`{url}` is disallowed by robots.txt (pass `--ignore-robots` to fetch it anyway)"
+                );
+            }
+
+            let result = self.send(url, headers).await;
+            if let Some(progress) = &self.progress {
+                (progress.0)(self.stats());
+            }
+            result
+        }
+        .instrument(tracing::info_span!("fetch", url = %url))
+        .await
+    }
+
+    /// Parses the URL of a proxy to send requests through, which must be an
+    /// `http` or `https` URL, or with the `socks` feature, a `socks5` or
+    /// `socks5h` URL.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if `url` is not a valid URL, or it has another scheme.
+    pub fn parse_proxy(url: &str) -> Result<Url> {
+        let url: Url = url
+            .parse()
+            .with_msg(|| format!("`{url}` is not a valid proxy URL"))?;
+
+        match url.scheme() {
+            "http" | "https" => Ok(url),
+            "socks5" | "socks5h" if cfg!(feature = "socks") => Ok(url),
+            "socks5" | "socks5h" => bail!(
+                "SOCKS proxies like `{url}` need `scrapelect` to be built with the `socks` feature"
+            ),
+            other if cfg!(feature = "socks") => bail!(
+                "unknown proxy scheme `{other}` (expected `http`, `https`, `socks5`, or `socks5h`)"
+            ),
+            other => bail!("unknown proxy scheme `{other}` (expected `http` or `https`)"),
+        }
+    }
+
+    /// Adds the options in `config` that are set on the [`reqwest::Client`]
+    /// itself, like the proxy and `--allow-insecure`, to `builder`.  It also
+    /// turns off following redirects in `reqwest`, because the `HttpClient`
+    /// follows them itself to store the cookies set along the way.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the proxy can't be used.
+    pub fn configure(
+        builder: reqwest::ClientBuilder,
+        config: &Config,
+    ) -> Result<reqwest::ClientBuilder> {
+        let builder = match &config.proxy {
+            Some(proxy) => builder.proxy(
+                reqwest::Proxy::all(proxy.clone())
+                    .with_msg(|| format!("proxy `{proxy}` can't be used"))?,
+            ),
+            None => builder,
+        };
+
+        Ok(builder
+            .redirect(reqwest::redirect::Policy::none())
+            .danger_accept_invalid_certs(config.allow_insecure.unwrap_or_default()))
+    }
+
+    /// Returns the `(name, value)` cookies that the last response from `url` set
+    /// in its `Set-Cookie` headers, or `None` if no response was received from
+    /// `url` yet.
+    #[must_use]
+    pub fn response_cookies(&self, url: &Url) -> Option<Vec<(String, String)>> {
+        self.response_cookies
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(url)
+            .cloned()
+    }
+
+    /// Returns the `Content-Type` header of the last response from `url`, or
+    /// `None` if no response was received from `url` yet or it had no
+    /// `Content-Type`.
+    #[must_use]
+    pub fn content_type(&self, url: &Url) -> Option<String> {
+        self.content_types
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(url)
+            .cloned()
+    }
+
+    /// Sends the cookie `name` with value `value` with every later request to
+    /// `url`'s host, until a response changes it.
+    pub fn set_cookie(&self, url: &Url, name: &str, value: &str) {
+        self.cookie_jar().set(url, name, value);
+    }
+
+    fn cookie_jar(&self) -> std::sync::MutexGuard<'_, CookieJar> {
+        self.cookies
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Returns the totals of the requests sent by this client and its clones so far.
+    #[must_use]
+    pub fn stats(&self) -> Stats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        Stats {
+            requests: load(&self.counters.requests),
+            failed: load(&self.counters.failed),
+            bytes: load(&self.counters.bytes),
+            cache_hits: 0,
+        }
+    }
+
+    /// Counts a request, and whether it failed.
+    fn count<T>(&self, result: Result<T>) -> Result<T> {
+        Counters::add(&self.counters.requests, 1);
+        if result.is_err() {
+            Counters::add(&self.counters.failed, 1);
+        }
+        result
+    }
+
+    /// Decodes the body of a page into text, with the configured input encoding,
+    /// or if it isn't set, the encoding declared in its byte order mark,
+    /// `Content-Type` header, or `<meta>` tags (in that order), or UTF-8 if none
+    /// is declared.
+    #[must_use]
+    pub fn decode(&self, bytes: &[u8], content_type: Option<&str>) -> String {
+        charset::decode(bytes, content_type, self.encoding)
+    }
+
+    /// Returns whether `url`'s `robots.txt` allows fetching `url`, fetching
+    /// and caching `robots.txt` if this is the first request to its host.
+    async fn is_allowed(&self, url: &Url) -> Result<bool> {
+        let Some(cache) = &self.robots else {
+            return Ok(true);
+        };
+
+        let cell = Arc::clone(
+            cache
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .entry(url.origin())
+                .or_default(),
+        );
+
+        let robots = cell
+            .get_or_try_init(|| async {
+                let robots_url = url
+                    .join("/robots.txt")
+                    .with_msg(|| format!("`{url}` has no robots.txt"))?;
+                self.fetch_robots(&robots_url)
+                    .await
+                    .with_msg(|| format!("reading `{robots_url}` failed"))
+            })
+            .await?;
+
+        let path = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_owned(),
+        };
+
+        Ok(robots.is_allowed(&path))
+    }
+
+    async fn fetch_robots(&self, url: &Url) -> Result<Robots> {
+        self.wait_for_slot(url).await;
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .msg("the request pool is closed")?;
+
+        let start = Instant::now();
+        let (response, _) = self.count(self.send_following(url, &[]).await)?;
+        let status = response.status();
+        tracing::info!(
+            url = %url,
+            status = status.as_u16(),
+            elapsed_ms = start.elapsed().as_millis(),
+            "fetched robots.txt"
+        );
+
+        // a missing robots.txt allows everything, but if the server can't
+        // serve it, nothing is allowed, as recommended by RFC 9309.
+        if status.is_success() {
+            let max = self
+                .max_response_size
+                .map_or(Self::ROBOTS_MAX_SIZE, |x| x.min(Self::ROBOTS_MAX_SIZE));
+            let body = Self::read_prefix(url, response, max).await?;
+            Counters::add(&self.counters.bytes, body.len() as u64);
+            Ok(Robots::parse(
+                &String::from_utf8_lossy(&body),
+                Self::ROBOTS_AGENT,
+            ))
+        } else if status.is_server_error() {
+            Ok(Robots::disallow_all())
+        } else {
+            Ok(Robots::ALLOW_ALL)
+        }
+    }
+
+    /// Sends a GET request to `url`, respecting the delay and concurrency
+    /// limits, and returns the text of the response body.
+    async fn send(&self, url: &Url, headers: &[(String, String)]) -> Result<String> {
+        self.wait_for_slot(url).await;
+
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .msg("the request pool is closed")?;
+
+        let start = Instant::now();
+        let (response, set) = match self.send_following(url, headers).await {
+            Ok(x) => x,
+            Err(e) => return self.count(Err(e)),
+        };
+        tracing::info!(
+            status = response.status().as_u16(),
+            elapsed_ms = start.elapsed().as_millis(),
+            "fetched"
+        );
+        self.response_cookies
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(url.clone(), set);
+
+        let status = response.status();
+        if !status.is_success() && !self.allow_status.contains(&status.as_u16()) {
+            let hint = format!(
+                "pass `--allow-status {}` to read the page anyway",
+                status.as_u16()
+            );
+            // the `reqwest` error, for `4xx` and `5xx`, lets callers classify this
+            // as a network error
+            return self.count(Err(match response.error_for_status_ref() {
+                Err(e) => other!(@e, "request to `{url}` failed ({hint})"),
+                Ok(_) => other!("request to `{url}` failed with status {status} ({hint})"),
+            }));
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .map(str::to_owned);
+        {
+            let mut content_types = self
+                .content_types
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            match &content_type {
+                Some(content_type) => content_types.insert(url.clone(), content_type.clone()),
+                None => content_types.remove(url),
+            };
+        }
+        let bytes = self.count(self.read_body(url, response).await)?;
+        Counters::add(&self.counters.bytes, bytes.len() as u64);
+
+        Ok(self.decode(&bytes, content_type.as_deref()))
+    }
+
+    /// The most redirects that are followed for one request.
+    pub const MAX_REDIRECTS: usize = 10;
+
+    /// Sends a GET request to `url`, following up to [`MAX_REDIRECTS`](Self::MAX_REDIRECTS)
+    /// redirects, and returns the final response and the `(name, value)` cookies
+    /// set by every response along the way.
+    ///
+    /// Each response's cookies are stored before the next request, so that it
+    /// sends the cookies for its own URL.  The extra `headers` are only sent to
+    /// `url`'s origin, like `reqwest` does with sensitive headers.
+    async fn send_following(
+        &self,
+        url: &Url,
+        headers: &[(String, String)],
+    ) -> Result<(reqwest::Response, Vec<(String, String)>)> {
+        let mut current = url.clone();
+        let mut set = Vec::new();
+
+        for _ in 0..=Self::MAX_REDIRECTS {
+            let mut request = self.client.get(current.clone());
+            if let Some(cookies) = self.cookie_jar().header(&current) {
+                request = request.header(COOKIE, cookies);
+            }
+            if current.origin() == url.origin() {
+                for (name, value) in headers {
+                    request = request.header(name, value);
+                }
+            }
+
+            let response = request.send().await.map_err(|e| match &self.proxy {
+                Some(proxy) if e.is_connect() => {
+                    other!(@e, "connecting to `{current}` through the proxy `{proxy}` failed")
+                }
+                _ => other!(@e, "request to `{current}` failed"),
+            })?;
+
+            {
+                let mut jar = self.cookie_jar();
+                for header in response.headers().get_all(SET_COOKIE) {
+                    if let Ok(header) = header.to_str() {
+                        jar.store(&current, header);
+                        set.extend(
+                            cookies::name_value(header)
+                                .map(|(name, value)| (name.to_owned(), value.to_owned())),
+                        );
+                    }
+                }
+            }
+
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .filter(|_| response.status().is_redirection())
+                .and_then(|x| x.to_str().ok());
+            let Some(location) = location else {
+                return Ok((response, set));
+            };
+
+            let next = current
+                .join(location)
+                .with_msg(|| format!("`{current}` redirected to an invalid URL `{location}`"))?;
+            tracing::info!(
+                url = %current,
+                status = response.status().as_u16(),
+                location = %next,
+                "redirected"
+            );
+            // the final request is counted by the caller
+            Counters::add(&self.counters.requests, 1);
+            current = next;
+        }
+
+        bail!(
+            "request to `{url}` failed: it redirected more than {} times",
+            Self::MAX_REDIRECTS
+        )
+    }
+
+    /// Reads the body of `response`, raising an error as soon as it is known
+    /// to be larger than the maximum response size, if there is one.
+    async fn read_body(&self, url: &Url, mut response: reqwest::Response) -> Result<Vec<u8>> {
+        let Some(max) = self.max_response_size else {
+            return response
+                .bytes()
+                .await
+                .map(Vec::from)
+                .with_msg(|| format!("retrieving body from `{url}` failed"));
+        };
+
+        let too_large = || {
+            other!(
+                "the response from `{url}` is larger than the maximum of {max} bytes \
+                (set with `--max-response-size`)"
+            )
+        };
+
+        // the length may be missing or wrong, so also count the bytes as they arrive
+        if response.content_length().is_some_and(|len| len > max) {
+            return Err(too_large());
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .with_msg(|| format!("retrieving body from `{url}` failed"))?
+        {
+            if (body.len() + chunk.len()) as u64 > max {
+                return Err(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(body)
+    }
+
+    /// Reads at most the first `max` bytes of `response`'s body, and stops
+    /// downloading the rest.
+    async fn read_prefix(url: &Url, mut response: reqwest::Response, max: u64) -> Result<Vec<u8>> {
+        let max = usize::try_from(max).unwrap_or(usize::MAX);
+
+        let mut body = Vec::new();
+        while body.len() < max {
+            let Some(chunk) = response
+                .chunk()
+                .await
+                .with_msg(|| format!("retrieving body from `{url}` failed"))?
+            else {
+                break;
+            };
+            body.extend_from_slice(&chunk);
+        }
+        body.truncate(max);
+
+        Ok(body)
+    }
+
+    /// Reserves the next time a request to `url`'s host can start, and waits
+    /// until then.
+    ///
+    /// The delay is only per host: while waiting, this does not hold a permit,
+    /// so requests to other hosts can still be sent.
+    async fn wait_for_slot(&self, url: &Url) {
+        if self.delay.is_zero() {
+            return;
+        }
+
+        let slot = {
+            let mut next_slot = self
+                .next_slot
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            let now = Instant::now();
+            let slot = next_slot
+                .get(&url.origin())
+                .map_or(now, |&next| next.max(now));
+            next_slot.insert(url.origin(), slot + self.delay);
+            slot
+        };
+
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{num::NonZeroUsize, time::Duration};
+
+    use crate::logging::{tests::Captured, Logger};
+
+    use super::{
+        mock::{MockServer, Response},
+        Config, HttpClient, ProgressHook,
+    };
+
+    fn robots_server() -> impl std::future::Future<Output = MockServer> {
+        MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::html("User-agent: *\nDisallow: /private/\n")
+                .with_header("content-type", "text/plain"),
+            _ => Response::html("<p>hi</p>"),
+        })
+    }
+
+    #[tokio::test]
+    async fn concurrency_is_limited() -> anyhow::Result<()> {
+        let server = MockServer::start(|_| {
+            Response::html("<p>hi</p>").with_delay(Duration::from_millis(50))
+        })
+        .await;
+
+        let client = HttpClient::new(
+            reqwest::Client::new(),
+            &Config {
+                concurrency: NonZeroUsize::new(2),
+                ignore_robots: Some(true),
+                ..Config::default()
+            },
+        );
+
+        let urls: Vec<_> = (0..6).map(|i| server.url(&format!("/{i}"))).collect();
+        futures::future::try_join_all(urls.iter().map(|url| client.get(url))).await?;
+
+        assert_eq!(server.requests().len(), 6);
+        assert_eq!(server.max_in_flight(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delay_is_per_host() -> anyhow::Result<()> {
+        const DELAY: Duration = Duration::from_millis(100);
+        const TOLERANCE: Duration = Duration::from_millis(10);
+
+        let server = MockServer::start(|_| Response::html("<p>hi</p>")).await;
+        let other = MockServer::start(|_| Response::html("<p>hi</p>")).await;
+
+        let client = HttpClient::new(
+            reqwest::Client::new(),
+            &Config {
+                delay: Some(DELAY),
+                ignore_robots: Some(true),
+                ..Config::default()
+            },
+        );
+
+        let (first, second, third) = (server.url("/1"), server.url("/2"), other.url("/"));
+        futures::future::try_join3(client.get(&first), client.get(&second), client.get(&third))
+            .await?;
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        // the server receives requests a little after they are sent, so allow
+        // for some jitter in when the first connection is accepted.
+        assert!(requests[1].received - requests[0].received >= DELAY - TOLERANCE);
+
+        // the other host doesn't have to wait for the first
+        assert!(other.requests()[0].received - requests[0].received < DELAY);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn robots_allowed() -> anyhow::Result<()> {
+        let server = robots_server().await;
+        let client = HttpClient::new(reqwest::Client::new(), &Config::default());
+
+        client.get(&server.url("/index.html")).await?;
+        client.get(&server.url("/public/page.html")).await?;
+
+        let paths: Vec<_> = server.requests().into_iter().map(|x| x.path).collect();
+        // robots.txt is only fetched once
+        assert_eq!(paths, ["/robots.txt", "/index.html", "/public/page.html"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn robots_disallowed() -> anyhow::Result<()> {
+        let server = robots_server().await;
+        let url = server.url("/private/page.html");
+
+        let client = HttpClient::new(reqwest::Client::new(), &Config::default());
+        assert!(client.get(&url).await.is_err());
+
+        let paths: Vec<_> = server.requests().into_iter().map(|x| x.path).collect();
+        assert_eq!(paths, ["/robots.txt"]);
+
+        let client = HttpClient::new(
+            reqwest::Client::new(),
+            &Config {
+                ignore_robots: Some(true),
+                ..Config::default()
+            },
+        );
+        client.get(&url).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn robots_prefix() -> anyhow::Result<()> {
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::html(format!(
+                "User-agent: *\nDisallow: /private/\n{}Disallow: /late/\n",
+                "# padding\n".repeat(200)
+            ))
+            .with_header("content-type", "text/plain"),
+            _ => Response::html("<p>hi</p>"),
+        })
+        .await;
+
+        let client = HttpClient::new(
+            reqwest::Client::new(),
+            &Config {
+                max_response_size: Some(1_000),
+                ..Config::default()
+            },
+        );
+        assert!(client.get(&server.url("/private/page.html")).await.is_err());
+        // the rule after the first 1000 bytes is ignored, and the file being
+        // too large is not an error
+        client.get(&server.url("/late/page.html")).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn decodes_declared_charset() -> anyhow::Result<()> {
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            "/header.html" => Response {
+                headers: vec![(
+                    "content-type".into(),
+                    "text/html; charset=windows-1252".into(),
+                )],
+                ..Response::html(b"<p>caf\xe9</p>".to_vec())
+            },
+            _ => Response {
+                headers: vec![("content-type".into(), "text/html".into())],
+                ..Response::html(b"<p>caf\xe9</p>".to_vec())
+            },
+        })
+        .await;
+
+        let client = HttpClient::new(reqwest::Client::new(), &Config::default());
+        assert_eq!(
+            client.get(&server.url("/header.html")).await?,
+            "<p>café</p>"
+        );
+        assert_eq!(
+            client.get(&server.url("/none.html")).await?,
+            "<p>caf\u{FFFD}</p>"
+        );
+
+        let client = HttpClient::new(
+            reqwest::Client::new(),
+            &Config {
+                input_encoding: Some(encoding_rs::WINDOWS_1252),
+                ..Config::default()
+            },
+        );
+        assert_eq!(client.get(&server.url("/none.html")).await?, "<p>café</p>");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn requests_go_through_proxy() -> anyhow::Result<()> {
+        // an HTTP proxy receives the full URL in the request line
+        let proxy = MockServer::start(|req| match &*req.path {
+            "http://example.invalid/robots.txt" => Response::status(404),
+            _ => Response::html("<h1>proxied</h1>"),
+        })
+        .await;
+
+        let config = Config {
+            proxy: Some(HttpClient::parse_proxy(proxy.url("/").as_str())?),
+            ..Config::default()
+        };
+        let client = HttpClient::new(
+            HttpClient::configure(reqwest::Client::builder(), &config)?.build()?,
+            &config,
+        );
+
+        let text = client.get(&"http://example.invalid/page".parse()?).await?;
+        assert_eq!(text, "<h1>proxied</h1>");
+
+        let paths: Vec<_> = proxy.requests().into_iter().map(|x| x.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "http://example.invalid/robots.txt",
+                "http://example.invalid/page"
+            ]
+        );
+
+        assert!(HttpClient::parse_proxy("ftp://localhost").is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn proxy_connection_error() -> anyhow::Result<()> {
+        // nothing listens on the discard port
+        let config = Config {
+            proxy: Some(HttpClient::parse_proxy("http://127.0.0.1:9")?),
+            ignore_robots: Some(true),
+            ..Config::default()
+        };
+        let client = HttpClient::new(
+            HttpClient::configure(reqwest::Client::builder(), &config)?.build()?,
+            &config,
+        );
+
+        let err = client
+            .get(&"http://example.invalid/".parse()?)
+            .await
+            .expect_err("the proxy is unreachable");
+        assert!(
+            err.to_string()
+                .contains("through the proxy `http://127.0.0.1:9/`"),
+            "{err}"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn redirects_store_cookies() -> anyhow::Result<()> {
+        let other = MockServer::start(|req| match &*req.path {
+            "/landing" => Response::status(302)
+                .with_header("set-cookie", "theme=dark")
+                .with_header("location", "/home"),
+            _ => Response::html("<h1>home</h1>"),
+        })
+        .await;
+        // a different host for the cookie jar, on the same server
+        let mut landing = other.url("/landing");
+        landing
+            .set_host(Some("localhost"))
+            .expect("localhost is a valid host");
+
+        let server = MockServer::start(move |req| match &*req.path {
+            "/login" => Response::status(302)
+                .with_header("set-cookie", "session=abc; Path=/")
+                .with_header("location", "/home"),
+            "/away" => Response::status(302).with_header("location", landing.as_str()),
+            "/loop" => Response::status(302).with_header("location", "/loop"),
+            _ => Response::html("<p>hi</p>"),
+        })
+        .await;
+
+        let config = Config {
+            ignore_robots: Some(true),
+            ..Config::default()
+        };
+        let client = HttpClient::new(
+            HttpClient::configure(reqwest::Client::builder(), &config)?.build()?,
+            &config,
+        );
+
+        let login = server.url("/login");
+        assert_eq!(client.get(&login).await?, "<p>hi</p>");
+        assert_eq!(
+            client.response_cookies(&login),
+            Some(vec![("session".to_owned(), "abc".to_owned())])
+        );
+        client.get(&server.url("/later")).await?;
+        assert_eq!(client.get(&server.url("/away")).await?, "<h1>home</h1>");
+
+        let err = client
+            .get(&server.url("/loop"))
+            .await
+            .expect_err("the redirects never end");
+        assert!(
+            err.to_string().contains("redirected more than 10 times"),
+            "{err}"
+        );
+
+        let cookies = |server: &MockServer| {
+            server
+                .requests()
+                .into_iter()
+                .filter(|x| x.path != "/loop")
+                .map(|x| (x.path.clone(), x.header("cookie").map(str::to_owned)))
+                .collect::<Vec<_>>()
+        };
+        let session = || Some("session=abc".to_owned());
+        assert_eq!(
+            cookies(&server),
+            [
+                ("/login".to_owned(), None),
+                ("/home".to_owned(), session()),
+                ("/later".to_owned(), session()),
+                ("/away".to_owned(), session()),
+            ]
+        );
+        // the other host gets its own cookies, and not the first one's
+        assert_eq!(
+            cookies(&other),
+            [
+                ("/landing".to_owned(), None),
+                ("/home".to_owned(), Some("theme=dark".to_owned())),
+            ]
+        );
+        assert_eq!(
+            server
+                .requests()
+                .iter()
+                .filter(|x| x.path == "/loop")
+                .count(),
+            HttpClient::MAX_REDIRECTS + 1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "socks")]
+    fn socks_proxy() -> anyhow::Result<()> {
+        for url in ["socks5://localhost:1080", "socks5h://localhost:1080"] {
+            let config = Config {
+                proxy: Some(HttpClient::parse_proxy(url)?),
+                ..Config::default()
+            };
+            HttpClient::configure(HttpClient::default_builder(), &config)?.build()?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(feature = "socks"))]
+    fn socks_proxy() {
+        for url in ["socks5://localhost:1080", "socks5h://localhost:1080"] {
+            let err = HttpClient::parse_proxy(url).expect_err("SOCKS needs the `socks` feature");
+            assert!(
+                err.to_string().contains("built with the `socks` feature"),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn insecure_is_opt_in() {
+        let builder = |allow_insecure| {
+            let config = Config {
+                allow_insecure,
+                ..Config::default()
+            };
+            format!(
+                "{:?}",
+                HttpClient::configure(HttpClient::default_builder(), &config)
+                    .expect("no proxy is set")
+            )
+        };
+
+        assert!(!builder(None).contains("danger_accept_invalid_certs"));
+        assert!(!builder(Some(false)).contains("danger_accept_invalid_certs"));
+        assert!(builder(Some(true)).contains("danger_accept_invalid_certs"));
+    }
+
+    #[tokio::test]
+    async fn requests_are_logged() -> anyhow::Result<()> {
+        let server = MockServer::start(|_| Response::html("<p>hi</p>")).await;
+        let client = HttpClient::new(
+            reqwest::Client::new(),
+            &Config {
+                ignore_robots: Some(true),
+                ..Config::default()
+            },
+        );
+
+        let captured = Captured::default();
+        let _guard =
+            tracing::subscriber::set_default(Logger::new(tracing::Level::INFO, captured.clone()));
+
+        let url = server.url("/page");
+        client.get(&url).await?;
+
+        let log = captured.text();
+        assert!(
+            log.contains(&format!(
+                "INFO fetch{{url={url}}}: fetched status=200 elapsed_ms="
+            )),
+            "{log}"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn oversized_responses_error() -> anyhow::Result<()> {
+        let body = "a".repeat(10_000);
+        let server = MockServer::start(move |req| match &*req.path {
+            "/chunked" => Response::html(body.clone()).with_header("transfer-encoding", "chunked"),
+            _ => Response::html(body.clone()),
+        })
+        .await;
+
+        let client = HttpClient::new(
+            reqwest::Client::new(),
+            &Config {
+                ignore_robots: Some(true),
+                max_response_size: Some(5_000),
+                ..Config::default()
+            },
+        );
+
+        for path in ["/sized", "/chunked"] {
+            let err = client
+                .get(&server.url(path))
+                .await
+                .expect_err("the response is too large");
+            assert!(
+                err.to_string()
+                    .contains("larger than the maximum of 5000 bytes"),
+                "{err}"
+            );
+        }
+
+        let client = HttpClient::new(
+            reqwest::Client::new(),
+            &Config {
+                ignore_robots: Some(true),
+                max_response_size: Some(10_000),
+                ..Config::default()
+            },
+        );
+        assert_eq!(client.get(&server.url("/chunked")).await?.len(), 10_000);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn error_statuses() -> anyhow::Result<()> {
+        let server = MockServer::start(|req| match &*req.path {
+            "/missing" => Response {
+                status: 404,
+                ..Response::html("<p>not found</p>")
+            },
+            "/broken" => Response::status(500),
+            _ => Response::html("<p>hi</p>"),
+        })
+        .await;
+
+        let client = HttpClient::new(
+            reqwest::Client::new(),
+            &Config {
+                ignore_robots: Some(true),
+                ..Config::default()
+            },
+        );
+
+        let err = client
+            .get(&server.url("/missing"))
+            .await
+            .expect_err("404 is not allowed");
+        let message = err.to_string();
+        assert!(message.contains("404 Not Found"), "{message}");
+        assert!(
+            message.contains(server.url("/missing").as_str()),
+            "{message}"
+        );
+        assert_eq!(client.stats().failed, 1);
+
+        let client = HttpClient::new(
+            reqwest::Client::new(),
+            &Config {
+                ignore_robots: Some(true),
+                allow_status: Some(vec![404]),
+                ..Config::default()
+            },
+        );
+        assert_eq!(
+            client.get(&server.url("/missing")).await?,
+            "<p>not found</p>"
+        );
+        assert!(client.get(&server.url("/broken")).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn progress_is_reported_per_page() -> anyhow::Result<()> {
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            "/missing" => Response::status(404),
+            _ => Response::html("<p>hi</p>"),
+        })
+        .await;
+
+        let reported = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let client = HttpClient::new(reqwest::Client::new(), &Config::default()).with_progress(
+            ProgressHook::new({
+                let reported = std::sync::Arc::clone(&reported);
+                move |stats| reported.lock().expect("lock poisoned").push(stats)
+            }),
+        );
+
+        client.get(&server.url("/1")).await?;
+        client.get(&server.url("/2")).await?;
+        assert!(client.get(&server.url("/missing")).await.is_err());
+
+        let reported = reported.lock().expect("lock poisoned");
+        // the first page also fetched `robots.txt`
+        assert_eq!(
+            reported
+                .iter()
+                .map(|x| (x.requests, x.failed))
+                .collect::<Vec<_>>(),
+            [(2, 0), (3, 0), (4, 1)]
+        );
+
+        Ok(())
+    }
+}
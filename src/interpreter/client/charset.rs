@@ -0,0 +1,97 @@
+//! Decoding fetched pages into text, using the character encoding that the
+//! server or the page declares.
+
+use encoding_rs::{Encoding, UTF_8};
+
+/// How many bytes at the start of a page to look through for a `<meta>`
+/// charset declaration, as in the HTML standard's prescan.
+const PRESCAN_LEN: usize = 1024;
+
+/// Decodes `bytes` into text.
+///
+/// The encoding is chosen from, in order: `forced`, a byte order mark, the
+/// `charset` of the `Content-Type` header `content_type`, a `<meta>` tag in
+/// the first 1024 bytes of the page, and UTF-8 if none of them are present.
+/// Invalid bytes are replaced with U+FFFD.
+pub fn decode(
+    bytes: &[u8],
+    content_type: Option<&str>,
+    forced: Option<&'static Encoding>,
+) -> String {
+    let encoding = forced
+        .or_else(|| Encoding::for_bom(bytes).map(|(encoding, _)| encoding))
+        .or_else(|| content_type.and_then(charset_param))
+        .or_else(|| meta_charset(&bytes[..bytes.len().min(PRESCAN_LEN)]))
+        .unwrap_or(UTF_8);
+
+    // `decode` removes a BOM, if there is one
+    encoding.decode(bytes).0.into_owned()
+}
+
+/// Returns the encoding named by the `charset` parameter of a `Content-Type`
+/// header, like `text/html; charset=windows-1252`.
+fn charset_param(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| Encoding::for_label(value.trim().trim_matches('"').as_bytes()))?
+    })
+}
+
+/// Returns the encoding named by a `<meta charset="...">` or
+/// `<meta http-equiv="Content-Type" content="...; charset=...">` tag in `bytes`.
+fn meta_charset(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(bytes).to_ascii_lowercase();
+
+    head.match_indices("<meta")
+        .find_map(|(start, _)| {
+            let tag = &head[start..];
+            let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+            let value = &tag[tag.find("charset")? + "charset".len()..];
+            let value = value.trim_start().strip_prefix('=')?.trim_start();
+            let value = value.trim_start_matches(['"', '\'']);
+            let end = value
+                .find(|c: char| matches!(c, '"' | '\'' | ';' | '/') || c.is_whitespace())
+                .unwrap_or(value.len());
+            Encoding::for_label(&value.as_bytes()[..end])
+        })
+        // a page can't declare itself as UTF-16 in ASCII, so this means UTF-8
+        .map(|encoding| encoding.output_encoding())
+}
+
+#[cfg(test)]
+mod tests {
+    use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+
+    use super::decode;
+
+    const FIXTURE: &[u8] = include_bytes!("../../../examples/inputs/windows-1252.html");
+
+    #[test]
+    fn decodes_windows_1252_fixture() {
+        // the fixture declares its encoding in a `<meta>` tag
+        let text = decode(FIXTURE, None, None);
+        assert!(text.contains("Café “crème” – 5€"), "{text}");
+    }
+
+    #[test]
+    fn content_type_and_override() {
+        let bytes = b"caf\xe9";
+
+        assert_eq!(
+            decode(bytes, Some("text/html; charset=\"Windows-1252\""), None),
+            "café"
+        );
+        assert_eq!(decode(bytes, Some("text/html"), None), "caf\u{FFFD}");
+        assert_eq!(
+            decode(bytes, Some("text/html; charset=utf-8"), Some(WINDOWS_1252)),
+            "café"
+        );
+        assert_eq!(decode(b"\x82\xb1\x82\xf1", None, Some(SHIFT_JIS)), "こん");
+        assert_eq!(
+            decode(b"\xef\xbb\xbfhi", Some("text/html; charset=latin1"), None),
+            "hi"
+        );
+    }
+}
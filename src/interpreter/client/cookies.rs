@@ -0,0 +1,206 @@
+//! A minimal cookie jar, which keeps the cookies set by responses for the rest
+//! of a run, following the rules of RFC 6265 for which requests they are sent
+//! with.
+//!
+//! Cookies are never written to disk, and `Expires` dates are ignored, so every
+//! cookie lasts until the end of the run unless it is removed with `Max-Age`.
+
+use reqwest::Url;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cookie {
+    name: String,
+    value: String,
+    /// The lowercase host, or the domain and its subdomains if not `host_only`.
+    domain: String,
+    host_only: bool,
+    path: String,
+    /// Whether to only send this cookie over HTTPS.
+    secure: bool,
+}
+
+impl Cookie {
+    fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+
+        let domain_matches = if self.host_only {
+            host.eq_ignore_ascii_case(&self.domain)
+        } else {
+            domain_matches(host, &self.domain)
+        };
+
+        domain_matches
+            && path_matches(url.path(), &self.path)
+            && (!self.secure || url.scheme() == "https")
+    }
+
+    /// Whether setting `other` replaces this cookie.
+    fn same_slot(&self, other: &Self) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+}
+
+/// The cookies received so far in a run, and the cookies that were set before
+/// it started.
+#[derive(Debug, Default)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    /// Sets the cookie `name` to `value` for requests to `url`'s host.
+    pub fn set(&mut self, url: &Url, name: &str, value: &str) {
+        if let Some(host) = url.host_str() {
+            self.insert(Cookie {
+                name: name.to_owned(),
+                value: value.to_owned(),
+                domain: host.to_ascii_lowercase(),
+                host_only: true,
+                path: "/".to_owned(),
+                secure: false,
+            });
+        }
+    }
+
+    /// Stores the cookie in the `Set-Cookie` header `header` of a response from
+    /// `url`.  Invalid cookies, and cookies for a domain that `url` is not on,
+    /// are ignored.
+    pub fn store(&mut self, url: &Url, header: &str) {
+        let Some(host) = url.host_str() else {
+            return;
+        };
+
+        let mut parts = header.split(';');
+        let Some((name, value)) = parts.next().and_then(name_value) else {
+            return;
+        };
+
+        let mut cookie = Cookie {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            domain: host.to_ascii_lowercase(),
+            host_only: true,
+            path: default_path(url.path()).to_owned(),
+            secure: false,
+        };
+        let mut expired = false;
+
+        for attribute in parts {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+            match &*key.trim().to_ascii_lowercase() {
+                "domain" if !value.is_empty() => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    if !domain_matches(host, &domain) {
+                        return;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => value.clone_into(&mut cookie.path),
+                "secure" => cookie.secure = true,
+                "max-age" => expired = value.parse::<i64>().is_ok_and(|x| x <= 0),
+                _ => (),
+            }
+        }
+
+        if expired {
+            self.cookies.retain(|x| !x.same_slot(&cookie));
+        } else {
+            self.insert(cookie);
+        }
+    }
+
+    /// Returns the value of the `Cookie` header to send with a request to `url`,
+    /// or `None` if no cookies match it.
+    pub fn header(&self, url: &Url) -> Option<String> {
+        let mut matching: Vec<_> = self.cookies.iter().filter(|x| x.matches(url)).collect();
+        if matching.is_empty() {
+            return None;
+        }
+
+        // cookies with longer paths are sent first
+        matching.sort_by_key(|x| std::cmp::Reverse(x.path.len()));
+        Some(
+            matching
+                .into_iter()
+                .map(|x| format!("{}={}", x.name, x.value))
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+    }
+
+    /// Adds `cookie`, replacing the cookie with the same name, domain, and path.
+    fn insert(&mut self, cookie: Cookie) {
+        match self.cookies.iter_mut().find(|x| x.same_slot(&cookie)) {
+            Some(existing) => *existing = cookie,
+            None => self.cookies.push(cookie),
+        }
+    }
+}
+
+/// Returns the trimmed name and value of the cookie in a `Set-Cookie` header,
+/// ignoring its attributes, or `None` if it is invalid.
+pub fn name_value(header: &str) -> Option<(&str, &str)> {
+    let pair = header.split(';').next()?;
+    let (name, value) = pair.split_once('=')?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| (name, value.trim()))
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+fn domain_matches(host: &str, domain: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    host == domain || host.strip_suffix(domain).is_some_and(|x| x.ends_with('.'))
+}
+
+/// Whether a cookie with the path `cookie_path` is sent with requests to `path`.
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    path.strip_prefix(cookie_path)
+        .is_some_and(|rest| cookie_path.ends_with('/') || rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The path of a cookie without a `Path` attribute: the "directory" of the
+/// request path.
+fn default_path(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CookieJar;
+
+    #[test]
+    fn cookie_scope() {
+        let url = |x: &str| x.parse().expect("invalid URL");
+        let mut jar = CookieJar::default();
+
+        jar.store(&url("https://a.com/account/login"), "session=1; HttpOnly");
+        jar.store(&url("https://a.com/"), "theme=dark; Domain=.a.com; Path=/");
+        jar.store(&url("https://a.com/"), "token=2; Secure");
+        jar.store(&url("https://a.com/"), "other=3; Domain=b.com");
+
+        assert_eq!(
+            jar.header(&url("https://a.com/account/settings"))
+                .as_deref(),
+            Some("session=1; theme=dark; token=2")
+        );
+        assert_eq!(
+            jar.header(&url("http://www.a.com/")).as_deref(),
+            Some("theme=dark")
+        );
+        assert_eq!(jar.header(&url("https://b.com/")), None);
+
+        jar.store(&url("https://a.com/"), "theme=light; Domain=a.com");
+        jar.store(&url("https://a.com/"), "token=; Max-Age=0");
+        assert_eq!(
+            jar.header(&url("https://a.com/")).as_deref(),
+            Some("theme=light")
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! A minimal HTTP server on localhost, for testing requests without
+//! relying on the internet.
+
+// not every test uses every helper
+#![allow(dead_code)]
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use reqwest::{StatusCode, Url};
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::{TcpListener, TcpStream},
+};
+
+/// A request received by the [`MockServer`].
+#[derive(Debug, Clone)]
+pub struct Request {
+    /// The path and query of the requested URL.
+    pub path: String,
+    /// The request headers, with lowercase names.
+    pub headers: Vec<(String, String)>,
+    /// When the server received the request.
+    pub received: Instant,
+}
+
+impl Request {
+    /// Returns the value of the first header named `name`, if there is one.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &**v)
+    }
+}
+
+/// The response that the [`MockServer`] sends for a request.
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// How long to wait before sending the response.
+    pub delay: Duration,
+}
+
+impl Response {
+    /// A `200 OK` response with an HTML body.
+    pub fn html(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("content-type".into(), "text/html; charset=utf-8".into())],
+            body: body.into(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// An empty response with the given status code.
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: vec![],
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Returns the value of the first header named `name`, if there is one.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &**v)
+    }
+
+    #[must_use]
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    #[must_use]
+    pub const fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+type Handler = dyn Fn(&Request) -> Response + Send + Sync;
+
+struct State {
+    handler: Box<Handler>,
+    requests: Mutex<Vec<Request>>,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+/// An HTTP server that responds to every request with the result of a handler
+/// function and records the requests it receives.
+pub struct MockServer {
+    base: Url,
+    state: Arc<State>,
+}
+
+impl MockServer {
+    /// Starts a server on a free localhost port, which runs until the
+    /// test's runtime shuts down.
+    pub async fn start(handler: impl Fn(&Request) -> Response + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("binding the mock server failed");
+        let base = format!(
+            "http://{}/",
+            listener.local_addr().expect("mock server has no address")
+        )
+        .parse()
+        .expect("mock server URL is invalid");
+
+        let state = Arc::new(State {
+            handler: Box::new(handler),
+            requests: Mutex::new(Vec::new()),
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: AtomicUsize::new(0),
+        });
+
+        let server_state = Arc::clone(&state);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, Arc::clone(&server_state)));
+            }
+        });
+
+        Self { base, state }
+    }
+
+    /// Returns the URL of `path` on this server.
+    pub fn url(&self, path: &str) -> Url {
+        self.base.join(path).expect("invalid mock server path")
+    }
+
+    /// Returns the requests received so far, in the order they were received.
+    pub fn requests(&self) -> Vec<Request> {
+        self.state
+            .requests
+            .lock()
+            .expect("mock server lock poisoned")
+            .clone()
+    }
+
+    /// Returns the largest number of requests that were being handled at the same time.
+    pub fn max_in_flight(&self) -> usize {
+        self.state.max_in_flight.load(Ordering::SeqCst)
+    }
+}
+
+async fn serve(mut stream: TcpStream, state: Arc<State>) {
+    let mut head = Vec::new();
+    let mut buf = [0; 1024];
+    while !head.windows(4).any(|x| x == b"\r\n\r\n") {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => head.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.lines();
+    let path = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or("/")
+        .to_owned();
+    let headers = lines
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_owned()))
+        .collect();
+
+    let request = Request {
+        path,
+        headers,
+        received: Instant::now(),
+    };
+
+    let in_flight = state.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+    state.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+
+    let response = (state.handler)(&request);
+    state
+        .requests
+        .lock()
+        .expect("mock server lock poisoned")
+        .push(request);
+
+    tokio::time::sleep(response.delay).await;
+
+    // a `transfer-encoding: chunked` response is sent without a content length
+    let chunked = response
+        .header("transfer-encoding")
+        .is_some_and(|x| x.eq_ignore_ascii_case("chunked"));
+
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nconnection: close\r\n",
+        response.status,
+        StatusCode::from_u16(response.status)
+            .ok()
+            .and_then(|x| x.canonical_reason())
+            .unwrap_or("Unknown"),
+    );
+    if !chunked {
+        head.push_str(&format!("content-length: {}\r\n", response.body.len()));
+    }
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+
+    let _ = stream.write_all(head.as_bytes()).await;
+    if chunked {
+        for chunk in response.body.chunks(1024) {
+            let _ = stream
+                .write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+                .await;
+            let _ = stream.write_all(chunk).await;
+            let _ = stream.write_all(b"\r\n").await;
+        }
+        let _ = stream.write_all(b"0\r\n\r\n").await;
+    } else {
+        let _ = stream.write_all(&response.body).await;
+    }
+    state.in_flight.fetch_sub(1, Ordering::SeqCst);
+    let _ = stream.shutdown().await;
+}
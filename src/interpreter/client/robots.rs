@@ -0,0 +1,193 @@
+//! A parser for `robots.txt` files, following the subset of
+//! [RFC 9309](https://www.rfc-editor.org/rfc/rfc9309) that applies to a
+//! single crawler.
+
+/// A single `Allow` or `Disallow` rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+/// The rules in a `robots.txt` file that apply to one user agent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Robots {
+    rules: Vec<Rule>,
+}
+
+impl Robots {
+    /// A `Robots` that allows every path.
+    pub const ALLOW_ALL: Self = Self { rules: Vec::new() };
+
+    /// Returns a `Robots` that disallows every path.
+    pub fn disallow_all() -> Self {
+        Self {
+            rules: vec![Rule {
+                allow: false,
+                pattern: "/".into(),
+            }],
+        }
+    }
+
+    /// Parses the rules in `robots.txt` that apply to the crawler named
+    /// `agent`.
+    ///
+    /// If there are groups that name `agent` (case-insensitively), their
+    /// rules are used.  Otherwise, the rules in the `*` groups are used.
+    pub fn parse(robots_txt: &str, agent: &str) -> Self {
+        let mut specific = Vec::new();
+        let mut wildcard = Vec::new();
+        let mut found_specific = false;
+
+        // the user agents of the current group
+        let mut agents: Vec<&str> = Vec::new();
+        // whether the last line was a `user-agent` line, so that consecutive
+        // `user-agent` lines form the same group
+        let mut in_agents = false;
+
+        for line in robots_txt.lines() {
+            let line = line.split_once('#').map_or(line, |(line, _)| line);
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match &*key.trim().to_ascii_lowercase() {
+                "user-agent" => {
+                    if !in_agents {
+                        agents.clear();
+                    }
+                    in_agents = true;
+                    agents.push(value);
+                }
+                key @ ("allow" | "disallow") => {
+                    in_agents = false;
+                    let is_specific = agents.iter().any(|x| x.eq_ignore_ascii_case(agent));
+                    found_specific |= is_specific;
+
+                    // an empty `Disallow` means nothing is disallowed
+                    if value.is_empty() {
+                        continue;
+                    }
+
+                    let rule = Rule {
+                        allow: key == "allow",
+                        pattern: value.to_owned(),
+                    };
+
+                    if is_specific {
+                        specific.push(rule.clone());
+                    }
+
+                    if agents.contains(&"*") {
+                        wildcard.push(rule);
+                    }
+                }
+                _ => in_agents = false,
+            }
+        }
+
+        Self {
+            rules: if found_specific { specific } else { wildcard },
+        }
+    }
+
+    /// Returns whether the crawler may fetch `path`, which should include the
+    /// query string, if any.
+    ///
+    /// The longest matching rule decides, and `Allow` wins a tie.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|rule| matches(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+/// Returns whether `path` starts with `pattern`, where `*` in `pattern`
+/// matches any sequence of characters, and a trailing `$` matches the end of
+/// `path`.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = pattern
+        .strip_suffix('$')
+        .map_or((pattern, false), |pattern| (pattern, true));
+
+    let mut parts = pattern.split('*');
+    let Some(rest) = parts.next().and_then(|prefix| path.strip_prefix(prefix)) else {
+        return false;
+    };
+
+    let parts: Vec<_> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return !anchored || rest.is_empty();
+    };
+
+    let mut rest = rest;
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+
+    if anchored {
+        rest.ends_with(last)
+    } else {
+        rest.contains(last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Robots;
+
+    const ROBOTS: &str = "
+# comments are ignored
+User-agent: *
+Disallow: /private/
+Allow: /private/public.html
+
+User-agent: other-bot
+User-agent: scrapelect
+Disallow: /secret   # no secrets
+Disallow: /*.json$
+Disallow:
+
+User-agent: *
+Disallow: /untracked
+";
+
+    #[test]
+    fn specific_agent() {
+        let robots = Robots::parse(ROBOTS, "scrapelect");
+        assert!(robots.is_allowed("/index.html"));
+        assert!(robots.is_allowed("/private/index.html"));
+        assert!(!robots.is_allowed("/secret"));
+        assert!(!robots.is_allowed("/secrets/page.html"));
+        assert!(!robots.is_allowed("/data/items.json"));
+        assert!(robots.is_allowed("/data/items.json?page=2"));
+    }
+
+    #[test]
+    fn wildcard_agent() {
+        let robots = Robots::parse(ROBOTS, "another-bot");
+        assert!(robots.is_allowed("/index.html"));
+        assert!(!robots.is_allowed("/private/index.html"));
+        assert!(robots.is_allowed("/private/public.html"));
+        assert!(!robots.is_allowed("/untracked/page.html"));
+        assert!(robots.is_allowed("/secret"));
+    }
+
+    #[test]
+    fn empty_and_disallow_all() {
+        assert!(Robots::parse("", "scrapelect").is_allowed("/"));
+        assert!(Robots::parse(
+            "User-agent: *\nDisallow: /\nUser-agent: scrapelect\nDisallow:",
+            "scrapelect"
+        )
+        .is_allowed("/"));
+        assert!(Robots::ALLOW_ALL.is_allowed("/anything"));
+        assert!(!Robots::disallow_all().is_allowed("/anything"));
+    }
+}
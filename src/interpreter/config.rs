@@ -0,0 +1,407 @@
+//! Options that configure how a program is run, which can be set by the
+//! directives in the head of a program or by command-line flags.
+
+use std::{num::NonZeroUsize, time::Duration};
+
+use encoding_rs::Encoding;
+use reqwest::Url;
+use scrapelect_filter_types::{bail, MessageExt as _, WrapExt as _};
+
+use crate::frontend::ast::{Directive, Leaf};
+
+use super::Result;
+
+/// Options for running a program.  Unset options (`None`) use their default value.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// The maximum number of requests that can be in flight at once.
+    ///
+    /// Set with `@concurrency` or `--concurrency`.
+    pub concurrency: Option<NonZeroUsize>,
+    /// The minimum time to wait between starting two requests to the same host.
+    ///
+    /// Set with `@delay_ms` or `--delay-ms`.
+    pub delay: Option<Duration>,
+    /// Whether to fetch pages even if the site's `robots.txt` disallows it.
+    ///
+    /// Set with `--ignore-robots`.
+    pub ignore_robots: Option<bool>,
+    /// Whether to only fetch each URL once, reusing the page for later
+    /// requests to the same URL.
+    ///
+    /// Set with `@dedup_requests`.
+    pub dedup_requests: Option<bool>,
+    /// The URL to resolve relative URLs against, instead of the URL of the
+    /// current page.
+    ///
+    /// Set with `@base_url`.
+    pub base_url: Option<Url>,
+    /// The field that identifies an item in a list on a page, so that items
+    /// with the same value for it on followed or later pages are dropped.
+    ///
+    /// Set with `@dedup_key`.
+    pub dedup_key: Option<String>,
+    /// Whether to reuse the results of equal calls of pure filters within each
+    /// element block, instead of calling the filter again.
+    ///
+    /// Set with `@memoize`.
+    pub memoize: Option<bool>,
+    /// The maximum number of elements that each top-level element block
+    /// selects.
+    ///
+    /// Set with `@limit` or `--limit`.
+    pub limit: Option<NonZeroUsize>,
+    /// How to parse the documents that are fetched.
+    ///
+    /// Set with `@parser`.
+    pub parser: Option<ParseMode>,
+    /// The encoding to decode fetched pages with, instead of the encoding that
+    /// each page declares.
+    ///
+    /// Set with `--input-encoding`.
+    pub input_encoding: Option<&'static Encoding>,
+    /// The `(name, value)` cookies to send with requests to the host of the
+    /// starting URL.
+    ///
+    /// Set with `@cookies` or `--cookie`.
+    pub cookies: Option<Vec<(String, String)>>,
+    /// The `http` or `https` proxy (or with the `socks` feature, `socks5` or
+    /// `socks5h` proxy) to send requests through.
+    ///
+    /// Set with `@proxy` or `--proxy`.  Parse it with [`HttpClient::parse_proxy`].
+    ///
+    /// [`HttpClient::parse_proxy`]: super::HttpClient::parse_proxy
+    pub proxy: Option<Url>,
+    /// Whether to suppress the output of `dbg` and printed warnings.  Warnings
+    /// are still collected, see [`Interpreter::take_diagnostics`].
+    ///
+    /// Set with `--quiet`.
+    ///
+    /// [`Interpreter::take_diagnostics`]: super::Interpreter::take_diagnostics
+    pub quiet: Option<bool>,
+    /// The maximum size of a response body, in bytes.  Larger responses raise
+    /// an error instead of being read.
+    ///
+    /// Set with `--max-response-size`.
+    pub max_response_size: Option<u64>,
+    /// The status codes outside of `2xx` whose responses are still read, instead
+    /// of raising an error.
+    ///
+    /// Set with `@allow_status` or `--allow-status`.
+    pub allow_status: Option<Vec<u16>>,
+    /// The seed for filters that make random choices, like `sample`, so that
+    /// runs on the same pages make the same choices.
+    ///
+    /// Set with `--seed`.
+    pub seed: Option<u64>,
+    /// How many times to fetch the starting page again, after a short wait, if
+    /// every top-level binding of the result is empty, like for a page that
+    /// sometimes serves an interstitial instead of its content.
+    ///
+    /// Set with `--retries-on-empty`.
+    pub retries_on_empty: Option<u32>,
+    /// The maximum number of pages to follow from the starting page, through
+    /// element blocks with a URL or `fetch`.  The starting page is at depth 0.
+    ///
+    /// Set with `--max-depth`.
+    pub max_depth: Option<usize>,
+    /// Whether to accept invalid TLS certificates, like self-signed ones,
+    /// instead of raising an error.  This is insecure, so it is off by default.
+    ///
+    /// Set with `--allow-insecure`.
+    pub allow_insecure: Option<bool>,
+}
+
+/// How to parse a document into elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Parse each document as HTML, XML, or JSON, depending on its
+    /// `Content-Type`, like [`ParseMode::for_content_type`].
+    #[default]
+    Auto,
+    /// Parse documents as HTML, like a browser.
+    Html,
+    /// Parse documents as XML, for feeds like RSS and Atom.
+    Xml,
+    /// Parse documents as JSON, into the value bound to `$document`, with an
+    /// empty element tree.
+    Json,
+}
+
+impl ParseMode {
+    /// Returns how to parse a document with the `Content-Type` header
+    /// `content_type`: as JSON for JSON types, like `application/json` and
+    /// `application/ld+json`, as XML for XML types other than XHTML, like
+    /// `application/rss+xml`, and as HTML otherwise.
+    #[must_use]
+    pub fn for_content_type(content_type: Option<&str>) -> Self {
+        let Some(content_type) = content_type else {
+            return Self::Html;
+        };
+        let essence = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+
+        match essence.as_str() {
+            "application/json" | "text/json" => Self::Json,
+            x if x.ends_with("+json") => Self::Json,
+            "application/xhtml+xml" => Self::Html,
+            "application/xml" | "text/xml" => Self::Xml,
+            x if x.ends_with("+xml") => Self::Xml,
+            _ => Self::Html,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the options set by the directives in the head of a program.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if a directive is unknown, set more than once, or has
+    /// an invalid value.
+    pub fn from_head(head: &[Directive<'_>]) -> Result<Self> {
+        let mut config = Self::default();
+
+        for Directive { id, value } in head {
+            let mut inner = || match *id {
+                "concurrency" => set(
+                    &mut config.concurrency,
+                    usize::try_from(int(value)?)
+                        .ok()
+                        .and_then(NonZeroUsize::new)
+                        .msg("expected a positive integer")?,
+                ),
+                "delay_ms" => set(
+                    &mut config.delay,
+                    u64::try_from(int(value)?)
+                        .ok()
+                        .map(Duration::from_millis)
+                        .msg("expected a non-negative integer")?,
+                ),
+                "limit" => set(
+                    &mut config.limit,
+                    usize::try_from(int(value)?)
+                        .ok()
+                        .and_then(NonZeroUsize::new)
+                        .msg("expected a positive integer")?,
+                ),
+                "dedup_requests" => set(&mut config.dedup_requests, bool(value)?),
+                "memoize" => set(&mut config.memoize, bool(value)?),
+                "dedup_key" => set(
+                    &mut config.dedup_key,
+                    Some(string(value)?)
+                        .filter(|x| !x.is_empty())
+                        .msg("expected a nonempty field name")?
+                        .to_owned(),
+                ),
+                "cookies" => set(
+                    &mut config.cookies,
+                    string(value)?
+                        .split(';')
+                        .filter(|x| !x.trim().is_empty())
+                        .map(|cookie| {
+                            let (name, value) = cookie
+                                .split_once('=')
+                                .with_msg(|| format!("expected `name=value`, got `{cookie}`"))?;
+                            Ok((name.trim().to_owned(), value.trim().to_owned()))
+                        })
+                        .collect::<Result<_>>()?,
+                ),
+                "allow_status" => set(
+                    &mut config.allow_status,
+                    match value {
+                        Leaf::Int(_) => vec![status(int(value)?)?],
+                        _ => string(value)?
+                            .split(',')
+                            .filter(|x| !x.trim().is_empty())
+                            .map(|code| {
+                                status(code.trim().parse().with_msg(|| {
+                                    format!("expected a status code, got `{}`", code.trim())
+                                })?)
+                            })
+                            .collect::<Result<_>>()?,
+                    },
+                ),
+                "proxy" => set(
+                    &mut config.proxy,
+                    super::HttpClient::parse_proxy(string(value)?)?,
+                ),
+                "parser" => set(
+                    &mut config.parser,
+                    match string(value)? {
+                        "auto" => ParseMode::Auto,
+                        "html" => ParseMode::Html,
+                        "xml" => ParseMode::Xml,
+                        "json" => ParseMode::Json,
+                        other => {
+                            bail!("expected `auto`, `html`, `xml`, or `json`, got `{other}`")
+                        }
+                    },
+                ),
+                "base_url" => {
+                    let url = string(value)?;
+                    set(
+                        &mut config.base_url,
+                        url.parse()
+                            .with_msg(|| format!("`{url}` is not a valid absolute URL"))?,
+                    )
+                }
+                other => bail!("unknown directive `@{other}`"),
+            };
+
+            inner().wrap_with(|| format!("note: occurred while reading directive `@{id}`"))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Returns `self`, with any unset options filled in by those set in `other`.
+    #[must_use]
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            concurrency: self.concurrency.or(other.concurrency),
+            delay: self.delay.or(other.delay),
+            ignore_robots: self.ignore_robots.or(other.ignore_robots),
+            dedup_requests: self.dedup_requests.or(other.dedup_requests),
+            base_url: self.base_url.or(other.base_url),
+            dedup_key: self.dedup_key.or(other.dedup_key),
+            memoize: self.memoize.or(other.memoize),
+            limit: self.limit.or(other.limit),
+            parser: self.parser.or(other.parser),
+            input_encoding: self.input_encoding.or(other.input_encoding),
+            cookies: self.cookies.or(other.cookies),
+            proxy: self.proxy.or(other.proxy),
+            quiet: self.quiet.or(other.quiet),
+            max_response_size: self.max_response_size.or(other.max_response_size),
+            allow_status: self.allow_status.or(other.allow_status),
+            seed: self.seed.or(other.seed),
+            retries_on_empty: self.retries_on_empty.or(other.retries_on_empty),
+            max_depth: self.max_depth.or(other.max_depth),
+            allow_insecure: self.allow_insecure.or(other.allow_insecure),
+        }
+    }
+}
+
+/// Sets an option, raising an error if it was already set by another directive.
+fn set<T>(option: &mut Option<T>, value: T) -> Result<()> {
+    if option.is_some() {
+        bail!("directive is set more than once");
+    }
+
+    *option = Some(value);
+    Ok(())
+}
+
+fn int(value: &Leaf<'_>) -> Result<i64> {
+    match value {
+        Leaf::Int(n) => Ok(*n),
+        other => bail!("expected an integer, got `{other:?}`"),
+    }
+}
+
+/// Checks that `code` is an HTTP status code, from 100 to 599.
+fn status(code: i64) -> Result<u16> {
+    u16::try_from(code)
+        .ok()
+        .filter(|x| (100..600).contains(x))
+        .with_msg(|| format!("`{code}` is not an HTTP status code"))
+}
+
+fn string<'a>(value: &'a Leaf<'_>) -> Result<&'a str> {
+    match value {
+        Leaf::String(s) => Ok(s),
+        other => bail!("expected a string, got `{other:?}`"),
+    }
+}
+
+fn bool(value: &Leaf<'_>) -> Result<bool> {
+    match value {
+        Leaf::String(s) if s == "true" => Ok(true),
+        Leaf::String(s) if s == "false" => Ok(false),
+        other => bail!("expected `true` or `false`, got `{other:?}`"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::frontend::Parser;
+
+    use super::{Config, ParseMode};
+
+    #[test]
+    fn test_from_head() {
+        let program = Parser::new(
+            r#"@concurrency: 2; @delay_ms: 250; @dedup_requests: true; @limit: 3; @parser: xml; @base_url: "https://a.com/b/";
+            @dedup_key: "id"; @memoize: true; @cookies: "session=abc; theme = dark"; @allow_status: "404, 410";"#,
+        )
+        .parse()
+        .expect("parse error");
+        let config = Config::from_head(&program.head).expect("invalid head");
+        assert_eq!(config.concurrency.map(usize::from), Some(2));
+        assert_eq!(config.delay, Some(Duration::from_millis(250)));
+        assert_eq!(config.dedup_requests, Some(true));
+        assert_eq!(config.limit.map(usize::from), Some(3));
+        assert_eq!(config.dedup_key.as_deref(), Some("id"));
+        assert_eq!(config.memoize, Some(true));
+        assert_eq!(config.parser, Some(ParseMode::Xml));
+        assert_eq!(
+            config.cookies,
+            Some(vec![
+                ("session".to_owned(), "abc".to_owned()),
+                ("theme".to_owned(), "dark".to_owned()),
+            ])
+        );
+        assert_eq!(config.allow_status, Some(vec![404, 410]));
+        assert_eq!(
+            config.base_url.as_ref().map(|x| x.as_str()),
+            Some("https://a.com/b/")
+        );
+
+        for invalid in [
+            "@concurrency: 0;",
+            r#"@concurrency: "two";"#,
+            "@concurrency: 1; @concurrency: 2;",
+            "@delay_ms: -1;",
+            "@dedup_requests: yes;",
+            "@limit: 0;",
+            r#"@dedup_key: "";"#,
+            "@parser: yaml;",
+            r#"@cookies: "session";"#,
+            r#"@proxy: "ftp://localhost";"#,
+            "@allow_status: 1000;",
+            r#"@allow_status: "404, gone";"#,
+            r#"@base_url: "/relative";"#,
+            "@base_url: 1;",
+            "@kitty: 1;",
+        ] {
+            let program = Parser::new(invalid).parse().expect("parse error");
+            assert!(Config::from_head(&program.head).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_mode_for_content_type() {
+        for (content_type, mode) in [
+            (None, ParseMode::Html),
+            (Some("text/html; charset=utf-8"), ParseMode::Html),
+            (Some("application/xhtml+xml"), ParseMode::Html),
+            (Some("application/json"), ParseMode::Json),
+            (Some("Application/LD+JSON; charset=utf-8"), ParseMode::Json),
+            (Some("text/xml"), ParseMode::Xml),
+            (Some("application/rss+xml"), ParseMode::Xml),
+            (Some("image/png"), ParseMode::Html),
+        ] {
+            assert_eq!(
+                ParseMode::for_content_type(content_type),
+                mode,
+                "{content_type:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Storage for documents fetched while a program is running, so that elements
+//! from them can be used for the rest of the program.
+
+use std::fmt;
+
+use scraper::Html;
+use typed_arena::Arena;
+
+/// An append-only list of documents, which lets you borrow a document for as
+/// long as the list, even while adding more documents.
+///
+/// The documents are stored in chunks that are never moved, so adding one
+/// doesn't walk the documents before it.
+#[derive(Default)]
+pub struct Documents(Arena<Html>);
+
+impl fmt::Debug for Documents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Documents")
+            .field("len", &self.0.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Documents {
+    /// Creates a new, empty list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `html` to the end of the list and returns a reference to it.
+    pub fn push(&self, html: Html) -> &Html {
+        self.0.alloc(html)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+
+    use super::Documents;
+
+    #[test]
+    fn documents_outlive_pushes() {
+        let documents = Documents::new();
+
+        let first = documents.push(Html::parse_fragment("<p>first</p>"));
+        let second = documents.push(Html::parse_fragment("<p>second</p>"));
+        // enough to fill more than one chunk
+        for _ in 0..100 {
+            documents.push(Html::parse_fragment("<p>more</p>"));
+        }
+
+        assert_eq!(first.root_element().text().collect::<String>(), "first");
+        assert_eq!(second.root_element().text().collect::<String>(), "second");
+    }
+}
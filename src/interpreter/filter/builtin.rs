@@ -3,8 +3,8 @@
 //! Conventions used:
 //!
 //! - Signature of a filter: `value: T | name(arg_1: U_1, ...): V` means that `name` is a filter
-//!     that takes a *pipeline value* of type `T`, has arguments `arg_i` of type `U_i`, and returns
-//!     a value of type `V`
+//!   that takes a *pipeline value* of type `T`, has arguments `arg_i` of type `U_i`, and returns
+//!   a value of type `V`
 //!   - Specifying an arg type with a question mark (e.g., `value: Value | dbg(msg: String?): Value`)
 //!     means that that argument (e.g., `msg`) is *optional* and can be omitted.
 //! - List shorthand: a `List` is represented as `[a_0, a_1, a_2, ..., a_n]` to mean that
@@ -18,13 +18,17 @@
 //!   in filters.
 
 use std::{
-    collections::BTreeMap,
-    sync::{Arc, LazyLock},
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{btree_map::Entry, BTreeMap, BTreeSet, HashMap},
+    sync::{Arc, LazyLock, Mutex},
+    time::Duration,
 };
 
+use regex::Regex;
 use scrapelect_filter_types::{
-    bail, filter_fn, EValue, ElementContextView, FilterDyn, ListIter, MessageExt, Number, PValue,
-    Pipeline, Result, Value,
+    bail, filter_fn, other, EValue, ElementContextView, FilterDyn, FilterFuture, ListIter,
+    MessageExt, Number, PValue, Pipeline, Result, Value, WrapExt,
 };
 
 type Structure<'doc> = BTreeMap<Arc<str>, PValue<'doc>>;
@@ -44,17 +48,46 @@ pub fn id<'doc>(value: PValue<'doc>) -> Result<PValue<'doc>> {
 /// Signature: `value | dbg(msg: String?): Value`
 ///
 /// Returns the `value` passed into it, printing a copy to the console,
-/// prepended with `msg` if specified.
+/// prepended with `msg` if specified.  Nothing is printed when running with
+/// `--quiet`.
 ///
 /// # Examples
 ///
 /// - `"hi" | dbg()` returns `"hi"` and prints `dbg message: "hi"`
 /// - `"hi" | dbg(msg: "I say")` returns `"hi"` and prints `I say: "hi"`
-#[filter_fn]
-pub fn dbg<'doc>(value: PValue<'doc>, msg: Option<Arc<str>>) -> Result<PValue<'doc>> {
+#[filter_fn(impure)]
+pub fn dbg<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
+    value: PValue<'doc>,
+    msg: Option<Arc<str>>,
+    ctx: &mut E,
+) -> Result<PValue<'doc>> {
     let value: EValue = value.into();
-    eprintln!("{}: {}", value, msg.as_deref().unwrap_or("dbg message"));
+    if !ctx.quiet() {
+        eprintln!("{}: {}", value, msg.as_deref().unwrap_or("dbg message"));
+    }
+
+    Ok(value.into())
+}
 
+/// Signature: `value | warn(msg: String): Value`
+///
+/// Returns the `value` passed into it, reporting a warning with `msg` and a copy
+/// of `value`.  The `scrapelect` CLI prints warnings to the console when the
+/// program finishes; library users can retrieve them with
+/// [`Interpreter::take_diagnostics`](crate::interpreter::Interpreter::take_diagnostics).
+///
+/// # Examples
+///
+/// - `"" | warn(msg: "empty title")` returns `""` and reports the warning
+///   `empty title: ""`
+#[filter_fn(impure)]
+pub fn warn<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
+    value: PValue<'doc>,
+    msg: Arc<str>,
+    ctx: &mut E,
+) -> Result<PValue<'doc>> {
+    let value: EValue = value.into();
+    ctx.warn(&format!("{msg}: {value}"));
     Ok(value.into())
 }
 
@@ -70,7 +103,7 @@ pub fn dbg<'doc>(value: PValue<'doc>, msg: Option<Arc<str>>) -> Result<PValue<'d
 ///   `stripped: "   hi   " | tee(into: "spacey") | strip()` results in:
 ///   - `$spacey = "   hi   "`
 ///   - `$stripped = "hi"`
-#[filter_fn]
+#[filter_fn(impure)]
 pub fn tee<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
     value: PValue<'doc>,
     into: Arc<str>,
@@ -81,6 +114,38 @@ pub fn tee<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
     Ok(value.into())
 }
 
+/// Signature: `value | tee_append(into: String): Value`
+///
+/// Returns the `value` passed into it, appending a copy to the list in the
+/// variable with name `into`, or binding `into` to a new list with just `value`
+/// if it is not bound yet.  Raises an error if `into` is bound to something
+/// other than a list.
+///
+/// A new list is bound in the current scope, like with [`tee`], but appending
+/// to a list from an outer scope changes it in that scope, so appends inside an
+/// element block are seen after the block, in document order, even if the
+/// blocks' pages load in a different order.  Elements can't be appended to a
+/// list from an outer scope, because they don't outlive their block.
+///
+/// # Examples
+///
+/// - `a: 1 | tee_append(into: "seen"); b: 2 | tee_append(into: "seen");` results in:
+///   - `$a = 1` and `$b = 2`
+///   - `$seen = [1, 2]`
+/// - `first: "-" | tee_append(into: "ids"); items: li { id: $element | attrs() | take(key: "id") | tee_append(into: "ids"); }*; ids: $ids;`
+///   results in `$ids` being `"-"` and then the `id` of each `li` element, in order.
+/// - `x: 1 | tee(into: "one") | tee_append(into: "one")` raises an error.
+#[filter_fn(impure)]
+pub fn tee_append<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
+    value: PValue<'doc>,
+    into: Arc<str>,
+    ctx: &mut E,
+) -> Result<PValue<'doc>> {
+    let value: EValue = value.into();
+    ctx.append(into.to_string().into(), value.clone())?;
+    Ok(value.into())
+}
+
 /// Signature: `value: String | strip(): String`
 ///
 /// Removes leading and trailing whitespace from a string.
@@ -94,6 +159,333 @@ pub fn strip<'doc>(value: Arc<str>) -> Result<PValue<'doc>> {
     Ok(Value::String(value.trim().into()))
 }
 
+/// Signature: `value: String | capitalize(): String`
+///
+/// Uppercases the first character of `value`, leaving the rest of it as it is.
+/// Some characters become more than one when uppercased, like `ß` to `SS`.
+///
+/// # Examples
+///
+/// - `"hello World" | capitalize()` returns `"Hello World"`
+/// - `"élan" | capitalize()` returns `"Élan"`
+/// - `"1st place" | capitalize()` returns `"1st place"`
+/// - `"" | capitalize()` returns `""`
+#[filter_fn]
+pub fn capitalize<'doc>(value: Arc<str>) -> Result<PValue<'doc>> {
+    let mut chars = value.chars();
+    Ok(Value::String(match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect::<String>().into(),
+        None => value,
+    }))
+}
+
+/// Splits `value` into words, for the case conversion filters.
+///
+/// Words are separated by any character that isn't a letter or a digit, and
+/// before an uppercase letter that follows a lowercase letter or a digit.  In a
+/// run of uppercase letters, the last one starts a new word if a lowercase
+/// letter follows it, so acronyms are kept together: `HTMLParser` is `HTML` and
+/// `Parser`, and `userID` is `user` and `ID`.
+fn words(value: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+
+    for part in value.split(|c: char| !c.is_alphanumeric()) {
+        let chars: Vec<_> = part.char_indices().collect();
+        let mut start = 0;
+        for (i, &(index, c)) in chars.iter().enumerate().skip(1) {
+            let prev = chars[i - 1].1;
+            let next_is_lower = chars.get(i + 1).is_some_and(|x| x.1.is_lowercase());
+            if c.is_uppercase()
+                && (prev.is_lowercase()
+                    || prev.is_numeric()
+                    || (prev.is_uppercase() && next_is_lower))
+            {
+                words.push(&part[start..index]);
+                start = index;
+            }
+        }
+        words.push(&part[start..]);
+    }
+
+    words.retain(|x| !x.is_empty());
+    words
+}
+
+/// Converts `value` to `case`, which is `snake`, `camel`, or `kebab`.
+fn convert_case(value: &str, case: &str) -> Result<String> {
+    let words = words(value);
+    let lower = words.iter().map(|x| x.to_lowercase());
+
+    Ok(match case {
+        "snake" => lower.collect::<Vec<_>>().join("_"),
+        "kebab" => lower.collect::<Vec<_>>().join("-"),
+        "camel" => lower
+            .enumerate()
+            .map(|(i, word)| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) if i > 0 => first.to_uppercase().chain(chars).collect(),
+                    _ => word,
+                }
+            })
+            .collect(),
+        other => bail!("unknown case `{other}` (expected `snake`, `camel`, or `kebab`)"),
+    })
+}
+
+/// Signature: `value: String | to_snake_case(): String`
+///
+/// Converts `value` to `snake_case`: its words in lowercase, joined with `_`.
+///
+/// Words are separated by any character that isn't a letter or a digit, like a
+/// space, `-`, or `_`, and by a change from lowercase to uppercase, as in
+/// `someField`.  A run of uppercase letters is one word, like `HTML` in
+/// `HTMLParser`, so acronyms are kept together.
+///
+/// # Examples
+///
+/// - `"Some Field Name" | to_snake_case()` returns `"some_field_name"`
+/// - `"HTMLParser" | to_snake_case()` returns `"html_parser"`
+/// - `"user-ID" | to_snake_case()` returns `"user_id"`
+#[filter_fn]
+pub fn to_snake_case<'doc>(value: Arc<str>) -> Result<PValue<'doc>> {
+    convert_case(&value, "snake").map(|x| Value::String(x.into()))
+}
+
+/// Signature: `value: String | to_camel_case(): String`
+///
+/// Converts `value` to `camelCase`: its words in lowercase, with the first
+/// letter of each word after the first in uppercase.  Words are split like in
+/// `to_snake_case`, so acronyms become one word, like `Html`.
+///
+/// # Examples
+///
+/// - `"Some Field Name" | to_camel_case()` returns `"someFieldName"`
+/// - `"HTMLParser" | to_camel_case()` returns `"htmlParser"`
+/// - `"user_id" | to_camel_case()` returns `"userId"`
+#[filter_fn]
+pub fn to_camel_case<'doc>(value: Arc<str>) -> Result<PValue<'doc>> {
+    convert_case(&value, "camel").map(|x| Value::String(x.into()))
+}
+
+/// Signature: `value: String | to_kebab_case(): String`
+///
+/// Converts `value` to `kebab-case`: its words in lowercase, joined with `-`.
+/// Words are split like in `to_snake_case`.
+///
+/// # Examples
+///
+/// - `"Some Field Name" | to_kebab_case()` returns `"some-field-name"`
+/// - `"userID" | to_kebab_case()` returns `"user-id"`
+#[filter_fn]
+pub fn to_kebab_case<'doc>(value: Arc<str>) -> Result<PValue<'doc>> {
+    convert_case(&value, "kebab").map(|x| Value::String(x.into()))
+}
+
+/// Signature: `value: Structure | rekey(case: String): Structure`
+///
+/// Converts each top-level key of `value` to `case`, which is `snake`, `camel`,
+/// or `kebab`, like `to_snake_case`, `to_camel_case`, or `to_kebab_case`.
+/// Nested structures are not changed.
+///
+/// Raises an error if `case` is something else, or if two keys become the same
+/// key.
+///
+/// # Examples
+///
+/// - `{ "Product Name": "Widget", "unitPrice": 5 } | rekey(case: "snake")` returns
+///   `{ product_name: "Widget", unit_price: 5 }`
+/// - `{ "a b": 1, "a-b": 2 } | rekey(case: "snake")` raises an error
+#[filter_fn]
+pub fn rekey<'doc>(value: Structure<'doc>, case: Arc<str>) -> Result<PValue<'doc>> {
+    let mut output = BTreeMap::new();
+
+    for (key, value) in value {
+        let new_key: Arc<str> = convert_case(&key, &case)?.into();
+        match output.entry(new_key) {
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+            Entry::Occupied(entry) => bail!(
+                "the keys `{key}` and another key are both `{}` in {case} case",
+                entry.key()
+            ),
+        }
+    }
+
+    Ok(Value::Structure(output))
+}
+
+/// Signature: `value: Structure | rename(map: Structure): Structure`
+///
+/// Renames the top-level keys of `value`: each key in `map` is renamed to its
+/// value in `map`, which must be a string.  Keys that aren't in `map` are kept
+/// as they are, and keys in `map` that aren't in `value` are ignored.  All keys
+/// are renamed at once, so two keys can swap names.
+///
+/// Raises an error if two keys would have the same name after renaming.
+///
+/// # Examples
+///
+/// - `{ title: "Widget", cost: 5 } | rename(map: { cost: "price" })` returns
+///   `{ title: "Widget", price: 5 }`
+/// - `{ a: 1, b: 2 } | rename(map: { a: "b" })` raises an error
+#[filter_fn]
+pub fn rename<'doc>(
+    value: Structure<'doc>,
+    map: BTreeMap<Arc<str>, EValue<'doc>>,
+) -> Result<PValue<'doc>> {
+    let map = map
+        .into_iter()
+        .map(|(key, value)| {
+            let value: Arc<str> = value
+                .try_unwrap()
+                .with_msg(|| format!("expected a string to rename `{key}` to"))?;
+            Ok((key, value))
+        })
+        .collect::<Result<BTreeMap<_, _>>>()?;
+
+    let mut output = BTreeMap::new();
+
+    for (key, value) in value {
+        let new_key = map.get(&key).map_or_else(|| Arc::clone(&key), Arc::clone);
+        match output.entry(new_key) {
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+            Entry::Occupied(entry) => bail!(
+                "the key `{key}` and another key are both named `{}` after renaming",
+                entry.key()
+            ),
+        }
+    }
+
+    Ok(Value::Structure(output))
+}
+
+/// Signature: `value: Structure | normalize_keys(trim: Bool?, collapse: Bool?, lower: Bool?, strict: Bool?): Structure`
+///
+/// Normalizes each top-level key of `value`, so that keys scraped with stray
+/// whitespace or different casing become the same key.  By default, each key
+/// is trimmed (`trim`), each run of whitespace in it is replaced with one space
+/// (`collapse`), and it is lowercased (`lower`).  Set an option to `false` to
+/// skip that step.
+///
+/// If two keys become the same key, the value of the one that sorts last is
+/// kept, or if `strict` is `true`, this raises an error.
+///
+/// # Examples
+///
+/// - `{ " Unit  Price ": 5, "Name": "Widget" } | normalize_keys()` returns
+///   `{ "unit price": 5, name: "Widget" }`
+/// - `{ "Name": "a", "name ": "b" } | normalize_keys()` returns `{ name: "b" }`
+/// - `{ "Name": "a", "name ": "b" } | normalize_keys(strict: true)` raises an error
+#[filter_fn]
+pub fn normalize_keys<'doc>(
+    value: Structure<'doc>,
+    trim: Option<bool>,
+    collapse: Option<bool>,
+    lower: Option<bool>,
+    strict: Option<bool>,
+) -> Result<PValue<'doc>> {
+    let mut output = BTreeMap::new();
+
+    for (key, value) in value {
+        let mut new_key = key.to_string();
+        if collapse.unwrap_or(true) {
+            new_key = collapse_whitespace(&new_key);
+        }
+        if trim.unwrap_or(true) {
+            new_key = new_key.trim().to_owned();
+        }
+        if lower.unwrap_or(true) {
+            new_key = new_key.to_lowercase();
+        }
+
+        match output.entry(Arc::from(new_key)) {
+            Entry::Occupied(entry) if strict.unwrap_or_default() => bail!(
+                "the key `{key}` and another key are both `{}` after normalizing",
+                entry.key()
+            ),
+            Entry::Occupied(mut entry) => {
+                entry.insert(value);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+        }
+    }
+
+    Ok(Value::Structure(output))
+}
+
+/// Replaces each run of whitespace in `text` with one space, keeping a space
+/// at the start or end if `text` has whitespace there.
+fn collapse_whitespace(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !in_space {
+                out.push(' ');
+            }
+            in_space = true;
+        } else {
+            out.push(c);
+            in_space = false;
+        }
+    }
+    out
+}
+
+/// Signature: `value: String | strip_prefix(fix: String, required: Bool?): String`
+///
+/// Removes `fix` from the start of `value`, once, if `value` starts with it.
+/// Otherwise, returns `value` unchanged, or raises an error if `required` is
+/// `true`.
+///
+/// # Examples
+///
+/// - `"$4.99" | strip_prefix(fix: "$")` returns `"4.99"`
+/// - `"4.99" | strip_prefix(fix: "$")` returns `"4.99"`
+/// - `"4.99" | strip_prefix(fix: "$", required: true)` raises an error
+#[filter_fn]
+pub fn strip_prefix<'doc>(
+    value: Arc<str>,
+    fix: Arc<str>,
+    required: Option<bool>,
+) -> Result<PValue<'doc>> {
+    match value.strip_prefix(&*fix) {
+        Some(stripped) => Ok(Value::String(stripped.into())),
+        None if required.unwrap_or_default() => bail!("`{value}` does not start with `{fix}`"),
+        None => Ok(Value::String(value)),
+    }
+}
+
+/// Signature: `value: String | strip_suffix(fix: String, required: Bool?): String`
+///
+/// Removes `fix` from the end of `value`, once, if `value` ends with it.
+/// Otherwise, returns `value` unchanged, or raises an error if `required` is
+/// `true`.
+///
+/// # Examples
+///
+/// - `"12 items" | strip_suffix(fix: " items")` returns `"12"`
+/// - `"12" | strip_suffix(fix: " items")` returns `"12"`
+/// - `"12" | strip_suffix(fix: " items", required: true)` raises an error
+#[filter_fn]
+pub fn strip_suffix<'doc>(
+    value: Arc<str>,
+    fix: Arc<str>,
+    required: Option<bool>,
+) -> Result<PValue<'doc>> {
+    match value.strip_suffix(&*fix) {
+        Some(stripped) => Ok(Value::String(stripped.into())),
+        None if required.unwrap_or_default() => bail!("`{value}` does not end with `{fix}`"),
+        None => Ok(Value::String(value)),
+    }
+}
+
 /// Signature: `value: Element | attrs(): Structure`
 ///
 /// Returns the attributes of an element as key-value records in the structure.
@@ -115,6 +507,43 @@ pub fn attrs<'doc>(value: scraper::ElementRef<'doc>) -> Result<PValue<'doc>> {
     ))
 }
 
+/// Signature: `value: Element | data_attrs(): Structure`
+///
+/// Returns the `data-*` attributes of an element, like `attrs`, but without the
+/// `data-` prefix and with their names in camelCase, like the `dataset` of an
+/// element in JavaScript: each `-` followed by a lowercase letter is removed,
+/// and the letter is uppercased.
+///
+/// # Examples
+///
+/// - `<div data-id="7" data-product-name="Widget" class="card"></div> | data_attrs()`
+///   returns `{ id: "7", productName: "Widget" }`
+/// - `<p>Hello!</p> | data_attrs()` returns `{}`
+#[filter_fn]
+pub fn data_attrs<'doc>(value: scraper::ElementRef<'doc>) -> Result<PValue<'doc>> {
+    Ok(Value::Structure(
+        value
+            .value()
+            .attrs()
+            .filter_map(|(k, v)| {
+                let name = k.strip_prefix("data-")?;
+                let mut key = String::with_capacity(name.len());
+                let mut chars = name.chars().peekable();
+                while let Some(c) = chars.next() {
+                    match chars.peek() {
+                        Some(next) if c == '-' && next.is_ascii_lowercase() => {
+                            key.push(next.to_ascii_uppercase());
+                            chars.next();
+                        }
+                        _ => key.push(c),
+                    }
+                }
+                Some((Arc::from(key), Value::String(Arc::from(v))))
+            })
+            .collect(),
+    ))
+}
+
 /// Signature: `value: Structure | take(key: String): Value`
 ///
 /// Returns the value at key `key` in the structure.  If there is no value there,
@@ -129,6 +558,56 @@ pub fn take<'doc>(mut value: Structure<'doc>, key: Arc<str>) -> Result<PValue<'d
     Ok(value.remove(&key).unwrap_or(Value::Null))
 }
 
+/// Splits a path like `a.b.0` into its segments, raising an error if it has an
+/// empty segment.
+fn path_segments(path: &str) -> Result<Vec<&str>> {
+    let segments: Vec<_> = path.split('.').collect();
+    if segments.iter().any(|x| x.is_empty()) {
+        bail!("invalid path `{path}`: expected keys and list indices separated by `.`");
+    }
+    Ok(segments)
+}
+
+/// Returns the value at `segments` in `value`, or `None` if a key or index is
+/// missing along the way.  A segment looks up a key in a structure, or an index
+/// in a list if it is a number.
+fn lookup_path<'doc>(value: PValue<'doc>, segments: &[&str]) -> Option<PValue<'doc>> {
+    segments
+        .iter()
+        .try_fold(value, |value, segment| match value {
+            Value::Structure(mut x) => x.remove(*segment),
+            Value::List(mut x) => {
+                let i = segment.parse::<usize>().ok()?;
+                (i < x.len()).then(|| x.swap_remove(i))
+            }
+            _ => None,
+        })
+}
+
+/// Signature: `value: Value | get_path(path: String, default: Value?): Value`
+///
+/// Returns the value at `path` in `value`, where `path` is a list of keys and
+/// list indices separated by `.`, like `"product.images.0"`.  If a key or index
+/// in `path` is missing, returns `default`, or `null` if it is not set.  A
+/// value that is there but `null` is returned as is.
+///
+/// Raises an error if `path` has an empty segment, like `"a..b"`.
+///
+/// # Examples
+///
+/// - `{ a: { b: [1, 2] } } | get_path(path: "a.b.1")` returns `2`
+/// - `{ a: {} } | get_path(path: "a.b", default: 0)` returns `0`
+/// - `{ a: { b: null } } | get_path(path: "a.b", default: 0)` returns `null`
+#[filter_fn]
+pub fn get_path<'doc>(
+    value: PValue<'doc>,
+    path: Arc<str>,
+    default: Option<EValue<'doc>>,
+) -> Result<PValue<'doc>> {
+    let segments = path_segments(&path)?;
+    Ok(lookup_path(value, &segments).unwrap_or_else(|| default.map_or(Value::Null, PValue::from)))
+}
+
 /// Signature: `value: (String or Int or Float) | int(): Int`
 ///
 /// Turns the value into an `Int`.  If it is a String, it must be a valid
@@ -143,14 +622,17 @@ pub fn take<'doc>(mut value: Structure<'doc>, key: Arc<str>) -> Result<PValue<'d
 /// - `">_<" | int()` raises an error.
 #[filter_fn]
 pub fn int<'doc>(value: PValue<'doc>) -> Result<PValue<'doc>> {
-    let n = match value {
-        Value::Int(n) => n,
-        Value::Float(x) => x as i64,
-        Value::String(s) => s.parse().with_msg(|| format!("`{s}` is not an integer."))?,
-        _ => bail!("expected an int, float, or string"),
-    };
+    to_int(value).map(Value::Int)
+}
 
-    Ok(Value::Int(n))
+/// Converts `value` to an integer, like the `int` filter.
+fn to_int(value: PValue<'_>) -> Result<i64> {
+    match value {
+        Value::Int(n) => Ok(n),
+        Value::Float(x) => Ok(x as i64),
+        Value::String(s) => s.parse().with_msg(|| format!("`{s}` is not an integer.")),
+        _ => bail!("expected an int, float, or string"),
+    }
 }
 
 /// Signature: `value: (String or Int or Float) | float(): Float`
@@ -166,316 +648,5806 @@ pub fn int<'doc>(value: PValue<'doc>) -> Result<PValue<'doc>> {
 /// - `">_<" | float()` raises an error.
 #[filter_fn]
 pub fn float<'doc>(value: PValue<'doc>) -> Result<PValue<'doc>> {
-    let x = match value {
-        Value::Int(n) => n as f64,
-        Value::Float(x) => x,
-        Value::String(s) => s.parse().with_msg(|| format!("`{s}` is not a float."))?,
-        _ => bail!("expected an int, float, or string"),
-    };
-
-    Ok(Value::Float(x))
+    to_float(value).map(Value::Float)
 }
 
-/// Signature: `value: List | nth(i: Int): Value`
-///
-/// Obtains the `i`th element in the list `value`, starting from zero.
-///
-/// Negative indices or indices >= the `length` of the list are invalid arguments
-/// and will raise an error.
-///
-/// # Examples
-///
-/// - `[1, 2, 3, 4] | nth(i: 0)` returns `1`
-/// - `[1, 2, 3, 4] | nth(i: 4)` raises an out-of-bounds error.
-#[filter_fn]
-pub fn nth<'doc>(mut value: ListIter<'doc>, i: i64) -> Result<PValue<'doc>> {
-    match value.nth(
-        i.try_into()
-            .with_msg(|| "negative indices are not supported in `nth({i})`")?,
-    ) {
-        Some(x) => Ok(x),
-        None => bail!("No element at index {i}"),
+/// Converts `value` to a float, like the `float` filter.
+fn to_float(value: PValue<'_>) -> Result<f64> {
+    match value {
+        Value::Int(n) => Ok(n as f64),
+        Value::Float(x) => Ok(x),
+        Value::String(s) => s.parse().with_msg(|| format!("`{s}` is not a float.")),
+        _ => bail!("expected an int, float, or string"),
     }
 }
 
-/// Signature: `value: Structure | keys(): List<String>`
+/// Signature: `value: String | to_number(strip: String?, decimal_sep: String?): (Int or Float)`
 ///
-/// Turns the structure `value` into a list of the *keys* of the structure.
+/// Parses a number written for people to read, like a scraped price.  All
+/// whitespace and each character of `strip` (default: `"$€£¥₹¢,.'_"`, currency
+/// symbols and thousands separators) are removed, except `decimal_sep`
+/// (default: `"."`), which is the decimal point.  The result is a `Float` if it
+/// has a decimal point, and an `Int` otherwise.
 ///
-/// Output is in alphabetical order.
+/// Raises an error if what's left is not a number, or if `decimal_sep` is not
+/// exactly one character.
 ///
 /// # Examples
 ///
-/// - `{a: 1, b: "hello"} | values()` returns `["a", "b"]`
-/// - `{} | values()` returns `[]`
+/// - `"$1,299.00" | to_number()` returns `1299.0`
+/// - `"1.299,00 €" | to_number(decimal_sep: ",")` returns `1299.0`
+/// - `"- 1 299" | to_number()` returns `-1299`
+/// - `"12 kg" | to_number(strip: "kg")` returns `12`
+/// - `"free" | to_number()` raises an error.
 #[filter_fn]
-pub fn keys<'doc>(value: Structure<'doc>) -> Result<PValue<'doc>> {
-    Ok(Value::Extra(Pipeline::ListIter(Box::new(
-        value.into_keys().map(Value::String),
-    ))))
+pub fn to_number<'doc>(
+    value: Arc<str>,
+    strip: Option<Arc<str>>,
+    decimal_sep: Option<Arc<str>>,
+) -> Result<PValue<'doc>> {
+    let strip = strip.as_deref().unwrap_or("$€£¥₹¢,.'_");
+    let decimal_sep = match decimal_sep
+        .as_deref()
+        .map(|x| x.chars().collect::<Vec<_>>())
+    {
+        None => '.',
+        Some(x) if x.len() == 1 => x[0],
+        Some(_) => bail!("`decimal_sep` must be exactly one character"),
+    };
+
+    let number = value
+        .chars()
+        .filter_map(|c| match c {
+            _ if c == decimal_sep => Some('.'),
+            _ if c.is_whitespace() || strip.contains(c) => None,
+            _ => Some(c),
+        })
+        .collect::<String>();
+
+    let parsed = if number.contains('.') {
+        number
+            .parse()
+            .ok()
+            .filter(|x: &f64| x.is_finite())
+            .map(Value::Float)
+    } else {
+        number.parse().ok().map(Value::Int)
+    };
+    parsed.with_msg(|| format!("`{value}` is not a number"))
 }
 
-/// Signature: `value: Structure | values(): List`
+/// Signature: `value: String | parse_duration(): Int`
 ///
-/// Turns the structure `value` into a list of the *values* of the structure.
+/// Parses a duration, like the length of a video or the cooking time of a recipe,
+/// and returns it as a whole number of seconds.  Leading and trailing whitespace
+/// is ignored.  The recognized formats are:
 ///
-/// Output is in alphabetical order by key, duplicates are included.
+/// - `MM:SS` or `HH:MM:SS`, like `"1:30:00"`.  The minutes and seconds after the
+///   first part must be less than 60.
+/// - Numbers with units, like `"1h 30m"` or `"2 hours, 5 min"`.  The units are `d`,
+///   `h`, `m`, and `s`, or words like `day`, `hr`, `hour`, `min`, `minute`, `sec`,
+///   and `second` (and their plurals), in any case.
+/// - [ISO 8601](https://en.wikipedia.org/wiki/ISO_8601#Durations) durations, like
+///   `"PT1H30M"` or `"P1DT2H"`, with weeks, days, hours, minutes, and seconds in
+///   that order.  Years and months are invalid, since they have no fixed length.
+///
+/// Every number must be a whole number.  Raises an error if `value` is not in
+/// one of these formats.
 ///
 /// # Examples
 ///
-/// - `{a: 1, b: "hello"} | values()` returns `[1, "hello"]`
-/// - `{} | values()` returns `[]`
+/// - `"1:30:00" | parse_duration()` returns `5400`
+/// - `"4:05" | parse_duration()` returns `245`
+/// - `"1h 30m" | parse_duration()` returns `5400`
+/// - `"PT1H30M" | parse_duration()` returns `5400`
+/// - `"soon" | parse_duration()` raises an error.
 #[filter_fn]
-pub fn values<'doc>(value: Structure<'doc>) -> Result<PValue<'doc>> {
-    Ok(Value::Extra(Pipeline::ListIter(Box::new(
-        value.into_values(),
-    ))))
+pub fn parse_duration<'doc>(value: Arc<str>) -> Result<PValue<'doc>> {
+    let text = value.trim();
+    let seconds = if let Some(iso) = text.strip_prefix(['P', 'p']) {
+        iso_duration(iso)
+    } else if text.contains(':') {
+        colon_duration(text)
+    } else {
+        unit_duration(text)
+    };
+
+    seconds.map(Value::Int).with_msg(|| {
+        format!(
+            "`{value}` is not a duration (expected `HH:MM:SS`, units like `1h 30m`, or ISO 8601 like `PT1H30M`)"
+        )
+    })
+}
+
+/// Parses a duration like `1:30:00` into seconds, for `parse_duration`.
+fn colon_duration(text: &str) -> Option<i64> {
+    let parts: Vec<_> = text.split(':').collect();
+    if !(2..=3).contains(&parts.len()) {
+        return None;
+    }
+
+    parts
+        .iter()
+        .enumerate()
+        .try_fold(0_i64, |total, (i, part)| {
+            if part.is_empty() || !part.bytes().all(|x| x.is_ascii_digit()) {
+                return None;
+            }
+            let n: i64 = part.parse().ok()?;
+            if i > 0 && n >= 60 {
+                return None;
+            }
+            total.checked_mul(60)?.checked_add(n)
+        })
 }
 
-/// Signature: `value: Bool | and(with: Bool): Bool`
+/// Parses a duration like `1h 30m` into seconds, for `parse_duration`.
+fn unit_duration(text: &str) -> Option<i64> {
+    let mut rest = text;
+    let mut total: Option<i64> = None;
+
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            return None;
+        }
+        let n: i64 = rest[..digits].parse().ok()?;
+        rest = rest[digits..].trim_start();
+
+        let len = rest
+            .find(|c: char| !c.is_alphabetic())
+            .unwrap_or(rest.len());
+        let unit = match rest[..len].to_lowercase().as_str() {
+            "d" | "day" | "days" => 86_400,
+            "h" | "hr" | "hrs" | "hour" | "hours" => 3_600,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60,
+            "s" | "sec" | "secs" | "second" | "seconds" => 1,
+            _ => return None,
+        };
+        total = Some(
+            total
+                .unwrap_or_default()
+                .checked_add(n.checked_mul(unit)?)?,
+        );
+        rest = rest[len..].trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+    }
+
+    total
+}
+
+/// Parses the part of an ISO 8601 duration after the `P`, like `T1H30M`, into
+/// seconds, for `parse_duration`.
+fn iso_duration(text: &str) -> Option<i64> {
+    const DATE: &[(char, i64)] = &[('W', 604_800), ('D', 86_400)];
+    const TIME: &[(char, i64)] = &[('H', 3_600), ('M', 60), ('S', 1)];
+
+    let (date, time) = match text.split_once(['T', 't']) {
+        // `T` must be followed by at least one time part
+        Some((_, "")) => return None,
+        Some((date, time)) => (date, time),
+        None => (text, ""),
+    };
+
+    let mut total: Option<i64> = None;
+    for (mut rest, mut units) in [(date, DATE), (time, TIME)] {
+        while !rest.is_empty() {
+            let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+            if digits == 0 {
+                return None;
+            }
+            let n: i64 = rest[..digits].parse().ok()?;
+
+            let unit = rest[digits..].chars().next()?.to_ascii_uppercase();
+            let i = units.iter().position(|&(x, _)| x == unit)?;
+            total = Some(
+                total
+                    .unwrap_or_default()
+                    .checked_add(n.checked_mul(units[i].1)?)?,
+            );
+
+            // each unit can only come once, and in order
+            units = &units[i + 1..];
+            rest = &rest[digits + 1..];
+        }
+    }
+
+    total
+}
+
+/// Converts each element of `value` with `convert`, dropping the elements that
+/// can't be converted if `skip_invalid` is `true`.
+fn convert_list<'doc>(
+    value: Vec<PValue<'doc>>,
+    skip_invalid: Option<bool>,
+    convert: impl Fn(PValue<'doc>) -> Result<PValue<'doc>>,
+) -> Result<PValue<'doc>> {
+    let converted = value.into_iter().map(convert);
+
+    if skip_invalid.unwrap_or_default() {
+        Ok(Value::List(converted.filter_map(Result::ok).collect()))
+    } else {
+        converted
+            .enumerate()
+            .map(|(i, x)| x.wrap_with(|| format!("note: occurred at list index {i}")))
+            .collect::<Result<_>>()
+            .map(Value::List)
+    }
+}
+
+/// Signature: `value: List<String or Int or Float> | to_int_list(skip_invalid: Bool?): List<Int>`
 ///
-/// Returns the boolean AND of `value` and `with`: `true` if both
-/// (or both) of `value` and `and` are true.
+/// Turns each element of `value` into an `Int`, like [`int`].  If an element
+/// can't be turned into an `Int`, this raises an error, or if `skip_invalid` is
+/// `true`, leaves it out of the returned list.
 ///
 /// # Examples
 ///
-/// - `true | or(with: true)` returns `true`
-/// - `false | or(with: true)` returns `false`
+/// - `["1", 2, 3.5] | to_int_list()` returns `[1, 2, 3]`
+/// - `["1", "two", 3] | to_int_list()` raises an error.
+/// - `["1", "two", 3] | to_int_list(skip_invalid: true)` returns `[1, 3]`
 #[filter_fn]
-pub fn and<'doc>(value: bool, with: bool) -> Result<PValue<'doc>> {
-    Ok(Value::Bool(value && with))
+pub fn to_int_list<'doc>(
+    value: Vec<PValue<'doc>>,
+    skip_invalid: Option<bool>,
+) -> Result<PValue<'doc>> {
+    convert_list(value, skip_invalid, |x| to_int(x).map(Value::Int))
 }
 
-/// Signature: `value: Bool | or(with: Bool): Bool`
+/// Signature: `value: List<String or Int or Float> | to_float_list(skip_invalid: Bool?): List<Float>`
 ///
-/// Returns the boolean OR of `value` and `with`: `true` if either
-/// (or both) of `value` and `and` is true.
+/// Turns each element of `value` into a `Float`, like [`float`].  If an element
+/// can't be turned into a `Float`, this raises an error, or if `skip_invalid` is
+/// `true`, leaves it out of the returned list.
 ///
 /// # Examples
 ///
-/// - `true | or(with: false)` returns `true`
-/// - `false | or(with: false)` returns `false`
+/// - `["1.5", 2, 3.5] | to_float_list()` returns `[1.5, 2.0, 3.5]`
+/// - `["1.5", "two", 3] | to_float_list()` raises an error.
+/// - `["1.5", "two", 3] | to_float_list(skip_invalid: true)` returns `[1.5, 3.0]`
 #[filter_fn]
-pub fn or<'doc>(value: bool, with: bool) -> Result<PValue<'doc>> {
-    Ok(Value::Bool(value || with))
+pub fn to_float_list<'doc>(
+    value: Vec<PValue<'doc>>,
+    skip_invalid: Option<bool>,
+) -> Result<PValue<'doc>> {
+    convert_list(value, skip_invalid, |x| to_float(x).map(Value::Float))
 }
 
-/// Signature: `value: Bool | not(): Bool`
+/// Signature: `value: List<Int or Float> | scale(constant: (Int or Float)?, skip_invalid: Bool?): List<Float>`
+///
+/// Scales the numbers in `value` to between `0` and `1`, so that the smallest
+/// becomes `0.0` and the largest becomes `1.0`, with `(x - min) / (max - min)`.
+/// If all of the numbers are equal, each becomes `constant` (default `0`).
 ///
-/// Returns the boolean NOT (opposite)  of `value`.
+/// An element that is not an `Int` or `Float` raises an error, or if
+/// `skip_invalid` is `true`, is left out of the returned list.
 ///
 /// # Examples
 ///
-/// - `true | not()` returns `false`
-/// - `false | not()` returns `true`
+/// - `[10, 15, 20] | scale()` returns `[0.0, 0.5, 1.0]`
+/// - `[3, 3] | scale(constant: 1)` returns `[1.0, 1.0]`
+/// - `[1, "two", 3] | scale(skip_invalid: true)` returns `[0.0, 1.0]`
 #[filter_fn]
-pub fn not<'doc>(value: bool) -> Result<PValue<'doc>> {
-    Ok(Value::Bool(!value))
-}
-
-/// Signature: `value: String | is_in(on: String?): List<String>`
+pub fn scale<'doc>(
+    value: Vec<PValue<'doc>>,
+    constant: Option<Number>,
+    skip_invalid: Option<bool>,
+) -> Result<PValue<'doc>> {
+    let numbers = value.into_iter().enumerate().map(|(i, x)| {
+        x.try_unwrap::<Number>()
+            .map(Number::cast_to_float)
+            .wrap_with(|| format!("note: occurred at list index {i}"))
+    });
+    let numbers: Vec<f64> = if skip_invalid.unwrap_or_default() {
+        numbers.filter_map(Result::ok).collect()
+    } else {
+        numbers.collect::<Result<_>>()?
+    };
+
+    let min = numbers.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let constant = constant.map_or(0.0, Number::cast_to_float);
+
+    Ok(Value::List(
+        numbers
+            .into_iter()
+            .map(|x| {
+                Value::Float(if max > min {
+                    (x - min) / (max - min)
+                } else {
+                    constant
+                })
+            })
+            .collect(),
+    ))
+}
+
+/// Signature: `value: Number | number_format(decimals: Int?, thousands_sep: String?, decimal_sep: String?): String`
 ///
-/// Splits a `String` into a `List` of substrings, on the specified delimiter `on`
-/// or over whitespace if `on` is not set.
+/// Formats `value` for people to read, with `thousands_sep` (default `","`)
+/// between each group of three digits before the decimal point, and
+/// `decimal_sep` (default `"."`) as the decimal point.
+///
+/// `value` is rounded to `decimals` digits after the decimal point.  If
+/// `decimals` is not set, `Int`s have none and `Float`s have as many as they
+/// need.
+///
+/// Raises an error if `decimals` is negative.
 ///
 /// # Examples
 ///
-/// - `"my very excellent mother" | split()` returns `["my", "very", "excellent", "mother"]`
-/// - `"my very excellent mother" | split(on: "excellent") returns `["my very ", " mother"]
-/// - `"my very excellent mother" | split(on: "HAMPSTERS") returns ["my very excellent mother"]
+/// - `1234567 | number_format()` returns `"1,234,567"`
+/// - `-1234.567 | number_format(decimals: 2)` returns `"-1,234.57"`
+/// - `1234.5 | number_format(decimals: 2, thousands_sep: ".", decimal_sep: ",")`
+///   returns `"1.234,50"`
 #[filter_fn]
-pub fn split<'doc>(value: Arc<str>, on: Option<Arc<str>>) -> Result<PValue<'doc>> {
-    if let Some(delim) = on {
-        Ok(Value::List(
-            value
-                .split(&*delim)
-                .map(|x| Value::String(Arc::from(x)))
-                .collect(),
-        ))
-    } else {
-        Ok(Value::List(
-            value
-                .split_whitespace()
-                .map(|x| Value::String(Arc::from(x)))
-                .collect(),
-        ))
+pub fn number_format<'doc>(
+    value: Number,
+    decimals: Option<i64>,
+    thousands_sep: Option<Arc<str>>,
+    decimal_sep: Option<Arc<str>>,
+) -> Result<PValue<'doc>> {
+    let decimals = decimals
+        .map(|x| usize::try_from(x).msg("`decimals` must not be negative"))
+        .transpose()?;
+
+    let (negative, digits) = match (value, decimals) {
+        (Number::Int(n), None) => (n < 0, n.unsigned_abs().to_string()),
+        (Number::Int(n), Some(decimals)) => (
+            n < 0,
+            format!("{}.{}", n.unsigned_abs(), "0".repeat(decimals)),
+        ),
+        (Number::Float(x), None) => (x < 0., x.abs().to_string()),
+        (Number::Float(x), Some(decimals)) => (x < 0., format!("{:.decimals$}", x.abs())),
+    };
+
+    let (whole, fraction) = match digits.split_once('.') {
+        Some((whole, fraction)) if !fraction.is_empty() => (whole, Some(fraction)),
+        Some((whole, _)) => (whole, None),
+        None => (digits.as_str(), None),
+    };
+
+    let thousands_sep = thousands_sep.as_deref().unwrap_or(",");
+    let mut formatted = String::new();
+    // `-0.00` would look odd, so only keep the sign if a digit isn't zero
+    if negative && digits.bytes().any(|x| matches!(x, b'1'..=b'9')) {
+        formatted.push('-');
+    }
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            formatted.push_str(thousands_sep);
+        }
+        formatted.push(digit);
+    }
+    if let Some(fraction) = fraction {
+        formatted.push_str(decimal_sep.as_deref().unwrap_or("."));
+        formatted.push_str(fraction);
     }
+
+    Ok(Value::String(formatted.into()))
 }
 
-/// Signature: `value | eq(to: Value): Bool`
+/// Signature: `value: Int | idiv(n: Int): Int`
 ///
-/// Takes a two values `value` and `to`, and returns whether `value` equal to `to`.
+/// Divides `value` by `n`, rounding down (towards negative infinity), so the
+/// result for negative numbers is one less than with plain truncation when
+/// there is a remainder.  Together with [`mod`](modulo), `value` is always
+/// equal to `value | idiv(n: n)` times `n` plus `value | mod(n: n)`.
+///
+/// Dividing by zero, or a result that does not fit in an `Int`, raises an error.
 ///
 /// # Examples
 ///
-/// - `2 | eq(to: 2)` is `true`
-/// - `6 | eq(to: 2)` is `false`.
+/// - `7 | idiv(n: 2)` returns `3`
+/// - `-7 | idiv(n: 2)` returns `-4`
+/// - `7 | idiv(n: -2)` returns `-4`
+/// - `7 | idiv(n: 0)` raises an error.
 #[filter_fn]
-pub fn eq<'doc>(value: PValue<'doc>, to: EValue<'doc>) -> Result<PValue<'doc>> {
-    Ok(Value::Bool(EValue::from(value) == to))
+pub fn idiv<'doc>(value: i64, n: i64) -> Result<PValue<'doc>> {
+    if n == 0 {
+        bail!("`idiv` by zero");
+    }
+    let quotient = value.checked_div(n).msg("integer overflow in `idiv`")?;
+
+    // `/` truncates towards zero, so round down if the signs differ and
+    // there is a remainder
+    let floor = if value % n != 0 && (value < 0) != (n < 0) {
+        quotient - 1
+    } else {
+        quotient
+    };
+
+    Ok(Value::Int(floor))
 }
 
-/// Signature: `value | is_in(list: List): Bool`
+/// Signature: `value: Int | mod(n: Int): Int`
 ///
-/// Takes a value and a List `list`, and returns whether `value` is in the `List`
+/// Returns the remainder of dividing `value` by `n` with [`idiv`].  The result
+/// is zero or has the same sign as `n` (like `%` in Python, not like `%` in
+/// Rust or C), so `value | mod(n: 10)` is always between `0` and `9`.
+///
+/// Dividing by zero raises an error.
 ///
 /// # Examples
 ///
-/// Let `list: [1, 2, 3, 4, 5];`:
+/// - `7 | mod(n: 3)` returns `1`
+/// - `-7 | mod(n: 3)` returns `2`
+/// - `7 | mod(n: -3)` returns `-2`
+/// - `7 | mod(n: 0)` raises an error.
+#[filter_fn]
+pub fn modulo<'doc>(value: i64, n: i64) -> Result<PValue<'doc>> {
+    if n == 0 {
+        bail!("`mod` by zero");
+    }
+    // `i64::MIN % -1` overflows in Rust, but the remainder is always zero
+    let remainder = value.checked_rem(n).unwrap_or(0);
+
+    let floor = if remainder != 0 && (remainder < 0) != (n < 0) {
+        remainder + n
+    } else {
+        remainder
+    };
+
+    Ok(Value::Int(floor))
+}
+
+/// Signature: `value | range(start: Int?, end: Int, step: Int?): List<Int>`
 ///
-/// Then `2 | is_in(list: $list)` is `true`, but `6 | is_in(list: $list)` is `false.
+/// Returns the `Int`s from `start` up to, but not including, `end`, counting by
+/// `step`.  If `start` is not set, `value` is used as the start if it is an
+/// `Int`, and otherwise the range starts at `0`.  If `step` is not set, it is
+/// `1`, or `-1` if `end` is less than `start`, so the range counts down.
+///
+/// If `step` goes in the opposite direction from `start` to `end`, or `start`
+/// equals `end`, the range is empty.  A `step` of zero is invalid and will
+/// raise an error.
+///
+/// # Examples
+///
+/// - `"" | range(end: 3)` returns `[0, 1, 2]`
+/// - `1 | range(end: 4)` returns `[1, 2, 3]`
+/// - `"" | range(start: 1, end: 10, step: 4)` returns `[1, 5, 9]`
+/// - `"" | range(start: 3, end: 0)` returns `[3, 2, 1]`
+/// - `"" | range(start: 0, end: 3, step: -1)` returns `[]`
 #[filter_fn]
-pub fn is_in<'doc>(value: PValue<'doc>, list: Vec<EValue<'doc>>) -> Result<PValue<'doc>> {
-    Ok(Value::Bool(list.contains(&value.into())))
+pub fn range<'doc>(
+    value: PValue<'doc>,
+    start: Option<i64>,
+    end: i64,
+    step: Option<i64>,
+) -> Result<PValue<'doc>> {
+    let start = match (start, value) {
+        (Some(start), _) | (None, Value::Int(start)) => start,
+        (None, _) => 0,
+    };
+    let step = step.unwrap_or(if end < start { -1 } else { 1 });
+
+    if step == 0 {
+        bail!("`range` step must be nonzero");
+    }
+
+    let mut list = Vec::new();
+    let mut i = Some(start);
+    while let Some(x) = i.filter(|&x| if step > 0 { x < end } else { x > end }) {
+        list.push(Value::Int(x));
+        // stop instead of overflowing if the range ends near the limits of `Int`
+        i = x.checked_add(step);
+    }
+
+    Ok(Value::List(list))
 }
 
-/// Signature: `value | truthy(): Bool`
+/// Signature: `value: List | nth(i: Int): Value`
 ///
-/// Takes a `value` and converts it into a `Bool`, based on whether it is "truthy".
+/// Obtains the `i`th element in the list `value`, starting from zero.
 ///
-/// Rules:
+/// Negative indices or indices >= the `length` of the list are invalid arguments
+/// and will raise an error.
 ///
-/// - `Null` is `false`
-/// - `Int`s and `Float`s are `true` if they are nonzero.
-/// - `String`s, `List`s, and `Structure`s are `true` if they are nonempty
-/// - `Element`s are `true`
-/// - `Bool`s are the boolean value
+/// # Examples
+///
+/// - `[1, 2, 3, 4] | nth(i: 0)` returns `1`
+/// - `[1, 2, 3, 4] | nth(i: 4)` raises an out-of-bounds error.
+#[filter_fn]
+pub fn nth<'doc>(mut value: ListIter<'doc>, i: i64) -> Result<PValue<'doc>> {
+    match value.nth(
+        i.try_into()
+            .with_msg(|| "negative indices are not supported in `nth({i})`")?,
+    ) {
+        Some(x) => Ok(x),
+        None => bail!("No element at index {i}"),
+    }
+}
+
+/// Signature: `value: List | one(): Value?`
+///
+/// Returns the first element of the list `value`, or `null` if the list is empty.
+///
+/// Unlike `nth(i: 0)`, which raises an error on an empty list, this is for
+/// values that may be missing: use `one()` when no match is expected sometimes,
+/// and `nth(i: 0)` when an empty list is a mistake that should stop the program.
+/// For element blocks, the `?` qualifier (like `h1 { ... }?`) does the same.
 ///
 /// # Examples
 ///
-/// - `"" | truthy()` is `false`
-/// - `"hi!" | truthy()` is `true`
-/// - `0 | truthy()` is `false`
-/// - `0.6931 | truthy()` is `true`
-/// - `null | truthy()` is `false`
-/// - `[null] | truthy()` is `true` (a List containing `null` is nonempty)
-/// - `true | truthy()` is `true`
+/// - `[1, 2, 3] | one()` returns `1`
+/// - `[] | one()` returns `null`, but `[] | nth(i: 0)` raises an error
 #[filter_fn]
-pub fn truthy<'doc>(value: PValue<'doc>) -> Result<PValue<'doc>> {
-    let truthy = match value {
-        Value::Null => false,
-        Value::Float(f) => f != 0.,
-        Value::Int(i) => i != 0,
-        Value::Bool(b) => b,
-        Value::String(s) => !s.is_empty(),
-        Value::List(l) => !l.is_empty(),
-        Value::Structure(s) => !s.is_empty(),
-        Value::Extra(Pipeline::Element(_)) => true,
-        Value::Extra(Pipeline::ListIter(mut i)) => i.next().is_some(),
-        Value::Extra(Pipeline::StructIter(mut i)) => i.next().is_some(),
-        Value::Extra(_) => unreachable!(),
-    };
+pub fn one<'doc>(mut value: ListIter<'doc>) -> Result<PValue<'doc>> {
+    Ok(value.next().unwrap_or(Value::Null))
+}
 
-    Ok(Value::Bool(truthy))
+/// Converts the count `n` of a list-limiting filter to a `usize`, clamped to
+/// the length of the list.
+fn clamp_count(n: i64, len: usize) -> Result<usize> {
+    let n = usize::try_from(n).msg("the count `n` must not be negative")?;
+    Ok(n.min(len))
 }
 
-/// Signature: `value: Element | text(): String`
+/// Signature: `value: List | head(n: Int): List`
 ///
-/// Returns the text contained inside this element.  Note that this is just
-/// direct text, not the text of any descendent elements.
+/// Returns the first `n` elements of `value`, or all of them if it has fewer
+/// than `n` elements.
+///
+/// A negative `n` is invalid and will raise an error.
 ///
 /// # Examples
 ///
-/// -  Let `element` = `<div>Hello...<span>inner</span>...world!</div>`
-///   - Then `$element | text()` is `"Hello......world!"`
-/// - `<img /> | text()` is `""`
+/// - `[1, 2, 3] | head(n: 2)` returns `[1, 2]`
+/// - `[1, 2, 3] | head(n: 5)` returns `[1, 2, 3]`
 #[filter_fn]
-pub fn text<'doc>(value: scraper::ElementRef<'doc>) -> Result<PValue<'doc>> {
-    Ok(Value::String(
+pub fn head<'doc>(mut value: Vec<PValue<'doc>>, n: i64) -> Result<PValue<'doc>> {
+    value.truncate(clamp_count(n, value.len())?);
+    Ok(Value::List(value))
+}
+
+/// Signature: `value: List | tail(n: Int): List`
+///
+/// Returns the last `n` elements of `value`, or all of them if it has fewer
+/// than `n` elements.
+///
+/// A negative `n` is invalid and will raise an error.
+///
+/// # Examples
+///
+/// - `[1, 2, 3] | tail(n: 2)` returns `[2, 3]`
+/// - `[1, 2, 3] | tail(n: 5)` returns `[1, 2, 3]`
+#[filter_fn]
+pub fn tail<'doc>(mut value: Vec<PValue<'doc>>, n: i64) -> Result<PValue<'doc>> {
+    let n = clamp_count(n, value.len())?;
+    Ok(Value::List(value.split_off(value.len() - n)))
+}
+
+/// Signature: `value: List | sample(n: Int, seed: Int?): List`
+///
+/// Returns `n` elements of `value` chosen at random, in the order that they
+/// are in `value`, or all of them if it has fewer than `n` elements.  If
+/// `seed` is set, the same elements are chosen every time for the same
+/// `value`, `n`, and `seed`.  Otherwise, if the run was started with `--seed`,
+/// the choices come from that seed and where the call is in the program and
+/// pages, so runs with the same `--seed` on the same pages choose the same
+/// elements, whichever pages load first.
+///
+/// A negative `n` is invalid and will raise an error.
+///
+/// # Examples
+///
+/// - `[1, 2, 3, 4] | sample(n: 2)` returns two of the elements, like `[1, 4]`
+/// - `[1, 2, 3] | sample(n: 5)` returns `[1, 2, 3]`
+#[filter_fn(impure)]
+pub fn sample<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
+    value: Vec<PValue<'doc>>,
+    n: i64,
+    seed: Option<i64>,
+    ctx: &mut E,
+) -> Result<PValue<'doc>> {
+    use rand::{rngs::StdRng, SeedableRng as _};
+
+    let n = clamp_count(n, value.len())?;
+    let mut rng = match seed.map(i64::cast_unsigned).or_else(|| ctx.next_seed()) {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let mut chosen = rand::seq::index::sample(&mut rng, value.len(), n).into_vec();
+    chosen.sort_unstable();
+
+    let mut chosen = chosen.into_iter().peekable();
+    Ok(Value::List(
         value
-            .children()
-            .filter_map(|x| x.value().as_text().map(|text| &*text.text))
-            .collect::<String>()
-            .into(),
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, x)| chosen.next_if_eq(&i).map(|_| x))
+            .collect(),
     ))
 }
 
-/// Signature: `value: Number | add(to: Number): Number`
+/// Signature: `value: List | zip_with_keys(keys: List<String>, strict: Bool?): Structure`
 ///
-/// Adds the two numbers together.  The two numbers must be the same
-/// type (Float or Int), and the return type will also be that type.
+/// Turns the list `value` into a structure, with the `i`th element of `value`
+/// at the `i`th key in `keys`.  If a key is repeated, the last element paired
+/// with it is kept.
+///
+/// If `value` and `keys` have different lengths, the extra elements or keys are
+/// dropped, unless `strict` is `true`, when this raises an error instead.  Keys
+/// that are not `String`s will raise an error.
 ///
 /// # Examples
-/// - `1 | add(to: 2)` returns `3`
-/// - `0.5 | add(to: 1.5)` returns `2.0`
-/// - `1 | add(to: 1.5)` raises an error because of the type mismatch.
+///
+/// - `["Kitty", 3] | zip_with_keys(keys: ["name", "age"])` returns
+///   `{ name: "Kitty", age: 3 }`
+/// - `["Kitty", 3, "extra"] | zip_with_keys(keys: ["name", "age"])` returns
+///   `{ name: "Kitty", age: 3 }`
+/// - `["Kitty"] | zip_with_keys(keys: ["name", "age"], strict: true)` raises an
+///   error.
 #[filter_fn]
-pub fn add<'doc>(value: Number, to: Number) -> Result<PValue<'doc>> {
-    match (value, to) {
-        (Number::Int(a), Number::Int(b)) => Ok(Value::Int(a + b)),
-        (Number::Float(a), Number::Float(b)) => Ok(Value::Float(a + b)),
-        (a, b) => bail!("Type mismatch when adding {a:?} + {b:?}"),
+pub fn zip_with_keys<'doc>(
+    value: Vec<PValue<'doc>>,
+    keys: Vec<EValue<'doc>>,
+    strict: Option<bool>,
+) -> Result<PValue<'doc>> {
+    if strict.unwrap_or_default() && value.len() != keys.len() {
+        bail!(
+            "`zip_with_keys` got {} values but {} keys",
+            value.len(),
+            keys.len()
+        );
     }
+
+    keys.into_iter()
+        .zip(value)
+        .map(|(key, value)| Ok((key.try_unwrap::<Arc<str>>()?, value)))
+        .collect::<Result<_>>()
+        .map(Value::Structure)
 }
 
-/// Signature: `value: Number | sub(by: Number): Number`
+/// Signature: `value: List<Structure> | transpose(): Structure<List>`, or
+/// `value: List<List> | transpose(): List<List>`
 ///
-/// Subtracts `by` from `value`. The two numbers must be the same
-/// type (Float or Int), and the return type will also be that type.
+/// Turns a list of rows into columns.  For a list of structures, returns a
+/// structure with each key of any of the structures, mapped to the list of the
+/// values at that key in each structure, in order.  For a list of lists, returns
+/// a list with the `i`th elements of each list in the `i`th list.
+///
+/// A structure missing a key (or a list that is shorter than the others) has
+/// `null` in its place in the columns.  A list that mixes structures and lists
+/// will raise an error.  An empty list returns `[]`.
 ///
 /// # Examples
-/// - `1 | sub(by: 2)` returns `-1`
-/// - `0.5 | sub(by: 1.5)` returns `-1.0`
-/// - `1 | sub(by: 1.5)` raises an error because of the type mismatch.
+///
+/// - `[{ a: 1, b: 2 }, { a: 3 }] | transpose()` returns `{ a: [1, 3], b: [2, null] }`
+/// - `[[1, 2], [3, 4], [5, 6]] | transpose()` returns `[[1, 3, 5], [2, 4, 6]]`
 #[filter_fn]
-pub fn sub<'doc>(value: Number, by: Number) -> Result<PValue<'doc>> {
-    match (value, by) {
-        (Number::Int(a), Number::Int(b)) => Ok(Value::Int(a - b)),
-        (Number::Float(a), Number::Float(b)) => Ok(Value::Float(a - b)),
-        (a, b) => bail!("Type mismatch when subtracting {a:?} - {b:?}"),
+pub fn transpose<'doc>(value: Vec<PValue<'doc>>) -> Result<PValue<'doc>> {
+    let Some(first) = value.first() else {
+        return Ok(Value::List(Vec::new()));
+    };
+
+    if matches!(
+        first,
+        Value::Structure(_) | Value::Extra(Pipeline::StructIter(_))
+    ) {
+        let mut rows = value
+            .into_iter()
+            .map(Value::try_unwrap::<Structure<'doc>>)
+            .collect::<Result<Vec<_>>>()?;
+        let keys: BTreeSet<_> = rows.iter().flat_map(|row| row.keys().cloned()).collect();
+
+        Ok(Value::Structure(
+            keys.into_iter()
+                .map(|key| {
+                    let column = rows
+                        .iter_mut()
+                        .map(|row| row.remove(&key).unwrap_or(Value::Null))
+                        .collect();
+                    (key, Value::List(column))
+                })
+                .collect(),
+        ))
+    } else {
+        let mut rows = value
+            .into_iter()
+            .map(|row| row.try_unwrap::<Vec<PValue<'doc>>>().map(Vec::into_iter))
+            .collect::<Result<Vec<_>>>()?;
+        let width = rows.iter().map(ExactSizeIterator::len).max().unwrap_or(0);
+
+        Ok(Value::List(
+            (0..width)
+                .map(|_| {
+                    Value::List(
+                        rows.iter_mut()
+                            .map(|row| row.next().unwrap_or(Value::Null))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        ))
     }
 }
 
-/// Signature: `value: Number | mult(by: Number): Number`
+/// Signature: `value: List<List> | unzip(): List<List>`
 ///
-/// Multiplies `value * by`. The two numbers must be the same
-/// type (Float or Int), and the return type will also be that type.
+/// Splits a list of pairs into two lists: the first element of each pair, and
+/// the second element of each pair, in order.  An empty list returns `[[], []]`.
+///
+/// Raises an error if an element of `value` is not a list with two elements.
 ///
 /// # Examples
-/// - `1 | mult(by: 2)` returns `2`
-/// - `0.5 | mult(by: 1.5)` returns `0.75``
-/// - `1 | mult(by: 1.5)` raises an error because of the type mismatch.
+///
+/// - `[["a", 1], ["b", 2]] | unzip()` returns `[["a", "b"], [1, 2]]`
+/// - `[["a", 1], ["b"]] | unzip()` raises an error
 #[filter_fn]
-pub fn mult<'doc>(value: Number, by: Number) -> Result<PValue<'doc>> {
-    match (value, by) {
-        (Number::Int(a), Number::Int(b)) => Ok(Value::Int(a * b)),
-        (Number::Float(a), Number::Float(b)) => Ok(Value::Float(a * b)),
-        (a, b) => bail!("Type mismatch when subtracting {a:?} * {b:?}"),
+pub fn unzip<'doc>(value: Vec<PValue<'doc>>) -> Result<PValue<'doc>> {
+    let mut firsts = Vec::with_capacity(value.len());
+    let mut seconds = Vec::with_capacity(value.len());
+
+    for (i, pair) in value.into_iter().enumerate() {
+        let pair: Vec<PValue<'doc>> = pair
+            .try_unwrap()
+            .wrap_with(|| format!("note: occurred at list index {i}"))?;
+        let Ok([first, second]) = <[_; 2]>::try_from(pair) else {
+            bail!("expected a list of two elements at list index {i}");
+        };
+        firsts.push(first);
+        seconds.push(second);
     }
+
+    Ok(Value::List(vec![Value::List(firsts), Value::List(seconds)]))
 }
 
-macro_rules! build_map {
-    ($(
-        $id: ident,
-    )*) => {
-        [$(
-            (stringify!($id), Box::new($id()) as Box<dyn FilterDyn + Send + Sync>),
+/// Signature: `value: (List or Structure) | fill_null(with: Value, deep: Bool?): (List or Structure)`
+///
+/// Replaces each `null` element of the list `value`, or each `null` value of
+/// the structure `value`, with `with`.  If `deep` is `true`, `null`s in nested
+/// lists and structures are replaced too.
+///
+/// Raises an error if `value` is not a list or structure.
+///
+/// # Examples
+///
+/// - `[1, null, 3] | fill_null(with: 0)` returns `[1, 0, 3]`
+/// - `{ a: null, b: { c: null } } | fill_null(with: "")` returns `{ a: "", b: { c: null } }`
+/// - `{ a: null, b: { c: null } } | fill_null(with: "", deep: true)` returns
+///   `{ a: "", b: { c: "" } }`
+#[filter_fn]
+pub fn fill_null<'doc>(
+    value: PValue<'doc>,
+    with: EValue<'doc>,
+    deep: Option<bool>,
+) -> Result<PValue<'doc>> {
+    let deep = deep.unwrap_or_default();
 
-        )*]
-    };
+    if matches!(
+        value,
+        Value::Structure(_) | Value::Extra(Pipeline::StructIter(_))
+    ) {
+        let value: Structure<'doc> = value.try_unwrap()?;
+        Ok(fill_nulls(Value::Structure(value), &with, deep, true))
+    } else {
+        let value: Vec<PValue<'doc>> = value.try_unwrap()?;
+        Ok(fill_nulls(Value::List(value), &with, deep, true))
+    }
 }
 
-pub static FILTERS: LazyLock<BTreeMap<&'static str, Box<dyn FilterDyn + Send + Sync>>> =
-    LazyLock::new(|| {
-        build_map! {
-            dbg,
-            tee,
-            strip,
-            take,
-            attrs,
-            int,
-            float,
-            nth,
-            keys,
-            values,
-            and,
-            or,
-            not,
-            split,
-            eq,
-            is_in,
+/// Replaces the `null`s in `value` with `with`, going into nested lists and
+/// structures if `deep` or this is the top level.
+fn fill_nulls<'doc>(
+    value: PValue<'doc>,
+    with: &EValue<'doc>,
+    deep: bool,
+    top: bool,
+) -> PValue<'doc> {
+    match value {
+        Value::Null => with.clone().into(),
+        Value::List(list) if deep || top => Value::List(
+            list.into_iter()
+                .map(|x| fill_nulls(x, with, deep, false))
+                .collect(),
+        ),
+        Value::Structure(structure) if deep || top => Value::Structure(
+            structure
+                .into_iter()
+                .map(|(k, x)| (k, fill_nulls(x, with, deep, false)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+/// Signature: `value: String | lines(): List<String>`
+///
+/// Splits a `String` into its lines, on `\n` or `\r\n`.  A newline at the very
+/// end of `value` doesn't start another line, so there is no empty last line,
+/// but empty lines in the middle are kept.
+///
+/// # Examples
+///
+/// - `"a\nb\n\nc\n" | lines()` returns `["a", "b", "", "c"]`, and so would the
+///   same text with `\r\n` line endings
+/// - `"" | lines()` returns `[]`
+#[filter_fn]
+pub fn lines<'doc>(value: Arc<str>) -> Result<PValue<'doc>> {
+    Ok(Value::List(
+        value.lines().map(|x| Value::String(Arc::from(x))).collect(),
+    ))
+}
+
+/// Signature: `value: String | dedent(): String`
+///
+/// Removes the longest run of leading whitespace that every line of `value`
+/// starts with, keeping the indentation of the lines relative to each other,
+/// like for the text of a `<pre>` block.
+///
+/// Tabs and spaces are compared literally, so a line indented with a tab and a
+/// line indented with spaces have no indentation in common.  Lines that are
+/// empty or only whitespace don't count towards the common indentation, and
+/// become empty.
+///
+/// # Examples
+///
+/// - `"    a\n      b\n\n    c" | dedent()` returns `"a\n  b\n\nc"`
+/// - A line indented with a tab followed by a line indented with four spaces is
+///   returned unchanged
+#[filter_fn]
+pub fn dedent<'doc>(value: Arc<str>) -> Result<PValue<'doc>> {
+    let is_blank = |line: &str| line.trim().is_empty();
+
+    let common = value
+        .split('\n')
+        .filter(|line| !is_blank(line))
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .reduce(|common, indent| {
+            let len = common
+                .chars()
+                .zip(indent.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a.len_utf8())
+                .sum();
+            &common[..len]
+        })
+        .unwrap_or_default();
+
+    Ok(Value::String(
+        value
+            .split('\n')
+            .map(|line| {
+                if is_blank(line) {
+                    ""
+                } else {
+                    &line[common.len()..]
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+            .into(),
+    ))
+}
+
+/// Signature: `value: List<String> | unlines(): String`
+///
+/// Joins a `List` of `String`s into one `String`, with a `\n` between each
+/// one.  There is no newline after the last item, so this undoes `lines`.
+///
+/// Raises an error if an item of `value` is not a `String`.
+///
+/// # Examples
+///
+/// - `["a", "b", "c"] | unlines()` returns `"a\nb\nc"`
+/// - `[] | unlines()` returns `""`
+#[filter_fn]
+pub fn unlines<'doc>(value: Vec<PValue<'doc>>) -> Result<PValue<'doc>> {
+    let lines = value
+        .into_iter()
+        .map(|x| x.try_unwrap::<Arc<str>>())
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Value::String(lines.join("\n").into()))
+}
+
+/// Signature: `value: Structure | keys(): List<String>`
+///
+/// Turns the structure `value` into a list of the *keys* of the structure.
+///
+/// Output is in alphabetical order.
+///
+/// # Examples
+///
+/// - `{a: 1, b: "hello"} | values()` returns `["a", "b"]`
+/// - `{} | values()` returns `[]`
+#[filter_fn]
+pub fn keys<'doc>(value: Structure<'doc>) -> Result<PValue<'doc>> {
+    Ok(Value::Extra(Pipeline::ListIter(Box::new(
+        value.into_keys().map(Value::String),
+    ))))
+}
+
+/// Signature: `value: Structure | values(): List`
+///
+/// Turns the structure `value` into a list of the *values* of the structure.
+///
+/// Output is in alphabetical order by key, duplicates are included.
+///
+/// # Examples
+///
+/// - `{a: 1, b: "hello"} | values()` returns `[1, "hello"]`
+/// - `{} | values()` returns `[]`
+#[filter_fn]
+pub fn values<'doc>(value: Structure<'doc>) -> Result<PValue<'doc>> {
+    Ok(Value::Extra(Pipeline::ListIter(Box::new(
+        value.into_values(),
+    ))))
+}
+
+/// Signature: `value | and(with: Value): Bool`
+///
+/// Returns the boolean AND of `value` and `with`: `true` if both of them are
+/// true.  Values that aren't `Bool`s count as their truthiness (see [`truthy`]).
+///
+/// This doesn't short-circuit: both `value` and `with` are always evaluated
+/// before `and` is called, even if `value` is false.
+///
+/// # Examples
+///
+/// - `true | and(with: true)` returns `true`
+/// - `false | and(with: true)` returns `false`
+/// - `"hi" | and(with: [])` returns `false`
+#[filter_fn]
+pub fn and<'doc>(value: PValue<'doc>, with: EValue<'doc>) -> Result<PValue<'doc>> {
+    Ok(Value::Bool(is_truthy(value) && is_truthy(with.into())))
+}
+
+/// Signature: `value | or(with: Value): Bool`
+///
+/// Returns the boolean OR of `value` and `with`: `true` if either (or both) of
+/// them is true.  Values that aren't `Bool`s count as their truthiness (see
+/// [`truthy`]).
+///
+/// Like `and`, this doesn't short-circuit: `with` is always evaluated, even if
+/// `value` is true.
+///
+/// # Examples
+///
+/// - `true | or(with: false)` returns `true`
+/// - `false | or(with: false)` returns `false`
+/// - `"" | or(with: 1)` returns `true`
+#[filter_fn]
+pub fn or<'doc>(value: PValue<'doc>, with: EValue<'doc>) -> Result<PValue<'doc>> {
+    Ok(Value::Bool(is_truthy(value) || is_truthy(with.into())))
+}
+
+/// Signature: `value | not(): Bool`
+///
+/// Returns the boolean NOT (opposite) of `value`, or for a value that isn't a
+/// `Bool`, the opposite of whether it is truthy (see [`truthy`]).
+///
+/// # Examples
+///
+/// - `true | not()` returns `false`
+/// - `false | not()` returns `true`
+/// - `"" | not()` returns `true`
+/// - `[1] | not()` returns `false`
+#[filter_fn]
+pub fn not<'doc>(value: PValue<'doc>) -> Result<PValue<'doc>> {
+    Ok(Value::Bool(!is_truthy(value)))
+}
+
+/// Signature: `value: String | is_in(on: String?): List<String>`
+///
+/// Splits a `String` into a `List` of substrings, on the specified delimiter `on`
+/// or over whitespace if `on` is not set.
+///
+/// # Examples
+///
+/// - `"my very excellent mother" | split()` returns `["my", "very", "excellent", "mother"]`
+/// - `"my very excellent mother" | split(on: "excellent") returns `["my very ", " mother"]
+/// - `"my very excellent mother" | split(on: "HAMPSTERS") returns ["my very excellent mother"]
+#[filter_fn]
+pub fn split<'doc>(value: Arc<str>, on: Option<Arc<str>>) -> Result<PValue<'doc>> {
+    if let Some(delim) = on {
+        Ok(Value::List(
+            value
+                .split(&*delim)
+                .map(|x| Value::String(Arc::from(x)))
+                .collect(),
+        ))
+    } else {
+        Ok(Value::List(
+            value
+                .split_whitespace()
+                .map(|x| Value::String(Arc::from(x)))
+                .collect(),
+        ))
+    }
+}
+
+/// Signature: `value: String | split_once(sep: String, required: Bool?): List<String?>`
+///
+/// Splits `value` on the first occurrence of `sep`, returning the `List`
+/// `[before, after]`.  If `sep` is not in `value`, returns `[value, null]`, or
+/// raises an error if `required` is `true`.
+///
+/// An empty `sep` is invalid and will raise an error.
+///
+/// # Examples
+///
+/// - `"color: red: dark" | split_once(sep: ": ")` returns `["color", "red: dark"]`
+/// - `"red" | split_once(sep: ": ")` returns `["red", null]`
+/// - `"red" | split_once(sep: ": ", required: true)` raises an error
+#[filter_fn]
+pub fn split_once<'doc>(
+    value: Arc<str>,
+    sep: Arc<str>,
+    required: Option<bool>,
+) -> Result<PValue<'doc>> {
+    if sep.is_empty() {
+        bail!("`split_once` needs a nonempty separator");
+    }
+
+    let (before, after) = match value.split_once(&*sep) {
+        Some((before, after)) => (Arc::from(before), Value::String(Arc::from(after))),
+        None if required.unwrap_or_default() => bail!("`{value}` does not contain `{sep}`"),
+        None => (value, Value::Null),
+    };
+
+    Ok(Value::List(vec![Value::String(before), after]))
+}
+
+/// Signature: `value | eq(to: Value): Bool`
+///
+/// Takes a two values `value` and `to`, and returns whether `value` equal to `to`.
+///
+/// # Examples
+///
+/// - `2 | eq(to: 2)` is `true`
+/// - `6 | eq(to: 2)` is `false`.
+#[filter_fn]
+pub fn eq<'doc>(value: PValue<'doc>, to: EValue<'doc>) -> Result<PValue<'doc>> {
+    Ok(Value::Bool(EValue::from(value) == to))
+}
+
+/// Signature: `value | is_in(list: List): Bool`
+///
+/// Takes a value and a List `list`, and returns whether `value` is in the `List`
+///
+/// # Examples
+///
+/// Let `list: [1, 2, 3, 4, 5];`:
+///
+/// Then `2 | is_in(list: $list)` is `true`, but `6 | is_in(list: $list)` is `false.
+#[filter_fn]
+pub fn is_in<'doc>(value: PValue<'doc>, list: Vec<EValue<'doc>>) -> Result<PValue<'doc>> {
+    Ok(Value::Bool(list.contains(&value.into())))
+}
+
+/// Signature: `value | truthy(): Bool`
+///
+/// Takes a `value` and converts it into a `Bool`, based on whether it is "truthy".
+///
+/// Rules:
+///
+/// - `Null` is `false`
+/// - `Int`s and `Float`s are `true` if they are nonzero.
+/// - `String`s, `List`s, and `Structure`s are `true` if they are nonempty
+/// - `Element`s are `true`
+/// - `Bool`s are the boolean value
+///
+/// # Examples
+///
+/// - `"" | truthy()` is `false`
+/// - `"hi!" | truthy()` is `true`
+/// - `0 | truthy()` is `false`
+/// - `0.6931 | truthy()` is `true`
+/// - `null | truthy()` is `false`
+/// - `[null] | truthy()` is `true` (a List containing `null` is nonempty)
+/// - `true | truthy()` is `true`
+#[filter_fn]
+pub fn truthy<'doc>(value: PValue<'doc>) -> Result<PValue<'doc>> {
+    Ok(Value::Bool(is_truthy(value)))
+}
+
+/// Returns whether `value` is "truthy", following the rules of the `truthy` filter.
+fn is_truthy(value: PValue<'_>) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Float(f) => f != 0.,
+        Value::Int(i) => i != 0,
+        Value::Bool(b) => b,
+        Value::String(s) => !s.is_empty(),
+        Value::List(l) => !l.is_empty(),
+        Value::Structure(s) => !s.is_empty(),
+        Value::Extra(Pipeline::Element(_)) => true,
+        Value::Extra(Pipeline::ListIter(mut i)) => i.next().is_some(),
+        Value::Extra(Pipeline::StructIter(mut i)) => i.next().is_some(),
+        Value::Extra(_) => unreachable!(),
+    }
+}
+
+/// Signature: `value | coerce(to: String): (Int or Float or String or Bool)`
+///
+/// Converts `value` to the type named `to`, following the rules of the filter
+/// for that type:
+///
+/// - `"int"`: like [`int`], from an `Int`, `Float` (rounding down), or `String`.
+/// - `"float"`: like [`float`], from an `Int`, `Float`, or `String`.
+/// - `"bool"`: like [`truthy`], from any value.
+/// - `"string"`: a `String` is returned as is, and an `Int`, `Float`, or `Bool` is
+///   formatted, like `3.5` to `"3.5"`.  Other values raise an error.
+///
+/// An unknown `to` will raise an error.
+///
+/// # Examples
+///
+/// - `"12" | coerce(to: "int")` returns `12`
+/// - `12 | coerce(to: "string")` returns `"12"`
+/// - `"" | coerce(to: "bool")` returns `false`
+/// - `"12" | coerce(to: "date")` raises an error.
+#[filter_fn]
+pub fn coerce<'doc>(value: PValue<'doc>, to: Arc<str>) -> Result<PValue<'doc>> {
+    match &*to {
+        "int" => to_int(value).map(Value::Int),
+        "float" => to_float(value).map(Value::Float),
+        "bool" => Ok(Value::Bool(is_truthy(value))),
+        "string" => match EValue::from(value) {
+            Value::String(s) => Ok(Value::String(s)),
+            value @ (Value::Int(_) | Value::Float(_) | Value::Bool(_)) => {
+                Ok(Value::String(value.to_string().into()))
+            }
+            other => bail!("cannot coerce `{other}` to a string"),
+        },
+        other => bail!("unknown type `{other}` (expected `int`, `float`, `string`, or `bool`)"),
+    }
+}
+
+/// Signature: `value | assert(cond: Value?, non_null: Bool?, msg: String?): Value`
+///
+/// Returns `value` unchanged if the assertion holds, and raises an error with
+/// the message `msg` if it doesn't, to catch pages that don't look like the
+/// program expects.
+///
+/// The assertion holds if `cond` is "truthy" (see `truthy`), and, if `non_null`
+/// is `true`, `value` is not `null`.  At least one of them must be set.
+///
+/// # Examples
+///
+/// - With `ok: $count | eq(to: 3);`, `$items | assert(cond: $ok)` returns `$items`
+///   if there are three of them, and raises an error otherwise
+/// - `"" | assert(non_null: true)` returns `""`
+/// - `(a null value) | assert(non_null: true, msg: "no price")` raises the error
+///   `no price`
+#[filter_fn]
+pub fn assert<'doc>(
+    value: PValue<'doc>,
+    cond: Option<EValue<'doc>>,
+    non_null: Option<bool>,
+    msg: Option<Arc<str>>,
+) -> Result<PValue<'doc>> {
+    if cond.is_none() && non_null.is_none() {
+        bail!("expected `cond` or `non_null` to be set");
+    }
+
+    if non_null.unwrap_or_default() && matches!(value, Value::Null) {
+        let msg = msg.as_deref();
+        bail!(
+            "{}",
+            msg.unwrap_or("assertion failed: expected a value, found null")
+        );
+    }
+
+    if cond.is_some_and(|cond| !is_truthy(cond.into())) {
+        bail!("{}", msg.as_deref().unwrap_or("assertion failed"));
+    }
+
+    Ok(value)
+}
+
+/// Signature: `value: Structure | validate(schema: Structure): Structure`
+///
+/// Returns `value` unchanged if it matches `schema`, and raises an error with
+/// the first key (in alphabetical order) that doesn't, to catch pages whose
+/// layout changed.
+///
+/// Each key of `schema` is a key that `value` must have, and its value is the
+/// type that the key must have: one of `"null"`, `"bool"`, `"int"`, `"float"`,
+/// `"number"` (an `int` or a `float`), `"string"`, `"list"`, `"structure"`,
+/// `"element"`, or `"any"`.  A type ending in `?`, like `"int?"`, also allows the
+/// key to be missing or `null`.  Keys of `value` that aren't in `schema` are not
+/// checked.
+///
+/// # Examples
+///
+/// - Let `$schema` be `{ name: "string", price: "number?" }`
+///   - Then `{ name: "cat", price: 5 } | validate(schema: $schema)` returns the structure
+///   - And `{ name: "cat" } | validate(schema: $schema)` returns the structure
+///   - And `{ price: 5 } | validate(schema: $schema)` raises an error that `name` is missing
+///   - And `{ name: 1 } | validate(schema: $schema)` raises an error that `name`
+///     has type `int`, not `string`
+#[filter_fn]
+pub fn validate<'doc>(
+    value: Structure<'doc>,
+    schema: BTreeMap<Arc<str>, EValue<'doc>>,
+) -> Result<PValue<'doc>> {
+    const TYPES: [&str; 10] = [
+        "null",
+        "bool",
+        "int",
+        "float",
+        "number",
+        "string",
+        "list",
+        "structure",
+        "element",
+        "any",
+    ];
+
+    for (key, expected) in schema {
+        let expected: Arc<str> = expected
+            .try_unwrap()
+            .wrap_with(|| format!("note: occurred in the schema of key `{key}`"))?;
+        let (expected, optional) = match expected.strip_suffix('?') {
+            Some(expected) => (expected, true),
+            None => (&*expected, false),
+        };
+        if !TYPES.contains(&expected) {
+            bail!("unknown type `{expected}` in the schema of key `{key}`");
+        }
+
+        let actual = match value.get(&key) {
+            None | Some(Value::Null) if optional => continue,
+            None => bail!("validation failed: missing required key `{key}`"),
+            Some(value) => type_name(value),
+        };
+        let matches = expected == "any"
+            || expected == actual
+            || (expected == "number" && matches!(actual, "int" | "float"));
+        if !matches {
+            bail!(
+                "validation failed: expected key `{key}` to have type `{expected}`, got `{actual}`"
+            );
+        }
+    }
+
+    Ok(Value::Structure(value))
+}
+
+/// Returns the name of the type of `value`, as in the schemas of [`validate`].
+fn type_name(value: &PValue<'_>) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Int(_) => "int",
+        Value::Float(_) => "float",
+        Value::String(_) => "string",
+        Value::List(_) | Value::Extra(Pipeline::ListIter(_)) => "list",
+        Value::Structure(_) | Value::Extra(Pipeline::StructIter(_)) => "structure",
+        Value::Extra(_) => "element",
+    }
+}
+
+/// Signature: `value: String or List or Structure | is_empty(): Bool`
+///
+/// Returns whether `value` has no characters, elements, or keys.  A string of
+/// only whitespace, like `" "`, is *not* empty; use `strip()` first to count it.
+///
+/// Other types of values are invalid and will raise an error.
+///
+/// # Examples
+///
+/// - `"" | is_empty()` is `true`
+/// - `" " | is_empty()` is `false`, but `" " | strip() | is_empty()` is `true`
+/// - `[] | is_empty()` is `true`
+/// - `{ a: null } | is_empty()` is `false`
+/// - `0 | is_empty()` raises an error.
+#[filter_fn]
+pub fn is_empty<'doc>(value: PValue<'doc>) -> Result<PValue<'doc>> {
+    let empty = match value {
+        Value::String(s) => s.is_empty(),
+        Value::List(l) => l.is_empty(),
+        Value::Structure(s) => s.is_empty(),
+        Value::Extra(Pipeline::ListIter(mut i)) => i.next().is_none(),
+        Value::Extra(Pipeline::StructIter(mut i)) => i.next().is_none(),
+        other => bail!(
+            "expected a string, list, or structure, got {}",
+            EValue::from(other)
+        ),
+    };
+
+    Ok(Value::Bool(empty))
+}
+
+/// Signature: `value | is_null(): Bool`
+///
+/// Returns whether `value` is `null`.
+///
+/// # Examples
+///
+/// - `null | is_null()` is `true`
+/// - `"" | is_null()` is `false`
+/// - `[null] | is_null()` is `false`
+#[filter_fn]
+pub fn is_null<'doc>(value: PValue<'doc>) -> Result<PValue<'doc>> {
+    Ok(Value::Bool(matches!(value, Value::Null)))
+}
+
+/// Signature: `value | is_number(): Bool`
+///
+/// Returns whether `value` is an `Int` or a `Float`.  Strings of digits, like
+/// `"1"`, are not numbers; use `int()` or `float()` to convert them.
+///
+/// # Examples
+///
+/// - `1 | is_number()` is `true`
+/// - `1.5 | is_number()` is `true`
+/// - `"1" | is_number()` is `false`
+#[filter_fn]
+pub fn is_number<'doc>(value: PValue<'doc>) -> Result<PValue<'doc>> {
+    Ok(Value::Bool(matches!(
+        value,
+        Value::Int(_) | Value::Float(_)
+    )))
+}
+
+/// Signature: `value: Element | text(): String`
+///
+/// Returns the text contained inside this element.  Note that this is just
+/// direct text, not the text of any descendent elements.
+///
+/// # Examples
+///
+/// -  Let `element` = `<div>Hello...<span>inner</span>...world!</div>`
+///   - Then `$element | text()` is `"Hello......world!"`
+/// - `<img /> | text()` is `""`
+#[filter_fn]
+pub fn text<'doc>(value: scraper::ElementRef<'doc>) -> Result<PValue<'doc>> {
+    Ok(Value::String(direct_text(value).into()))
+}
+
+/// Signature: `value: Element | to_structure(depth: Int?): Structure`
+///
+/// Converts an element into data: a structure with its tag name at `tag`, its
+/// attributes at `attrs` (like [`attrs`]), its direct text at `text` (like
+/// [`text`]), and its child elements at `children`, each converted the same way.
+///
+/// If `depth` is set, only that many levels of children are converted, and the
+/// elements at the last level have `children: []`.  A negative `depth` raises
+/// an error.
+///
+/// # Examples
+///
+/// - Let `element` = `<ul id="pets"><li>cat</li></ul>`
+///   - Then `$element | to_structure()` is
+///     `{ tag: "ul", attrs: { id: "pets" }, text: "", children: [{ tag: "li", attrs: {}, text: "cat", children: [] }] }`
+///   - And `$element | to_structure(depth: 0)` is
+///     `{ tag: "ul", attrs: { id: "pets" }, text: "", children: [] }`
+#[filter_fn]
+pub fn to_structure<'doc>(
+    value: scraper::ElementRef<'doc>,
+    depth: Option<i64>,
+) -> Result<PValue<'doc>> {
+    let depth = depth
+        .map(|x| usize::try_from(x).msg("`depth` must not be negative"))
+        .transpose()?;
+
+    Ok(element_structure(value, depth.unwrap_or(usize::MAX)))
+}
+
+/// Converts `element` into a structure for [`to_structure`], with `depth`
+/// levels of children.
+fn element_structure<'doc>(element: scraper::ElementRef<'_>, depth: usize) -> PValue<'doc> {
+    let children = match depth.checked_sub(1) {
+        Some(depth) => element
+            .child_elements()
+            .map(|child| element_structure(child, depth))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let mut structure = Structure::new();
+    structure.insert(
+        Arc::from("tag"),
+        Value::String(element.value().name().into()),
+    );
+    structure.insert(
+        Arc::from("attrs"),
+        Value::Structure(
+            element
+                .value()
+                .attrs()
+                .map(|(k, v)| (Arc::from(k), Value::String(Arc::from(v))))
+                .collect(),
+        ),
+    );
+    structure.insert(
+        Arc::from("text"),
+        Value::String(direct_text(element).into()),
+    );
+    structure.insert(Arc::from("children"), Value::List(children));
+    Value::Structure(structure)
+}
+
+/// Returns the text directly inside `element`, like the `text` filter.
+fn direct_text(element: scraper::ElementRef<'_>) -> String {
+    element
+        .children()
+        .filter_map(|x| x.value().as_text().map(|text| &*text.text))
+        .collect()
+}
+
+/// Returns `element` if it matches `selector`, or otherwise its closest
+/// ancestor that does.
+fn closest<'doc>(
+    element: scraper::ElementRef<'doc>,
+    selector: &scraper::Selector,
+) -> Option<scraper::ElementRef<'doc>> {
+    std::iter::once(element)
+        .chain(element.ancestors().filter_map(scraper::ElementRef::wrap))
+        .find(|x| selector.matches(x))
+}
+
+/// Signature: `value: Element | select_closest_text(selector: String): String?`
+///
+/// Finds the closest element to `value` that matches the CSS selector
+/// `selector`, which is `value` itself if it matches or else its nearest
+/// ancestor that does, and returns its text, like `text`.  Returns `null` if no
+/// element matches.
+///
+/// This is for the text of the element around another one, like the name of the
+/// card that a "buy" button is in.
+///
+/// Raises an error if `selector` is not a valid CSS selector.
+///
+/// # Examples
+///
+/// - Let `element` be the `<button>` in
+///   `<li class="item">Widget<button>Buy</button></li>`
+///   - Then `$element | select_closest_text(selector: ".item")` is `"Widget"`
+///   - And `$element | select_closest_text(selector: "button")` is `"Buy"`
+///   - And `$element | select_closest_text(selector: "table")` is `null`
+#[filter_fn]
+pub fn select_closest_text<'doc>(
+    value: scraper::ElementRef<'doc>,
+    selector: Arc<str>,
+) -> Result<PValue<'doc>> {
+    Ok(closest(value, &self::selector(&selector)?)
+        .map_or(Value::Null, |x| Value::String(direct_text(x).into())))
+}
+
+/// Signature: `value: Element | select_nth(selector: String, i: Int): Element?`
+///
+/// Returns the `i`th element inside `value` that matches the CSS selector
+/// `selector`, in document order and starting from zero, or `null` if there are
+/// not that many.  A negative `i` counts from the end, so `-1` is the last match.
+///
+/// This is like `$element | select(selector: ...) | nth(i: ...)`, but it stops
+/// at the match instead of collecting every one.  A negative `i` needs to count
+/// the matches first.  An invalid `selector` will raise an error.
+///
+/// # Examples
+///
+/// - Let `element` = `<ul><li>cat</li><li>dog</li><li>fish</li></ul>`
+///   - Then `$element | select_nth(selector: "li", i: 1) | text()` is `"dog"`
+///   - And `$element | select_nth(selector: "li", i: -1) | text()` is `"fish"`
+///   - And `$element | select_nth(selector: "li", i: 3)` is `null`
+#[filter_fn]
+pub fn select_nth<'doc>(
+    value: scraper::ElementRef<'doc>,
+    selector: Arc<str>,
+    i: i64,
+) -> Result<PValue<'doc>> {
+    let selector = self::selector(&selector)?;
+    let index = match usize::try_from(i) {
+        Ok(i) => Some(i),
+        Err(_) => value
+            .select(&selector)
+            .count()
+            .checked_sub(usize::try_from(i.unsigned_abs()).unwrap_or(usize::MAX)),
+    };
+
+    Ok(index
+        .and_then(|i| value.select(&selector).nth(i))
+        .map_or(Value::Null, |x| EValue::from(x).into()))
+}
+
+/// Signature: `value: Element | first_selector(selectors: List<String>): Element?`
+///
+/// Tries each CSS selector in `selectors` in order, and returns the first element
+/// inside `value` that matches the first selector with any match, or `null` if
+/// none of them match.
+///
+/// This is useful when a page has several layouts, so a field can be in one of a
+/// few places.  An invalid selector, or one that is not a `String`, will raise an
+/// error, even if an earlier selector matched.
+///
+/// # Examples
+///
+/// - Let `element` = `<div><span class="price">$3</span></div>`
+///   - Then `$element | first_selector(selectors: [".sale", ".price"]) | text()`
+///     is `"$3"`
+///   - And `$element | first_selector(selectors: [".sale", ".discount"])` is `null`
+#[filter_fn]
+pub fn first_selector<'doc>(
+    value: scraper::ElementRef<'doc>,
+    selectors: Vec<EValue<'doc>>,
+) -> Result<PValue<'doc>> {
+    let selectors = selectors
+        .into_iter()
+        .map(|selector| {
+            let selector = selector
+                .try_unwrap::<Arc<str>>()
+                .msg("expected each selector to be a string")?;
+            self::selector(&selector)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(selectors
+        .iter()
+        .find_map(|selector| value.select(selector).next())
+        .map_or(Value::Null, |x| EValue::from(x).into()))
+}
+
+/// Signature: `value: Element | texts(selector: String, trim: Bool?): List<String>`
+///
+/// Selects every element inside `value` that matches the CSS selector `selector`,
+/// in document order, and returns a list of the text inside each one.  Unlike
+/// [`text`], this includes the text of their descendant elements.  If `trim` is
+/// `true`, whitespace is trimmed from the start and end of each text.
+///
+/// This is like an element block that binds the text of each element, but
+/// returns the texts directly instead of a list of structures.  An invalid
+/// `selector` will raise an error.
+///
+/// # Examples
+///
+/// - Let `element` = `<ul><li>cat</li><li><b>big</b> dog</li></ul>`
+///   - Then `$element | texts(selector: "li")` is `["cat", "big dog"]`
+///   - And `$element | texts(selector: "p")` is `[]`
+#[filter_fn]
+pub fn texts<'doc>(
+    value: scraper::ElementRef<'doc>,
+    selector: Arc<str>,
+    trim: Option<bool>,
+) -> Result<PValue<'doc>> {
+    let selector = self::selector(&selector)?;
+    let trim = trim.unwrap_or_default();
+
+    Ok(Value::List(
+        value
+            .select(&selector)
+            .map(|element| {
+                let text = element.text().collect::<String>();
+                let text = if trim { text.trim() } else { &text };
+                Value::String(text.into())
+            })
+            .collect(),
+    ))
+}
+
+/// Signature: `value: Element | attrs_of(selector: String, name: String, skip_missing: Bool?): List<String?>`
+///
+/// Selects every element inside `value` that matches the CSS selector `selector`,
+/// in document order, and returns a list of the value of the attribute `name`
+/// on each one.  Elements without the attribute have `null` in its place, or if
+/// `skip_missing` is `true`, are left out of the list.
+///
+/// An invalid `selector` will raise an error.
+///
+/// # Examples
+///
+/// - Let `element` = `<p><a href="/a">A</a><a>B</a><a href="/c">C</a></p>`
+///   - Then `$element | attrs_of(selector: "a", name: "href")` is `["/a", null, "/c"]`
+///   - And `$element | attrs_of(selector: "a", name: "href", skip_missing: true)`
+///     is `["/a", "/c"]`
+#[filter_fn]
+pub fn attrs_of<'doc>(
+    value: scraper::ElementRef<'doc>,
+    selector: Arc<str>,
+    name: Arc<str>,
+    skip_missing: Option<bool>,
+) -> Result<PValue<'doc>> {
+    let selector = self::selector(&selector)?;
+    let attrs = value
+        .select(&selector)
+        .map(|element| element.value().attr(&name));
+
+    Ok(Value::List(if skip_missing.unwrap_or_default() {
+        attrs.flatten().map(|x| Value::String(x.into())).collect()
+    } else {
+        attrs
+            .map(|x| x.map_or(Value::Null, |x| Value::String(x.into())))
+            .collect()
+    }))
+}
+
+/// Elements that `strip_tags` puts on their own lines.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "tr",
+    "ul",
+];
+
+/// Appends the text inside `element` to `out`, with whitespace collapsed and a
+/// newline around each block element.
+fn strip_tags_into(element: scraper::ElementRef<'_>, out: &mut String) {
+    let name = element.value().name();
+    if matches!(name, "script" | "style" | "template") {
+        return;
+    }
+
+    let block = BLOCK_ELEMENTS.contains(&name);
+    if block && !out.is_empty() {
+        out.push('\n');
+    }
+
+    for child in element.children() {
+        if let Some(text) = child.value().as_text() {
+            for (i, word) in text.split(char::is_whitespace).enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                out.push_str(word);
+            }
+        } else if let Some(child) = scraper::ElementRef::wrap(child) {
+            strip_tags_into(child, out);
+        }
+    }
+
+    if name == "br" || block {
+        out.push('\n');
+    }
+}
+
+/// Signature: `value: String | strip_tags(): String`
+///
+/// Parses `value` as a fragment of HTML and returns its text, with HTML entities
+/// (like `&amp;`) decoded.  Block elements (like `<p>` and `<li>`) and `<br>`
+/// tags start a new line, and other whitespace is collapsed to a single space.
+/// Blank lines are removed.  The contents of `<script>` and `<style>` elements are removed.
+///
+/// # Examples
+///
+/// - `"<p>Fish &amp; <b>chips</b></p><p>Peas</p>" | strip_tags()` returns
+///   `"Fish & chips\nPeas"`
+/// - `"one<br>two" | strip_tags()` returns `"one\ntwo"`
+#[filter_fn]
+pub fn strip_tags<'doc>(value: Arc<str>) -> Result<PValue<'doc>> {
+    let html = scraper::Html::parse_fragment(&value);
+    let mut out = String::new();
+    strip_tags_into(html.root_element(), &mut out);
+
+    let lines: Vec<_> = out
+        .lines()
+        .map(|line| {
+            line.split(' ')
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    Ok(Value::String(lines.join("\n").into()))
+}
+
+/// Signature: `value: Number | add(to: Number): Number`
+///
+/// Adds the two numbers together.  The two numbers must be the same
+/// type (Float or Int), and the return type will also be that type.
+///
+/// # Examples
+/// - `1 | add(to: 2)` returns `3`
+/// - `0.5 | add(to: 1.5)` returns `2.0`
+/// - `1 | add(to: 1.5)` raises an error because of the type mismatch.
+#[filter_fn]
+pub fn add<'doc>(value: Number, to: Number) -> Result<PValue<'doc>> {
+    match (value, to) {
+        (Number::Int(a), Number::Int(b)) => Ok(Value::Int(a + b)),
+        (Number::Float(a), Number::Float(b)) => Ok(Value::Float(a + b)),
+        (a, b) => bail!("Type mismatch when adding {a:?} + {b:?}"),
+    }
+}
+
+/// Signature: `value: Number | sub(by: Number): Number`
+///
+/// Subtracts `by` from `value`. The two numbers must be the same
+/// type (Float or Int), and the return type will also be that type.
+///
+/// # Examples
+/// - `1 | sub(by: 2)` returns `-1`
+/// - `0.5 | sub(by: 1.5)` returns `-1.0`
+/// - `1 | sub(by: 1.5)` raises an error because of the type mismatch.
+#[filter_fn]
+pub fn sub<'doc>(value: Number, by: Number) -> Result<PValue<'doc>> {
+    match (value, by) {
+        (Number::Int(a), Number::Int(b)) => Ok(Value::Int(a - b)),
+        (Number::Float(a), Number::Float(b)) => Ok(Value::Float(a - b)),
+        (a, b) => bail!("Type mismatch when subtracting {a:?} - {b:?}"),
+    }
+}
+
+/// Signature: `value: Number | mult(by: Number): Number`
+///
+/// Multiplies `value * by`. The two numbers must be the same
+/// type (Float or Int), and the return type will also be that type.
+///
+/// # Examples
+/// - `1 | mult(by: 2)` returns `2`
+/// - `0.5 | mult(by: 1.5)` returns `0.75``
+/// - `1 | mult(by: 1.5)` raises an error because of the type mismatch.
+#[filter_fn]
+pub fn mult<'doc>(value: Number, by: Number) -> Result<PValue<'doc>> {
+    match (value, by) {
+        (Number::Int(a), Number::Int(b)) => Ok(Value::Int(a * b)),
+        (Number::Float(a), Number::Float(b)) => Ok(Value::Float(a * b)),
+        (a, b) => bail!("Type mismatch when subtracting {a:?} * {b:?}"),
+    }
+}
+
+/// Signature: `value: List<Number> | diff(): List<Number>`
+///
+/// Returns the differences between each pair of consecutive numbers in `value`,
+/// each element subtracted from the one after it, so the output has one fewer
+/// element than `value`.  A difference is an `Int` if both numbers are `Int`s,
+/// and a `Float` otherwise.  Lists with fewer than two elements return `[]`.
+///
+/// Elements that are not numbers are invalid and will raise an error.
+///
+/// # Examples
+///
+/// - `[1, 4, 9, 16] | diff()` returns `[3, 5, 7]`
+/// - `[1, 1.5, 3] | diff()` returns `[0.5, 1.5]`
+/// - `[5] | diff()` returns `[]`
+#[filter_fn]
+pub fn diff<'doc>(value: Vec<PValue<'doc>>) -> Result<PValue<'doc>> {
+    let numbers = value
+        .into_iter()
+        .map(Value::try_unwrap::<Number>)
+        .collect::<Result<Vec<_>>>()?;
+
+    numbers
+        .windows(2)
+        .map(|pair| match (pair[0], pair[1]) {
+            (Number::Int(a), Number::Int(b)) => b
+                .checked_sub(a)
+                .map(Value::Int)
+                .msg("integer overflow in `diff`"),
+            (a, b) => Ok(Value::Float(b.cast_to_float() - a.cast_to_float())),
+        })
+        .collect::<Result<_>>()
+        .map(Value::List)
+}
+
+/// Compares two values of the same type (numbers, strings, or bools), for use
+/// in filters that order values.  An `Int` and a `Float` are compared numerically.
+///
+/// # Errors
+///
+/// Returns an `Err` if the values are of different or unorderable types, or one of
+/// them is a `NaN` float.
+fn compare_values<X, Y>(a: &Value<X>, b: &Value<Y>) -> Result<Ordering> {
+    let ordering = match (a, b) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        (Value::Int(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+        (Value::Float(a), Value::Int(b)) => a.partial_cmp(&(*b as f64)),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => bail!("cannot compare values of different or unorderable types"),
+    };
+
+    ordering.msg("cannot compare NaN floats")
+}
+
+/// Finds the structure in `value` with the most `wanted` value at `key`,
+/// keeping the first one on ties.
+fn extreme_by<'doc>(value: Vec<PValue<'doc>>, key: &str, wanted: Ordering) -> Result<PValue<'doc>> {
+    let mut best: Option<Structure<'doc>> = None;
+
+    for item in value {
+        let item: Structure<'doc> = item.try_unwrap()?;
+        let Some(candidate) = item.get(key).filter(|x| !matches!(x, Value::Null)) else {
+            continue;
+        };
+
+        let replace = match &best {
+            Some(best) => {
+                compare_values(candidate, &best[key])
+                    .with_msg(|| format!("comparing values at key `{key}`"))?
+                    == wanted
+            }
+            None => true,
+        };
+
+        if replace {
+            best = Some(item);
+        }
+    }
+
+    best.map(Value::Structure)
+        .with_msg(|| format!("no structure in the list has key `{key}`"))
+}
+
+/// Signature: `value: List<Structure> | min_by(key: String): Structure`
+///
+/// Returns the structure in the list with the smallest value at `key`.
+/// Values are compared like in [`max_by`]: numbers with numbers and strings
+/// with strings (alphabetically).
+///
+/// Structures that are missing `key` (or where it is `null`) are skipped.  If two
+/// structures are tied, the first one is returned.  It is an error if no structure
+/// in the list has `key`, including if the list is empty.
+///
+/// # Examples
+///
+/// - `[{ name: "cat", price: 5 }, { name: "dog", price: 3 }] | min_by(key: "price")`
+///   returns `{ name: "dog", price: 3 }`
+/// - `[{ name: "cat" }, { name: "dog", price: 3 }] | min_by(key: "price")`
+///   returns `{ name: "dog", price: 3 }`
+/// - `[] | min_by(key: "price")` raises an error.
+#[filter_fn]
+pub fn min_by<'doc>(value: Vec<PValue<'doc>>, key: Arc<str>) -> Result<PValue<'doc>> {
+    extreme_by(value, &key, Ordering::Less)
+}
+
+/// Signature: `value: List<Structure> | max_by(key: String): Structure`
+///
+/// Returns the structure in the list with the largest value at `key`.
+///
+/// Values must be comparable: `Int`s and `Float`s can be compared with each other,
+/// `String`s are compared alphabetically, and `Bool`s have `false < true`.  Comparing
+/// values of other or different types raises an error.
+///
+/// Structures that are missing `key` (or where it is `null`) are skipped.  If two
+/// structures are tied, the first one is returned.  It is an error if no structure
+/// in the list has `key`, including if the list is empty.
+///
+/// # Examples
+///
+/// - `[{ name: "cat", price: 5 }, { name: "dog", price: 3.5 }] | max_by(key: "price")`
+///   returns `{ name: "cat", price: 5 }`
+/// - `[{ name: "cat" }, { name: "dog" }] | max_by(key: "name")` returns `{ name: "dog" }`
+/// - `[{ name: "cat" }] | max_by(key: "price")` raises an error.
+#[filter_fn]
+pub fn max_by<'doc>(value: Vec<PValue<'doc>>, key: Arc<str>) -> Result<PValue<'doc>> {
+    extreme_by(value, &key, Ordering::Greater)
+}
+
+/// Signature: `value: List<Structure> | sort_by(keys: List): List<Structure>`
+///
+/// Sorts the structures in `value` by the values at each of `keys` in turn: by
+/// the first key, then structures with equal values there by the second key, and
+/// so on.  Each key is either a name, which sorts in ascending order, or a
+/// `[name, "asc"]` or `[name, "desc"]` pair.  The sort is stable, so structures
+/// that are equal at every key keep their order.
+///
+/// Values are compared like in [`max_by`].  Structures that are missing a key
+/// (or where it is `null`) come after every structure that has it, in both
+/// directions.  Raises an error if `keys` is empty, a key is invalid, or two
+/// values at a key can't be compared.
+///
+/// # Examples
+///
+/// - Let `$keys` be `["category", ["price", "desc"]]`
+///   - Then `[{ category: "b", price: 1 }, { category: "a", price: 2 }, { category: "b", price: 3 }] | sort_by(keys: $keys)`
+///     returns `[{ category: "a", price: 2 }, { category: "b", price: 3 }, { category: "b", price: 1 }]`
+/// - `[{ price: 1 }, {}, { price: 0 }] | sort_by(keys: ["price"])` returns
+///   `[{ price: 0 }, { price: 1 }, {}]`
+#[filter_fn]
+pub fn sort_by<'doc>(value: Vec<PValue<'doc>>, keys: Vec<EValue<'doc>>) -> Result<PValue<'doc>> {
+    let keys = keys.into_iter().map(sort_key).collect::<Result<Vec<_>>>()?;
+    if keys.is_empty() {
+        bail!("`sort_by` needs at least one key");
+    }
+    let mut items = value
+        .into_iter()
+        .map(PValue::try_unwrap::<Structure<'doc>>)
+        .collect::<Result<Vec<_>>>()?;
+
+    let present = |x: &&PValue<'doc>| !matches!(x, Value::Null);
+    let mut error = None;
+    items.sort_by(|a, b| {
+        for (key, descending) in &keys {
+            let ordering = match (a.get(key).filter(present), b.get(key).filter(present)) {
+                (Some(a), Some(b)) => {
+                    match compare_values(a, b)
+                        .with_msg(|| format!("comparing values at key `{key}`"))
+                    {
+                        Ok(ordering) if *descending => ordering.reverse(),
+                        Ok(ordering) => ordering,
+                        Err(e) => {
+                            error.get_or_insert(e);
+                            Ordering::Equal
+                        }
+                    }
+                }
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+            if ordering.is_ne() {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    });
+
+    match error {
+        Some(e) => Err(e),
+        None => Ok(Value::List(
+            items.into_iter().map(Value::Structure).collect(),
+        )),
+    }
+}
+
+/// Reads a key of [`sort_by`] into its name and whether it sorts in descending order.
+fn sort_key(key: EValue<'_>) -> Result<(Arc<str>, bool)> {
+    let invalid = |key: &dyn std::fmt::Display| {
+        other!("expected a key name or a `[name, \"asc\" or \"desc\"]` pair, got `{key}`")
+    };
+
+    match key {
+        Value::String(name) => Ok((name, false)),
+        Value::List(pair) => match <[_; 2]>::try_from(pair) {
+            Ok([Value::String(name), Value::String(direction)]) => match &*direction {
+                "asc" => Ok((name, false)),
+                "desc" => Ok((name, true)),
+                _ => Err(invalid(&direction)),
+            },
+            Ok(pair) => Err(invalid(&Value::List(pair.into()))),
+            Err(list) => Err(invalid(&Value::List(list))),
+        },
+        other => Err(invalid(&other)),
+    }
+}
+
+/// Signature: `value: Element | meta(): Structure`
+///
+/// Returns the `<meta>` tags inside `value` (usually the document root, `$element`
+/// at the top level) as a structure mapping each tag's name to its `content`.
+///
+/// The name of a tag is its `property` attribute if it has one, and otherwise its
+/// `name` attribute, so OpenGraph (`og:*`) tags, which use `property`, and Twitter
+/// card (`twitter:*`) tags, which use `name`, are both included.  Tags without a
+/// name or `content` are skipped.  If more than one tag has the same name, the
+/// first one in the document is used.
+///
+/// # Examples
+///
+/// - Let the document head contain
+///   `<meta property="og:title" content="Cats" />`,
+///   `<meta name="description" content="All about cats" />`, and
+///   `<meta name="description" content="Something else" />`.
+///   - Then `$element | meta()` is `{ "og:title": "Cats", description: "All about cats" }`
+/// - `<p>No meta tags</p> | meta()` returns `{}`
+#[filter_fn]
+pub fn meta<'doc>(value: scraper::ElementRef<'doc>) -> Result<PValue<'doc>> {
+    static SELECTOR: LazyLock<scraper::Selector> =
+        LazyLock::new(|| scraper::Selector::parse("meta").expect("selector is valid"));
+
+    let mut structure = Structure::new();
+
+    for element in value.select(&SELECTOR) {
+        let element = element.value();
+        let name = element.attr("property").or_else(|| element.attr("name"));
+
+        if let (Some(name), Some(content)) = (name, element.attr("content")) {
+            structure
+                .entry(Arc::from(name))
+                .or_insert_with(|| Value::String(Arc::from(content)));
+        }
+    }
+
+    Ok(Value::Structure(structure))
+}
+
+/// Signature: `value: Element | jsonld(): List`
+///
+/// Finds all of the JSON-LD blocks (`<script type="application/ld+json">`) inside
+/// `value` and returns a list of their parsed contents, in document order.
+///
+/// A block that is not valid JSON is skipped, with a warning.
+///
+/// # Examples
+///
+/// - Let the document contain
+///   `<script type="application/ld+json">{ "@type": "Person", "name": "Kitty" }</script>`.
+///   - Then `$element | jsonld()` is `[{ "@type": "Person", name: "Kitty" }]`
+/// - `<p>No scripts</p> | jsonld()` returns `[]`
+#[filter_fn(impure)]
+pub fn jsonld<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
+    value: scraper::ElementRef<'doc>,
+    ctx: &mut E,
+) -> Result<PValue<'doc>> {
+    static SELECTOR: LazyLock<scraper::Selector> = LazyLock::new(|| {
+        scraper::Selector::parse(r#"script[type="application/ld+json"]"#)
+            .expect("selector is valid")
+    });
+
+    let mut blocks = Vec::new();
+
+    for (i, script) in value.select(&SELECTOR).enumerate() {
+        match serde_json::from_str::<serde_json::Value>(&script.text().collect::<String>()) {
+            Ok(json) => blocks.push(json.into()),
+            Err(e) => ctx.warn(&format!("skipping malformed JSON-LD block #{i}: {e}")),
+        }
+    }
+
+    Ok(Value::List(blocks))
+}
+
+/// Returns the rows of `table`, including those in its `<thead>`, `<tbody>`,
+/// and `<tfoot>` sections, but not those of any nested tables.
+fn table_rows<'doc>(
+    table: scraper::ElementRef<'doc>,
+) -> impl Iterator<Item = scraper::ElementRef<'doc>> {
+    table.child_elements().flat_map(|child| {
+        let rows: Vec<_> = match child.value().name() {
+            "tr" => vec![child],
+            "thead" | "tbody" | "tfoot" => child
+                .child_elements()
+                .filter(|x| x.value().name() == "tr")
+                .collect(),
+            _ => vec![],
+        };
+        rows
+    })
+}
+
+/// Returns `value` if it is a `<table>`, or the first table inside it.
+fn find_table(value: scraper::ElementRef<'_>) -> Result<scraper::ElementRef<'_>> {
+    static TABLE: LazyLock<scraper::Selector> =
+        LazyLock::new(|| scraper::Selector::parse("table").expect("selector is valid"));
+
+    if value.value().name() == "table" {
+        Ok(value)
+    } else {
+        value.select(&TABLE).next().msg("no `<table>` found")
+    }
+}
+
+/// Returns the `colspan` or `rowspan` attribute `attr` of `cell`, which
+/// is 1 if it is missing or invalid.
+fn span(cell: scraper::ElementRef<'_>, attr: &str) -> usize {
+    cell.value()
+        .attr(attr)
+        .and_then(|x| x.trim().parse().ok())
+        .filter(|&x| x > 0)
+        .unwrap_or(1)
+}
+
+/// Signature: `value: Element | table(): List<Structure>`
+///
+/// Converts an HTML table into a list of structures, one for each row in the body
+/// of the table.  `value` is either a `<table>` or an element containing one, in
+/// which case its first table is used.
+///
+/// The header of the table is its first row, if that row contains only `<th>` cells.
+/// The text of each header cell is the key for its column, and each body row maps
+/// the keys to the text of its cells (with leading and trailing whitespace removed).
+/// If the table has no header, the keys are the column numbers, starting from `"0"`.
+/// Rows with fewer cells than the header have `null` in the missing columns.
+///
+/// Spanning cells are handled minimally: a body cell with `colspan` or `rowspan`
+/// fills every column and row it covers with its text, and a header cell with
+/// `colspan` names each of its columns `key`, `key_1`, `key_2`, and so on.
+/// Only the first row of the header is used.
+///
+/// # Examples
+///
+/// - Let `table` be
+///   `<table><tr><th>name</th><th>age</th></tr><tr><td>Kitty</td><td>3</td></tr></table>`.
+///   - Then `$table | table()` is `[{ name: "Kitty", age: "3" }]`
+/// - `<table><tr><td>a</td><td>b</td></tr></table> | table()` is `[{ "0": "a", "1": "b" }]`
+/// - `<p>No table</p> | table()` raises an error.
+#[filter_fn]
+pub fn table<'doc>(value: scraper::ElementRef<'doc>) -> Result<PValue<'doc>> {
+    let table = find_table(value)?;
+
+    let cells = |row: scraper::ElementRef<'doc>| {
+        row.child_elements()
+            .filter(|x| matches!(x.value().name(), "th" | "td"))
+            .collect::<Vec<_>>()
+    };
+    let cell_text = |cell: scraper::ElementRef<'doc>| -> Arc<str> {
+        cell.text().collect::<String>().trim().into()
+    };
+
+    let mut rows = table_rows(table).peekable();
+
+    let mut keys: Vec<Arc<str>> = Vec::new();
+    if let Some(header) = rows
+        .peek()
+        .map(|&row| cells(row))
+        .filter(|cells| !cells.is_empty() && cells.iter().all(|x| x.value().name() == "th"))
+    {
+        rows.next();
+        for cell in header {
+            let key = cell_text(cell);
+            keys.push(Arc::clone(&key));
+            for i in 1..span(cell, "colspan") {
+                keys.push(format!("{key}_{i}").into());
+            }
+        }
+    }
+
+    // cells from previous rows with a `rowspan` that covers later rows:
+    // (rows remaining, text) for each column
+    let mut spanning: Vec<Option<(usize, Arc<str>)>> = Vec::new();
+    let mut output = Vec::new();
+
+    for row in rows {
+        let mut columns: Vec<Option<Arc<str>>> = Vec::new();
+        let mut cells = cells(row).into_iter();
+
+        loop {
+            let col = columns.len();
+            if let Some(Some((remaining, text))) = spanning.get_mut(col) {
+                columns.push(Some(Arc::clone(text)));
+                *remaining -= 1;
+                if *remaining == 0 {
+                    spanning[col] = None;
+                }
+                continue;
+            }
+
+            let Some(cell) = cells.next() else {
+                break;
+            };
+
+            let text = cell_text(cell);
+            let rowspan = span(cell, "rowspan");
+            for _ in 0..span(cell, "colspan") {
+                let col = columns.len();
+                if rowspan > 1 {
+                    if spanning.len() <= col {
+                        spanning.resize(col + 1, None);
+                    }
+                    spanning[col] = Some((rowspan - 1, Arc::clone(&text)));
+                }
+                columns.push(Some(Arc::clone(&text)));
+            }
+        }
+
+        if columns.is_empty() {
+            continue;
+        }
+
+        columns.resize(columns.len().max(keys.len()), None);
+
+        output.push(Value::Structure(
+            columns
+                .into_iter()
+                .enumerate()
+                .map(|(i, text)| {
+                    let key = keys.get(i).map_or_else(|| i.to_string().into(), Arc::clone);
+                    (key, text.map_or(Value::Null, Value::String))
+                })
+                .collect(),
+        ));
+    }
+
+    Ok(Value::List(output))
+}
+
+/// Signature: `value: Element | cell(row: Int, col: Int): String?`
+///
+/// Returns the text of the cell at row `row` and column `col` of an HTML table
+/// (with leading and trailing whitespace removed), or `null` if there is no such
+/// cell.  Like in [`table`], `value` is either a `<table>` or an element containing
+/// one, in which case its first table is used.
+///
+/// Both start from zero, and a negative index counts from the end, so `-1` is the
+/// last row or the last cell in the row.  Unlike in `table`, the header is not
+/// treated specially: if the table has a header row, it is row `0`.  Cells are
+/// counted as they are in the row, so a spanning cell only counts once, and
+/// cells covered by a `rowspan` from an earlier row are not counted.
+///
+/// # Examples
+///
+/// - Let `table` be
+///   `<table><tr><th>name</th><th>age</th></tr><tr><td>Kitty</td><td>3</td></tr></table>`.
+///   - Then `$table | cell(row: 1, col: 0)` is `"Kitty"`
+///   - And `$table | cell(row: 0, col: -1)` is `"age"`
+///   - And `$table | cell(row: 2, col: 0)` is `null`
+#[filter_fn]
+pub fn cell<'doc>(value: scraper::ElementRef<'doc>, row: i64, col: i64) -> Result<PValue<'doc>> {
+    let index = |i: i64, len: usize| match usize::try_from(i) {
+        Ok(i) => (i < len).then_some(i),
+        Err(_) => len.checked_sub(usize::try_from(i.unsigned_abs()).unwrap_or(usize::MAX)),
+    };
+
+    let rows: Vec<_> = table_rows(find_table(value)?).collect();
+    let cells: Vec<_> = match index(row, rows.len()) {
+        Some(row) => rows[row]
+            .child_elements()
+            .filter(|x| matches!(x.value().name(), "th" | "td"))
+            .collect(),
+        None => return Ok(Value::Null),
+    };
+
+    Ok(index(col, cells.len()).map_or(Value::Null, |col| {
+        Value::String(cells[col].text().collect::<String>().trim().into())
+    }))
+}
+
+/// Inserts `value` into `output` at `path`, flattening it first if it is a
+/// nonempty list or structure.
+fn flatten_into<'doc>(
+    output: &mut BTreeMap<Arc<str>, EValue<'doc>>,
+    path: String,
+    value: EValue<'doc>,
+    sep: &str,
+) -> Result<()> {
+    match value {
+        Value::Structure(structure) if !structure.is_empty() => {
+            for (key, value) in structure {
+                flatten_into(output, format!("{path}{sep}{key}"), value, sep)?;
+            }
+        }
+        Value::List(list) if !list.is_empty() => {
+            for (i, value) in list.into_iter().enumerate() {
+                flatten_into(output, format!("{path}[{i}]"), value, sep)?;
+            }
+        }
+        value => {
+            if output.insert(Arc::from(&*path), value).is_some() {
+                bail!("key `{path}` appears more than once after flattening");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Signature: `value: Structure | flatten_keys(sep: String?): Structure`
+///
+/// Flattens a structure of nested structures and lists into a structure with one
+/// level, where each key is the path to its value in the original structure.
+/// Keys of nested structures are joined with `sep` (default `"."`), and list items
+/// are indexed with `[i]`.  Empty nested structures and lists are kept as is.
+///
+/// It is an error if two paths flatten to the same key, such as the key `"a.b"` and
+/// the key `"b"` inside the key `"a"`.
+///
+/// # Examples
+///
+/// - `{ a: { b: [{ c: 1 }, 2] }, d: "hi" } | flatten_keys()` returns
+///   `{ "a.b[0].c": 1, "a.b[1]": 2, d: "hi" }`
+/// - `{ a: { b: 1 } } | flatten_keys(sep: "/")` returns `{ "a/b": 1 }`
+/// - `{ a: { b: 1 }, "a.b": 2 } | flatten_keys()` raises an error.
+#[filter_fn]
+pub fn flatten_keys<'doc>(value: Structure<'doc>, sep: Option<Arc<str>>) -> Result<PValue<'doc>> {
+    let sep = sep.as_deref().unwrap_or(".");
+    let mut output = BTreeMap::new();
+
+    for (key, value) in value {
+        flatten_into(&mut output, key.to_string(), value.into(), sep)?;
+    }
+
+    Ok(Value::Structure(
+        output.into_iter().map(|(k, v)| (k, v.into())).collect(),
+    ))
+}
+
+/// Merges the keys of `source` into `target`, replacing the value of a key that
+/// is in both.  If `deep` is `true`, two structures at the same key are merged
+/// like this instead.
+fn merge_into<'doc>(
+    target: &mut BTreeMap<Arc<str>, EValue<'doc>>,
+    source: BTreeMap<Arc<str>, EValue<'doc>>,
+    deep: bool,
+) {
+    for (key, value) in source {
+        let value = match (target.remove(&key), value) {
+            (Some(Value::Structure(mut existing)), Value::Structure(value)) if deep => {
+                merge_into(&mut existing, value, deep);
+                Value::Structure(existing)
+            }
+            (_, value) => value,
+        };
+        target.insert(key, value);
+    }
+}
+
+/// Signature: `value: List<Structure> | pluck(key: String, skip_missing: Bool?): List`
+///
+/// Returns the value at `key` in each structure in `value`, like calling `take`
+/// on each of them.  A structure without `key` gives `null`, or is left out if
+/// `skip_missing` is `true`.
+///
+/// Raises an error if an element of `value` is not a structure.
+///
+/// # Examples
+///
+/// - `[{ name: "a", n: 1 }, { name: "b" }] | pluck(key: "n")` returns `[1, null]`
+/// - `[{ name: "a", n: 1 }, { name: "b" }] | pluck(key: "n", skip_missing: true)`
+///   returns `[1]`
+#[filter_fn]
+pub fn pluck<'doc>(
+    value: Vec<PValue<'doc>>,
+    key: Arc<str>,
+    skip_missing: Option<bool>,
+) -> Result<PValue<'doc>> {
+    let mut output = Vec::with_capacity(value.len());
+
+    for (i, item) in value.into_iter().enumerate() {
+        let mut item: Structure = item
+            .try_unwrap()
+            .wrap_with(|| format!("note: occurred at list index {i}"))?;
+
+        match item.remove(&key) {
+            Some(x) => output.push(x),
+            None if skip_missing.unwrap_or_default() => (),
+            None => output.push(Value::Null),
+        }
+    }
+
+    Ok(Value::List(output))
+}
+
+/// Signature: `value: List<Structure> | index_by(key: String, first: Bool?): Structure`
+///
+/// Turns a list of structures into a structure that maps the value of each
+/// structure's `key` to that structure, to look records up by a field like an id.
+///
+/// If more than one structure has the same value, the last one is kept, or the
+/// first one if `first` is `true`.  Structures where `key` is missing or `null`
+/// are left out.  `Int` and `Float` values are turned into strings.
+///
+/// Raises an error if an element of `value` is not a structure, or if a value at
+/// `key` is not a string, number, or `null`.
+///
+/// # Examples
+///
+/// - `[{ id: "a", n: 1 }, { id: "b", n: 2 }] | index_by(key: "id")` returns
+///   `{ a: { id: "a", n: 1 }, b: { id: "b", n: 2 } }`
+/// - `[{ id: 1, n: 1 }, { id: 1, n: 2 }, { n: 3 }] | index_by(key: "id")` returns
+///   `{ "1": { id: 1, n: 2 } }`
+/// - `[{ id: 1, n: 1 }, { id: 1, n: 2 }] | index_by(key: "id", first: true)`
+///   returns `{ "1": { id: 1, n: 1 } }`
+#[filter_fn]
+pub fn index_by<'doc>(
+    value: Vec<PValue<'doc>>,
+    key: Arc<str>,
+    first: Option<bool>,
+) -> Result<PValue<'doc>> {
+    let mut output = BTreeMap::new();
+
+    for (i, item) in value.into_iter().enumerate() {
+        let item: Structure = item
+            .try_unwrap()
+            .wrap_with(|| format!("note: occurred at list index {i}"))?;
+
+        let index: Arc<str> = match item.get(&key) {
+            None | Some(Value::Null) => continue,
+            Some(Value::String(s)) => Arc::clone(s),
+            Some(Value::Int(n)) => n.to_string().into(),
+            Some(Value::Float(x)) => x.to_string().into(),
+            Some(_) => bail!("the value of `{key}` at list index {i} is not a string or number"),
+        };
+
+        if !(first.unwrap_or_default() && output.contains_key(&index)) {
+            output.insert(index, Value::Structure(item));
+        }
+    }
+
+    Ok(Value::Structure(output))
+}
+
+/// Signature: `value: List | group_consecutive(by: String?): List<List>`
+///
+/// Splits `value` into runs of equal adjacent elements, returning a list of the
+/// runs in order, like for finding the rows under each repeated heading.  Unlike
+/// grouping, equal elements that are not next to each other are in different
+/// runs.
+///
+/// If `by` is set, the elements must be structures, and they are compared by
+/// their value at the key `by` instead of as a whole.  A missing value is the
+/// same as `null`.
+///
+/// # Examples
+///
+/// - `[1, 1, 2, 1] | group_consecutive()` returns `[[1, 1], [2], [1]]`
+/// - `[{ day: "mon", n: 1 }, { day: "mon", n: 2 }, { day: "tue", n: 3 }] | group_consecutive(by: "day")`
+///   returns `[[{ day: "mon", n: 1 }, { day: "mon", n: 2 }], [{ day: "tue", n: 3 }]]`
+#[filter_fn]
+pub fn group_consecutive<'doc>(
+    value: Vec<PValue<'doc>>,
+    by: Option<Arc<str>>,
+) -> Result<PValue<'doc>> {
+    let mut runs: Vec<(EValue<'doc>, Vec<PValue<'doc>>)> = Vec::new();
+
+    for (i, item) in value.into_iter().map(EValue::from).enumerate() {
+        let key = match &by {
+            Some(by) => match &item {
+                Value::Structure(x) => x.get(by).cloned().unwrap_or(Value::Null),
+                other => bail!("expected a structure at list index {i}, got `{other}`"),
+            },
+            None => item.clone(),
+        };
+
+        match runs.last_mut() {
+            Some((last, run)) if *last == key => run.push(item.into()),
+            _ => runs.push((key, vec![item.into()])),
+        }
+    }
+
+    Ok(Value::List(
+        runs.into_iter().map(|(_, run)| Value::List(run)).collect(),
+    ))
+}
+
+/// Signature: `value: List | distinct(by: String?): List`
+///
+/// Returns `value` without the elements that are equal to an earlier element,
+/// keeping the first of each in order.  Unlike `@dedup_key`, this only looks at
+/// this list, not the lists on other pages.
+///
+/// If `by` is set, elements are compared by their value at the path `by` instead
+/// of as a whole, where `by` is keys and list indices separated by `.`, like in
+/// [`get_path`].  Elements where `by` is missing or `null` are always kept.
+///
+/// Raises an error if `by` has an empty segment, like `"a..b"`.
+///
+/// # Examples
+///
+/// - `[1, 2, 1, 3, 2] | distinct()` returns `[1, 2, 3]`
+/// - `[{ id: { n: 1 }, v: "a" }, { id: { n: 1 }, v: "b" }, { v: "c" }] | distinct(by: "id.n")`
+///   returns `[{ id: { n: 1 }, v: "a" }, { v: "c" }]`
+#[filter_fn]
+pub fn distinct<'doc>(value: Vec<PValue<'doc>>, by: Option<Arc<str>>) -> Result<PValue<'doc>> {
+    let segments = by.as_deref().map(path_segments).transpose()?;
+    let mut seen: Vec<EValue<'doc>> = Vec::new();
+    let mut output = Vec::new();
+
+    for item in value.into_iter().map(EValue::from) {
+        let key = match &segments {
+            Some(segments) => match lookup_path(item.clone().into(), segments) {
+                None | Some(Value::Null) => {
+                    output.push(item.into());
+                    continue;
+                }
+                Some(key) => EValue::from(key),
+            },
+            None => item.clone(),
+        };
+
+        if !seen.contains(&key) {
+            seen.push(key);
+            output.push(item.into());
+        }
+    }
+
+    Ok(Value::List(output))
+}
+
+/// Signature: `value: List<Structure> | merge_all(deep: Bool?): Structure`
+///
+/// Merges the structures in `value` into one structure with all of their keys.
+/// If more than one has the same key, the value from the last one is kept.
+///
+/// If `deep` is `true`, structures at the same key are merged too, instead of
+/// the last one replacing the others.  Other values, like lists, are still
+/// replaced.
+///
+/// Raises an error if an element of `value` is not a structure.
+///
+/// # Examples
+///
+/// - `[{ a: 1, b: 2 }, { b: 3 }] | merge_all()` returns `{ a: 1, b: 3 }`
+/// - `[{ a: { x: 1 } }, { a: { y: 2 } }] | merge_all()` returns `{ a: { y: 2 } }`
+/// - `[{ a: { x: 1 } }, { a: { y: 2 } }] | merge_all(deep: true)` returns
+///   `{ a: { x: 1, y: 2 } }`
+/// - `[] | merge_all()` returns `{}`
+#[filter_fn]
+pub fn merge_all<'doc>(value: Vec<PValue<'doc>>, deep: Option<bool>) -> Result<PValue<'doc>> {
+    let deep = deep.unwrap_or_default();
+    let mut output = BTreeMap::new();
+
+    for (i, item) in value.into_iter().enumerate() {
+        let item: Structure = item
+            .try_unwrap()
+            .wrap_with(|| format!("note: occurred at list index {i}"))?;
+        merge_into(
+            &mut output,
+            item.into_iter().map(|(k, v)| (k, v.into())).collect(),
+            deep,
+        );
+    }
+
+    Ok(Value::Structure(
+        output.into_iter().map(|(k, v)| (k, v.into())).collect(),
+    ))
+}
+
+/// Signature: `value | try(filter: String, default: Value?, ...): Value`
+///
+/// Calls the filter named `filter` on `value`, forwarding every other argument
+/// to it.  If that filter raises an error, returns `default` (or `null` if it is
+/// not set) instead, and reports the error as a warning.
+///
+/// Note that the arguments `filter` and `default` are not forwarded, so `try`
+/// cannot wrap a filter that has arguments with those names.
+///
+/// # Examples
+///
+/// - `"12" | try(filter: "int")` returns `12`
+/// - `"twelve" | try(filter: "int")` returns `null`
+/// - `"twelve" | try(filter: "int", default: 0)` returns `0`
+/// - `"a,b" | try(filter: "split", on: ",")` returns `["a", "b"]`
+pub struct Try;
+
+impl FilterDyn for Try {
+    fn apply<'a, 'ast: 'a, 'doc: 'a>(
+        &'a self,
+        value: PValue<'doc>,
+        mut args: BTreeMap<&'ast str, EValue<'doc>>,
+        ctx: &'a mut dyn ElementContextView<'ast, 'doc>,
+    ) -> FilterFuture<'a, 'doc> {
+        Box::pin(async move {
+            let filter: Arc<str> = args
+                .remove("filter")
+                .msg("missing required argument `filter`")?
+                .try_unwrap()?;
+            let default = args.remove("default").unwrap_or(Value::Null);
+
+            let inner = FILTERS
+                .get(&*filter)
+                .with_msg(|| format!("unrecognized filter `{filter}`"))?;
+
+            match inner.apply(value, args, ctx).await {
+                Ok(value) => Ok(value),
+                Err(e) => {
+                    ctx.warn(&format!(
+                        "filter `{filter}` failed, using the default value instead: {e:#}"
+                    ));
+                    Ok(default.into())
+                }
+            }
+        })
+    }
+}
+
+/// Signature: `value | pipe(filters: List<String>): Value`
+///
+/// Calls each filter named in `filters` in order, starting with `value` and
+/// passing each one the result of the last, and returns the result of the last
+/// filter.  This makes it possible to bind a sequence of filters once and reuse it.
+///
+/// Each filter is called without arguments, so `pipe` can only chain filters
+/// that have no required arguments.  To pass arguments, write the filters out
+/// in the statement instead, like `$value | strip() | split(on: ",")`.
+///
+/// An unknown filter name, or a filter that raises an error, will raise an error.
+///
+/// # Examples
+///
+/// - `" 1 4 9 " | pipe(filters: ["strip", "split", "to_int_list"])` returns `[1, 4, 9]`
+/// - `"hi" | pipe(filters: [])` returns `"hi"`
+pub struct Pipe;
+
+impl FilterDyn for Pipe {
+    fn apply<'a, 'ast: 'a, 'doc: 'a>(
+        &'a self,
+        mut value: PValue<'doc>,
+        mut args: BTreeMap<&'ast str, EValue<'doc>>,
+        ctx: &'a mut dyn ElementContextView<'ast, 'doc>,
+    ) -> FilterFuture<'a, 'doc> {
+        Box::pin(async move {
+            let filters: Vec<EValue> = args
+                .remove("filters")
+                .msg("missing required argument `filters`")?
+                .try_unwrap()?;
+            if let Some(arg) = args.keys().next() {
+                bail!("unexpected argument `{arg}`");
+            }
+
+            for (i, filter) in filters.into_iter().enumerate() {
+                let name: Arc<str> = filter.try_unwrap()?;
+                let inner = FILTERS
+                    .get(&*name)
+                    .with_msg(|| format!("unrecognized filter `{name}`"))?;
+
+                value = inner
+                    .apply(value, BTreeMap::new(), ctx)
+                    .await
+                    .wrap_with(|| format!("note: occurred in step {i} (`{name}`) of `pipe`"))?;
+            }
+
+            Ok(value)
+        })
+    }
+}
+
+/// Signature: `value: List | each(filter: String, index: String?, ...): List`
+///
+/// Calls the filter named `filter` on each element of the list `value`, forwarding
+/// every other argument to it, and returns the list of results.  While the filter
+/// runs on an element, the variable named `index` (default: `"index"`) is bound
+/// to the position of the element in the list, starting from zero, so filters
+/// that read variables, like [`var`], can use it.
+///
+/// The variable is only bound while `each` runs: afterwards, it has its old
+/// value again, or is unbound if it had none.  Like in `try`, the arguments
+/// `filter` and `index` are not forwarded.  An unknown filter, or a filter that
+/// raises an error on any element, will raise an error.
+///
+/// # Examples
+///
+/// - `["a", "b"] | each(filter: "var", name: "index")` returns `[0, 1]`
+/// - `["a", "b"] | each(filter: "var", index: "i", name: "i")` returns `[0, 1]`
+/// - `[" a ", "b "] | each(filter: "strip")` returns `["a", "b"]`
+pub struct Each;
+
+impl FilterDyn for Each {
+    fn apply<'a, 'ast: 'a, 'doc: 'a>(
+        &'a self,
+        value: PValue<'doc>,
+        mut args: BTreeMap<&'ast str, EValue<'doc>>,
+        ctx: &'a mut dyn ElementContextView<'ast, 'doc>,
+    ) -> FilterFuture<'a, 'doc> {
+        Box::pin(async move {
+            let filter: Arc<str> = args
+                .remove("filter")
+                .msg("missing required argument `filter`")?
+                .try_unwrap()?;
+            let index: Arc<str> = args
+                .remove("index")
+                .map_or_else(|| Ok(Arc::from("index")), Value::try_unwrap)?;
+            let list: Vec<PValue<'doc>> = value.try_unwrap()?;
+
+            let inner = FILTERS
+                .get(&*filter)
+                .with_msg(|| format!("unrecognized filter `{filter}`"))?;
+
+            let previous = ctx.remove_inner(&index);
+            let mut results = Vec::with_capacity(list.len());
+            let mut error = None;
+            for (i, item) in list.into_iter().enumerate() {
+                let result = match ctx.set(Cow::Owned(index.to_string()), Value::Int(i as i64)) {
+                    Ok(()) => inner
+                        .apply(item, args.clone(), ctx)
+                        .await
+                        .wrap_with(|| format!("note: occurred at list index {i} of `each`")),
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Ok(result) => results.push(result),
+                    Err(e) => {
+                        error = Some(e);
+                        break;
+                    }
+                }
+            }
+
+            ctx.remove_inner(&index);
+            if let Some(previous) = previous {
+                ctx.set(Cow::Owned(index.to_string()), previous)?;
+            }
+
+            match error {
+                Some(e) => Err(e),
+                None => Ok(Value::List(results)),
+            }
+        })
+    }
+}
+
+/// Signature: `value | var(name: String): Value`
+///
+/// Returns the value of the variable named `name`, ignoring `value`.  This is
+/// for reading variables inside filters that call other filters, like [`Each`]
+/// (`each`), where `$name` would be read before the filter runs.
+///
+/// Raises an error if `name` is not bound.
+///
+/// # Examples
+///
+/// - Let `$tmp` be `"abcde"`
+///   - Then `1 | var(name: "tmp")` returns `"abcde"`
+///   - And `1 | var(name: "kitty")` raises an error.
+#[filter_fn(impure)]
+pub fn var<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
+    value: PValue<'doc>,
+    name: Arc<str>,
+    ctx: &mut E,
+) -> Result<PValue<'doc>> {
+    let _ = value;
+    Ok(ctx.get(&name)?.into())
+}
+
+/// Signature: `value: Element | when_present(selector: String, then: String): Value?`
+///
+/// Calls the filter named `then` on `value` if an element inside `value` matches
+/// the CSS selector `selector`, and returns `null` otherwise.  This is for
+/// optional sections of a page, to only run a filter when the section is there.
+///
+/// Like in `pipe`, the filter is called without arguments.
+///
+/// Raises an error if `selector` is not a valid CSS selector, or if `then` is not
+/// a known filter.
+///
+/// # Examples
+///
+/// - Let `element` = `<div class="product" data-sku="a1"><span class="sale">Sale!</span></div>`
+///   - Then `$element | when_present(selector: ".sale", then: "attrs")` is
+///     `{ class: "product", data-sku: "a1" }`
+///   - And `$element | when_present(selector: ".sold-out", then: "attrs")` is `null`
+pub struct WhenPresent;
+
+impl FilterDyn for WhenPresent {
+    fn apply<'a, 'ast: 'a, 'doc: 'a>(
+        &'a self,
+        value: PValue<'doc>,
+        mut args: BTreeMap<&'ast str, EValue<'doc>>,
+        ctx: &'a mut dyn ElementContextView<'ast, 'doc>,
+    ) -> FilterFuture<'a, 'doc> {
+        Box::pin(async move {
+            let source: Arc<str> = args
+                .remove("selector")
+                .msg("missing required argument `selector`")?
+                .try_unwrap()?;
+            let then: Arc<str> = args
+                .remove("then")
+                .msg("missing required argument `then`")?
+                .try_unwrap()?;
+            if let Some(arg) = args.keys().next() {
+                bail!("unexpected argument `{arg}`");
+            }
+
+            let inner = FILTERS
+                .get(&*then)
+                .with_msg(|| format!("unrecognized filter `{then}`"))?;
+            let element: scraper::ElementRef<'doc> = value.try_unwrap()?;
+
+            if element.select(&selector(&source)?).next().is_some() {
+                inner
+                    .apply(EValue::from(element).into(), BTreeMap::new(), ctx)
+                    .await
+            } else {
+                Ok(Value::Null)
+            }
+        })
+    }
+}
+
+/// Signature: `value | sleep(ms: Int): Value`
+///
+/// Waits for `ms` milliseconds, then returns the `value` passed into it.  Other
+/// element blocks can continue running (and sending requests) while waiting.
+///
+/// Negative durations are invalid and will raise an error.
+///
+/// # Examples
+///
+/// - `"hi" | sleep(ms: 500)` returns `"hi"` after half a second
+#[filter_fn(impure)]
+pub async fn sleep<'doc>(value: PValue<'doc>, ms: i64) -> Result<PValue<'doc>> {
+    let ms = u64::try_from(ms).with_msg(|| format!("cannot sleep for {ms} ms"))?;
+
+    tokio::time::sleep(Duration::from_millis(ms)).await;
+    Ok(value)
+}
+
+/// Signature: `value: String | fetch(headers: Structure<String>?): Element`
+///
+/// Fetches the page at the URL `value` and returns the root element of its
+/// document, which can be passed to filters that take an `Element`.  A relative
+/// `value` is resolved against the URL of the current page (or `@base_url`, if it
+/// is set).
+///
+/// Each key and value in `headers` is sent as an extra request header.  A page
+/// fetched with `headers` is always fetched again, even with `@dedup_requests`.
+///
+/// Raises an error if `value` is not a valid URL, a header is not a `String`, or
+/// the page can't be fetched.
+///
+/// # Examples
+///
+/// - `"/about" | fetch() | meta() | take(key: "og:title")` returns the `og:title`
+///   of the `/about` page on the current site
+/// - `"/account" | fetch(headers: { authorization: "Bearer abc" })` fetches the
+///   `/account` page with an `Authorization` header
+#[filter_fn(impure)]
+pub async fn fetch<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
+    value: Arc<str>,
+    headers: Option<BTreeMap<Arc<str>, EValue<'doc>>>,
+    ctx: &mut E,
+) -> Result<PValue<'doc>> {
+    let url = crate::interpreter::resolve_url(ctx.base_url(), &value)?;
+    let element = ctx.fetch(url, request_headers(headers)?).await?;
+
+    Ok(EValue::from(element).into())
+}
+
+/// Converts the `headers` argument of `fetch` or `fetch_all` to `(name, value)`
+/// pairs.
+fn request_headers(
+    headers: Option<BTreeMap<Arc<str>, EValue<'_>>>,
+) -> Result<Vec<(String, String)>> {
+    headers
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, value)| {
+            let value: Arc<str> = value
+                .try_unwrap()
+                .with_msg(|| format!("expected a string for the header `{name}`"))?;
+            Ok((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Signature: `value: List<String> | fetch_all(headers: Structure<String>?, on_error: String?): List<Element>`
+///
+/// Fetches the page at each URL in `value`, like [`fetch`], and returns the list
+/// of the root elements of their documents, in the same order.  The pages are
+/// fetched at the same time, up to `@concurrency` at once and waiting `@delay_ms`
+/// between requests to the same host, like the pages of element blocks.
+///
+/// Each key and value in `headers` is sent with every request.  `on_error` is
+/// what to do when a URL is invalid or its page can't be fetched:
+///
+/// - `"raise"` (the default): raise the first error.
+/// - `"null"`: put `null` in the list instead.
+/// - `"record"`: put a structure `{ url, error }` in the list instead, with the
+///   URL and the error message.
+///
+/// # Examples
+///
+/// - `["/a", "/b"] | fetch_all() | each(filter: "texts", selector: "h1")` returns
+///   the text of the headings on the `/a` and `/b` pages of the current site
+/// - `["/a", "/missing"] | fetch_all(on_error: "null")` returns the root element
+///   of `/a` and `null`
+#[filter_fn(impure)]
+pub async fn fetch_all<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
+    value: Vec<PValue<'doc>>,
+    headers: Option<BTreeMap<Arc<str>, EValue<'doc>>>,
+    on_error: Option<Arc<str>>,
+    ctx: &mut E,
+) -> Result<PValue<'doc>> {
+    let on_error = on_error.as_deref().unwrap_or("raise");
+    if !matches!(on_error, "raise" | "null" | "record") {
+        bail!("unknown `on_error` `{on_error}` (expected `raise`, `null`, or `record`)");
+    }
+    let headers = request_headers(headers)?;
+
+    let ctx = &*ctx;
+    let pages = futures::future::join_all(value.into_iter().map(EValue::from).map(|url| {
+        let headers = headers.clone();
+        async move {
+            let fetched = async {
+                let url: Arc<str> = url.clone().try_unwrap()?;
+                let url = crate::interpreter::resolve_url(ctx.base_url(), &url)?;
+                ctx.fetch(url, headers).await
+            }
+            .await;
+            (url, fetched)
+        }
+    }))
+    .await;
+
+    pages
+        .into_iter()
+        .enumerate()
+        .map(|(i, (url, page))| match page {
+            Ok(element) => Ok(EValue::from(element).into()),
+            Err(e) => match on_error {
+                "null" => Ok(Value::Null),
+                "record" => Ok(Value::Structure(BTreeMap::from([
+                    (Arc::from("url"), PValue::from(url)),
+                    (Arc::from("error"), Value::String(e.to_string().into())),
+                ]))),
+                _ => Err(e).wrap_with(|| format!("note: occurred at list index {i}")),
+            },
+        })
+        .collect::<Result<_>>()
+        .map(Value::List)
+}
+
+/// Signature: `value: String | cookies_from_response(): Structure<String>`
+///
+/// Returns the cookies set by the `Set-Cookie` headers of the last response from
+/// the URL `value` (and of the redirects before it), as a structure of each
+/// cookie's name and value.  A relative `value` is resolved like in `fetch`.
+///
+/// The cookies are already sent with later requests to the same site, so this is
+/// for passing them on some other way, like in a header passed to `fetch`.
+///
+/// Raises an error if nothing has been fetched from `value` yet.
+///
+/// # Examples
+///
+/// - Let the response from `/login` have the header `Set-Cookie: token=abc; Path=/`.
+///   - Then `"/login" | cookies_from_response()` is `{ token: "abc" }`
+#[filter_fn(impure)]
+pub fn cookies_from_response<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
+    value: Arc<str>,
+    ctx: &mut E,
+) -> Result<PValue<'doc>> {
+    let url = crate::interpreter::resolve_url(ctx.base_url(), &value)?;
+
+    Ok(Value::Structure(
+        ctx.response_cookies(&url)?
+            .into_iter()
+            .map(|(name, value)| (name.into(), Value::String(value.into())))
+            .collect(),
+    ))
+}
+
+/// Signature: `value: (String or Element) | content_type(): String?`
+///
+/// Returns the `Content-Type` header of the last response from the URL `value`,
+/// resolved like in `fetch`, or if `value` is not a string (like `$element`), of
+/// the current page.  Returns `null` if the response had no `Content-Type`, or
+/// the page was not fetched over HTTP, like a file.
+///
+/// # Examples
+///
+/// - On a page served with `Content-Type: text/html; charset=utf-8`,
+///   `$element | content_type()` returns `"text/html; charset=utf-8"`
+/// - After `"/api/items" | fetch()`, `"/api/items" | content_type()` returns the
+///   type of that response, like `"application/json"`
+#[filter_fn(impure)]
+pub fn content_type<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
+    value: PValue<'doc>,
+    ctx: &mut E,
+) -> Result<PValue<'doc>> {
+    let url = match value {
+        Value::String(url) => crate::interpreter::resolve_url(ctx.base_url(), &url)?,
+        _ => ctx.url().clone(),
+    };
+
+    Ok(ctx.content_type(&url)?.map(Arc::from).into())
+}
+
+/// Signature: `value: String | resolve_url(base: String?): String`
+///
+/// Resolves the URL `value` against the absolute URL `base`, returning the absolute
+/// URL.  If `base` is not set, `value` is resolved against the URL of the current
+/// page, or `@base_url` if it is set.  An absolute `value` is returned as is.
+///
+/// Raises an error if `value` or `base` is not a valid URL.
+///
+/// # Examples
+///
+/// - `"/about" | resolve_url(base: "https://example.com/blog/")` returns
+///   `"https://example.com/about"`
+/// - On the page `https://example.com/blog/`, `"post.html" | resolve_url()`
+///   returns `"https://example.com/blog/post.html"`
+#[filter_fn]
+pub fn resolve_url<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
+    value: Arc<str>,
+    base: Option<Arc<str>>,
+    ctx: &mut E,
+) -> Result<PValue<'doc>> {
+    let url = match base {
+        Some(base) => {
+            let base = base
+                .parse()
+                .with_msg(|| format!("`{base}` is not a valid absolute URL"))?;
+            crate::interpreter::resolve_url(&base, &value)?
+        }
+        None => crate::interpreter::resolve_url(ctx.base_url(), &value)?,
+    };
+
+    Ok(Value::String(url.as_str().into()))
+}
+
+/// Signature: `value: Element | abs_links(selector: String?): List<String>`
+///
+/// Returns the `href` of each element inside `value` that matches the CSS selector
+/// `selector` (by default, `a[href]`), in document order, resolved against the URL
+/// of the current page, or `@base_url` if it is set.  Matching elements without
+/// an `href` are skipped.
+///
+/// This is like `$element | select(selector: ...)`, then `attr(name: "href")` and
+/// `resolve_url()` on each link.  An invalid `selector` or `href` will raise an
+/// error.
+///
+/// # Examples
+///
+/// - On the page `https://example.com/blog/`, let `element` =
+///   `<p><a href="post.html">a</a><a href="//cdn.com/x">b</a></p>`
+///   - Then `$element | abs_links()` is
+///     `["https://example.com/blog/post.html", "https://cdn.com/x"]`
+#[filter_fn]
+pub fn abs_links<'ast, 'doc, E: ElementContextView<'ast, 'doc> + ?Sized>(
+    value: scraper::ElementRef<'doc>,
+    selector: Option<Arc<str>>,
+    ctx: &mut E,
+) -> Result<PValue<'doc>> {
+    let selector = self::selector(selector.as_ref().unwrap_or(&Arc::from("a[href]")))?;
+
+    value
+        .select(&selector)
+        .filter_map(|link| link.attr("href"))
+        .map(|href| {
+            crate::interpreter::resolve_url(ctx.base_url(), href)
+                .map(|url| Value::String(url.as_str().into()))
+        })
+        .collect::<Result<_>>()
+        .map(Value::List)
+}
+
+/// Signature: `value: String | normalize_url(): String`
+///
+/// Returns the canonical form of the absolute URL `value`, so that URLs of the
+/// same page compare equal: the scheme and host are lowercased, the default port
+/// is removed, `.` and `..` path segments are resolved, the query parameters are
+/// sorted by name (keeping the order of parameters with the same name), and the
+/// fragment is removed.
+///
+/// Raises an error if `value` is not a valid absolute URL.
+///
+/// # Examples
+///
+/// - `"HTTPS://Example.com:443/a/./b/../c?z=1&a=2#top" | normalize_url()` returns
+///   `"https://example.com/a/c?a=2&z=1"`
+/// - `"https://example.com/?" | normalize_url()` returns `"https://example.com/"`
+#[filter_fn]
+pub fn normalize_url<'doc>(value: Arc<str>) -> Result<PValue<'doc>> {
+    // parsing already lowercases the host, removes the default port, and
+    // resolves dot segments
+    let mut url: reqwest::Url = value
+        .parse()
+        .with_msg(|| format!("`{value}` is not a valid absolute URL"))?;
+    url.set_fragment(None);
+
+    let query = url.query().map(|query| {
+        let mut params: Vec<_> = query.split('&').filter(|x| !x.is_empty()).collect();
+        params.sort_by_key(|x| x.split_once('=').map_or(*x, |(name, _)| name));
+        params.join("&")
+    });
+    url.set_query(query.as_deref().filter(|x| !x.is_empty()));
+
+    Ok(Value::String(url.as_str().into()))
+}
+
+/// Converts a scalar `value` under `key` into the text of a query parameter.
+fn query_value(key: &str, value: EValue<'_>) -> Result<String> {
+    match value {
+        Value::Null => Ok(String::new()),
+        Value::String(s) => Ok(s.to_string()),
+        Value::Int(_) | Value::Float(_) | Value::Bool(_) => Ok(value.to_string()),
+        other => bail!("cannot encode `{other}` at key `{key}` in a query string"),
+    }
+}
+
+/// Signature: `value: Structure | encode_query(): String`
+///
+/// Encodes a structure of scalar values as a URL query string, sorted by key.
+/// Keys and values are percent-encoded, with spaces encoded as `+`.  A key with a
+/// `List` value is repeated once for each item in the list, and a `null` value is
+/// encoded as an empty string.
+///
+/// Nested structures (and lists of lists) are invalid and will raise an error.
+///
+/// # Examples
+///
+/// - `{ a: 1, b: "two" } | encode_query()` returns `"a=1&b=two"`
+/// - `{ q: "fish & chips" } | encode_query()` returns `"q=fish+%26+chips"`
+/// - `{ tag: ["a", "b"] } | encode_query()` returns `"tag=a&tag=b"`
+#[filter_fn]
+pub fn encode_query<'doc>(value: Structure<'doc>) -> Result<PValue<'doc>> {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+
+    for (key, value) in value {
+        match EValue::from(value) {
+            Value::List(list) => {
+                for item in list {
+                    query.append_pair(&key, &query_value(&key, item)?);
+                }
+            }
+            value => {
+                query.append_pair(&key, &query_value(&key, value)?);
+            }
+        }
+    }
+
+    Ok(Value::String(query.finish().into()))
+}
+
+/// Signature: `value: Structure | to_csv_row(columns: List<String>?): String`
+///
+/// Formats the values of a structure as one line of CSV, without a trailing
+/// newline, like for writing a row at a time.  The values are in the order of
+/// `columns`, if it is set, with an empty field for a column the structure
+/// doesn't have, and otherwise in the order of the structure's keys.
+///
+/// A field that has a comma, a double quote, or a line break is quoted, with
+/// each double quote in it doubled.  A `null` value is an empty field.  Lists,
+/// structures, and elements are invalid and will raise an error.
+///
+/// # Examples
+///
+/// - `{ name: "cat", price: 5 } | to_csv_row()` returns `"cat,5"`
+/// - `{ name: "cat", price: 5 } | to_csv_row(columns: ["price", "name", "age"])`
+///   returns `"5,cat,"`
+/// - `{ quote: "say \"hi\", cat" } | to_csv_row()` returns `"\"say \"\"hi\"\", cat\""`
+#[filter_fn]
+pub fn to_csv_row<'doc>(
+    mut value: Structure<'doc>,
+    columns: Option<Vec<EValue<'doc>>>,
+) -> Result<PValue<'doc>> {
+    let values: Vec<_> = match columns {
+        Some(columns) => columns
+            .into_iter()
+            .map(|column| {
+                let column = column
+                    .try_unwrap::<Arc<str>>()
+                    .msg("expected each column to be a string")?;
+                let value = value.remove(&column).unwrap_or(Value::Null);
+                Ok((column, value))
+            })
+            .collect::<Result<_>>()?,
+        None => value.into_iter().collect(),
+    };
+
+    let fields = values
+        .into_iter()
+        .map(|(key, value)| {
+            let field = match EValue::from(value) {
+                Value::Null => String::new(),
+                Value::String(s) => s.to_string(),
+                value @ (Value::Int(_) | Value::Float(_) | Value::Bool(_)) => value.to_string(),
+                other => bail!("cannot put `{other}` at key `{key}` in a CSV row"),
+            };
+
+            Ok(if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Value::String(fields.join(",").into()))
+}
+
+/// Signature: `value: String | decode_query(): Structure`
+///
+/// Decodes a URL query string (with or without a leading `?`) into a structure of
+/// its percent-decoded parameters, with each `+` decoded as a space.  A key that
+/// appears more than once maps to a `List` of its values, in order, and a key
+/// without a value (like `flag` in `?flag&a=1`) maps to an empty string.
+///
+/// This is the inverse of [`encode_query`].
+///
+/// # Examples
+///
+/// - `"?a=1&b=two" | decode_query()` returns `{ a: "1", b: "two" }`
+/// - `"q=fish+%26+chips" | decode_query()` returns `{ q: "fish & chips" }`
+/// - `"tag=a&tag=b&flag" | decode_query()` returns `{ flag: "", tag: ["a", "b"] }`
+#[filter_fn]
+pub fn decode_query<'doc>(value: Arc<str>) -> Result<PValue<'doc>> {
+    let query = value.strip_prefix('?').unwrap_or(&value);
+    let mut output = Structure::new();
+
+    for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+        let value = Value::String(Arc::from(value));
+        match output.entry(Arc::from(key)) {
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+            Entry::Occupied(mut entry) => match entry.get_mut() {
+                Value::List(list) => list.push(value),
+                first => {
+                    let first = std::mem::replace(first, Value::Null);
+                    entry.insert(Value::List(vec![first, value]));
+                }
+            },
+        }
+    }
+
+    Ok(Value::Structure(output))
+}
+
+/// Signature: `value: String | replace_map(map: Structure): String`
+///
+/// Replaces each occurrence of a key of `map` in `value` with the value at that
+/// key, which must be a `String`.  `value` is scanned once from left to right, so
+/// replaced text is never replaced again.  At each position, the longest key
+/// that matches is replaced, so with the keys `"cat"` and `"catalog"`, the text
+/// `"catalog"` is replaced with the value of `"catalog"`, not of `"cat"`.
+///
+/// Empty keys, and values that are not `String`s, are invalid and will raise an error.
+///
+/// # Examples
+///
+/// - `"kitty and puppy" | replace_map(map: { kitty: "cat", puppy: "dog" })` returns
+///   `"cat and dog"`
+/// - `"catalog of cats" | replace_map(map: { cat: "dog", catalog: "list" })` returns
+///   `"list of dogs"`
+#[filter_fn]
+pub fn replace_map<'doc>(
+    value: Arc<str>,
+    map: BTreeMap<Arc<str>, EValue<'doc>>,
+) -> Result<PValue<'doc>> {
+    let mut replacements = map
+        .into_iter()
+        .map(|(key, value)| {
+            if key.is_empty() {
+                bail!("`replace_map` keys must be nonempty");
+            }
+            let value: Arc<str> = value
+                .try_unwrap()
+                .with_msg(|| format!("expected a string to replace `{key}` with"))?;
+            Ok((key, value))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    replacements.sort_by_key(|(key, _)| std::cmp::Reverse(key.len()));
+
+    let mut out = String::with_capacity(value.len());
+    let mut rest = &*value;
+
+    while let Some(c) = rest.chars().next() {
+        match replacements
+            .iter()
+            .find(|(key, _)| rest.starts_with(&**key))
+        {
+            Some((key, replacement)) => {
+                out.push_str(replacement);
+                rest = &rest[key.len()..];
+            }
+            None => {
+                out.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    Ok(Value::String(out.into()))
+}
+
+/// Compiled regular expressions, keyed by their pattern, so that a filter
+/// called on every item of a list only compiles its pattern once.
+static REGEXES: LazyLock<Mutex<HashMap<Arc<str>, Regex>>> = LazyLock::new(Mutex::default);
+
+/// Compiles the regular expression `pattern`, or returns it from the cache
+/// if it was already compiled.
+fn regex(pattern: &Arc<str>) -> Result<Regex> {
+    let mut regexes = REGEXES
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+    if let Some(regex) = regexes.get(pattern) {
+        return Ok(regex.clone());
+    }
+
+    let regex = Regex::new(pattern).with_msg(|| format!("invalid regex `{pattern}`"))?;
+    regexes.insert(Arc::clone(pattern), regex.clone());
+    Ok(regex)
+}
+
+/// Parsed CSS selectors, keyed by their source, like [`REGEXES`].
+static SELECTORS: LazyLock<Mutex<HashMap<Arc<str>, scraper::Selector>>> =
+    LazyLock::new(Mutex::default);
+
+/// Parses the CSS selector `source`, or returns it from the cache if it was
+/// already parsed.
+fn selector(source: &Arc<str>) -> Result<scraper::Selector> {
+    let mut selectors = SELECTORS
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+    if let Some(selector) = selectors.get(source) {
+        return Ok(selector.clone());
+    }
+
+    let selector =
+        scraper::Selector::parse(source).map_err(|e| other!("invalid selector `{source}`: {e}"))?;
+    selectors.insert(Arc::clone(source), selector.clone());
+    Ok(selector)
+}
+
+/// Signature: `value: String | count_matches(pattern: String, regex: Bool?): Int`
+///
+/// Counts the non-overlapping occurrences of `pattern` in `value`, from left to
+/// right.  If `regex` is `true`, `pattern` is a [regular expression](https://docs.rs/regex/latest/regex/#syntax),
+/// otherwise (the default) it is a plain substring.
+///
+/// An empty `pattern` is invalid and will raise an error.
+///
+/// # Examples
+///
+/// - `"banana" | count_matches(pattern: "an")` returns `2`
+/// - `"aaaa" | count_matches(pattern: "aa")` returns `2`, not `3`
+/// - `"a1b22c333" | count_matches(pattern: "[0-9]+", regex: true)` returns `3`
+#[filter_fn]
+pub fn count_matches<'doc>(
+    value: Arc<str>,
+    pattern: Arc<str>,
+    regex: Option<bool>,
+) -> Result<PValue<'doc>> {
+    if pattern.is_empty() {
+        bail!("`count_matches` needs a nonempty pattern");
+    }
+
+    let count = if regex.unwrap_or_default() {
+        self::regex(&pattern)?.find_iter(&value).count()
+    } else {
+        value.matches(&*pattern).count()
+    };
+
+    Ok(Value::Int(i64::try_from(count).msg("too many matches")?))
+}
+
+/// Signature: `value: String | regex_all(pattern: String): List<Structure>`
+///
+/// Returns every non-overlapping match of the [regular expression](https://docs.rs/regex/latest/regex/#syntax)
+/// `pattern` in `value`, from left to right.  Each match is a structure with
+/// the matched text at `match`, the text of each numbered capture group at
+/// `groups`, and the text of each named group at its name.  A group that
+/// didn't take part in the match is `null`.
+///
+/// # Examples
+///
+/// - `"a1 b22" | regex_all(pattern: "[a-z]([0-9]+)")` returns
+///   `[{ match: "a1", groups: ["1"] }, { match: "b22", groups: ["22"] }]`
+/// - `"v2" | regex_all(pattern: "v(?<major>[0-9])")` returns
+///   `[{ match: "v2", groups: ["2"], major: "2" }]`
+/// - `"fish" | regex_all(pattern: "[0-9]")` returns `[]`
+#[filter_fn]
+pub fn regex_all<'doc>(value: Arc<str>, pattern: Arc<str>) -> Result<PValue<'doc>> {
+    let regex = regex(&pattern)?;
+    let text = |x: Option<regex::Match<'_>>| {
+        x.map_or(Value::Null, |x| Value::String(Arc::from(x.as_str())))
+    };
+
+    Ok(Value::List(
+        regex
+            .captures_iter(&value)
+            .map(|captures| {
+                let mut groups = captures.iter();
+                let mut structure = Structure::new();
+                structure.insert(Arc::from("match"), text(groups.next().flatten()));
+                structure.insert(Arc::from("groups"), Value::List(groups.map(text).collect()));
+                for name in regex.capture_names().flatten() {
+                    structure.insert(Arc::from(name), text(captures.name(name)));
+                }
+                Value::Structure(structure)
+            })
+            .collect(),
+    ))
+}
+
+/// Signature: `value: String | redact(pattern: String, mask: String?): String`
+///
+/// Replaces every non-overlapping match of the [regular expression](https://docs.rs/regex/latest/regex/#syntax)
+/// `pattern` in `value` with `mask` (default: `"***"`), like for hiding emails
+/// or tokens in shared output.  `mask` is inserted as is, so `$1` in it is not
+/// a capture group.
+///
+/// # Examples
+///
+/// - `"mail me at a@b.com" | redact(pattern: "[^ @]+@[^ @]+")` returns
+///   `"mail me at ***"`
+/// - `"token=abc123" | redact(pattern: "[0-9]", mask: "#")` returns `"token=abc###"`
+/// - `"fish" | redact(pattern: "[0-9]")` returns `"fish"`
+#[filter_fn]
+pub fn redact<'doc>(
+    value: Arc<str>,
+    pattern: Arc<str>,
+    mask: Option<Arc<str>>,
+) -> Result<PValue<'doc>> {
+    let mask = mask.as_deref().unwrap_or("***");
+    let redacted = regex(&pattern)?.replace_all(&value, regex::NoExpand(mask));
+
+    Ok(Value::String(redacted.into()))
+}
+
+/// Signature: `value: String | if_match(pattern: String, default: Value?): Value`
+///
+/// Returns `value` if the [regular expression](https://docs.rs/regex/latest/regex/#syntax)
+/// `pattern` matches anywhere in it, or `default` (or `null` if it is not set)
+/// otherwise, like for dropping values that don't look right.  Use `^` and `$` in
+/// `pattern` to match the whole string.
+///
+/// # Examples
+///
+/// - `"2024-01-31" | if_match(pattern: "^[0-9]{4}-[0-9]{2}-[0-9]{2}$")` returns `"2024-01-31"`
+/// - `"soon" | if_match(pattern: "^[0-9]{4}-[0-9]{2}-[0-9]{2}$")` returns `null`
+/// - `"soon" | if_match(pattern: "[0-9]", default: "unknown")` returns `"unknown"`
+#[filter_fn]
+pub fn if_match<'doc>(
+    value: Arc<str>,
+    pattern: Arc<str>,
+    default: Option<EValue<'doc>>,
+) -> Result<PValue<'doc>> {
+    if regex(&pattern)?.is_match(&value) {
+        Ok(Value::String(value))
+    } else {
+        Ok(default.map_or(Value::Null, PValue::from))
+    }
+}
+
+macro_rules! build_map {
+    ($(
+        $id: ident,
+    )*) => {
+        [$(
+            (stringify!($id), Box::new($id()) as Box<dyn FilterDyn + Send + Sync>),
+
+        )*]
+    };
+}
+
+pub static FILTERS: LazyLock<BTreeMap<&'static str, Box<dyn FilterDyn + Send + Sync>>> =
+    LazyLock::new(|| {
+        build_map! {
+            dbg,
+            warn,
+            tee,
+            tee_append,
+            var,
+            strip,
+            strip_prefix,
+            strip_suffix,
+            capitalize,
+            to_snake_case,
+            to_camel_case,
+            to_kebab_case,
+            rekey,
+            rename,
+            normalize_keys,
+            take,
+            get_path,
+            attrs,
+            data_attrs,
+            int,
+            float,
+            to_number,
+            parse_duration,
+            to_int_list,
+            to_float_list,
+            scale,
+            number_format,
+            idiv,
+            diff,
+            nth,
+            one,
+            head,
+            tail,
+            sample,
+            range,
+            keys,
+            values,
+            zip_with_keys,
+            transpose,
+            unzip,
+            fill_null,
+            and,
+            or,
+            not,
+            truthy,
+            coerce,
+            split,
+            split_once,
+            lines,
+            unlines,
+            dedent,
+            eq,
+            is_in,
+            is_empty,
+            is_null,
+            is_number,
+            assert,
+            validate,
             text,
+            to_structure,
+            texts,
+            select_nth,
+            first_selector,
+            select_closest_text,
+            attrs_of,
+            strip_tags,
+            count_matches,
+            regex_all,
+            redact,
+            if_match,
+            replace_map,
+            min_by,
+            max_by,
+            sort_by,
+            meta,
+            jsonld,
+            table,
+            cell,
+            flatten_keys,
+            merge_all,
+            pluck,
+            index_by,
+            group_consecutive,
+            distinct,
+            sleep,
+            fetch,
+            fetch_all,
+            cookies_from_response,
+            content_type,
+            resolve_url,
+            abs_links,
+            normalize_url,
+            encode_query,
+            to_csv_row,
+            decode_query,
+        }
+        .into_iter()
+        // `try` and `mod` are keywords, so they can't be identifiers in `build_map!`,
+        // and `pipe`, `when_present`, and `each` call other filters, so they aren't
+        // `#[filter_fn]`s.
+        .chain([
+            ("try", Box::new(Try) as Box<dyn FilterDyn + Send + Sync>),
+            ("mod", Box::new(modulo())),
+            ("pipe", Box::new(Pipe)),
+            ("when_present", Box::new(WhenPresent)),
+            ("each", Box::new(Each)),
+        ])
+        .collect()
+    });
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::interpreter::interpret_string_harness;
+
+    const ITEMS: &str = r#"
+        <ul>
+            <li data-name="cat" data-price="5">cat</li>
+            <li data-name="dog" data-price="3.5">dog</li>
+            <li data-name="fish">fish</li>
+            <li data-name="bird" data-price="3.5">bird</li>
+        </ul>
+    "#;
+
+    #[tokio::test]
+    async fn min_max_by_numeric_key() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            items: li {
+                name: $element | attrs() | take(key: "data-name");
+                price: $element | attrs() | take(key: "data-price") | float()?;
+            }*;
+            cheapest: $items | min_by(key: "price");
+            priciest: $items | max_by(key: "price");
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["cheapest"])?,
+            json!({ "name": "dog", "price": 3.5 })
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["priciest"])?,
+            json!({ "name": "cat", "price": 5.0 })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn min_max_by_string_key() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            items: li {
+                name: $element | attrs() | take(key: "data-name");
+            }*;
+            first: $items | min_by(key: "name");
+            last: $items | max_by(key: "name");
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["first"])?,
+            json!({ "name": "bird" })
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["last"])?,
+            json!({ "name": "fish" })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn min_by_no_key_errors() {
+        let output = interpret_string_harness(
+            r#"
+            items: li {
+                name: $element | attrs() | take(key: "data-name");
+            }*;
+            cheapest: $items | min_by(key: "price");
+            "#,
+            ITEMS,
+        )
+        .await;
+
+        assert!(output.is_err(), "got {output:?}");
+    }
+
+    #[tokio::test]
+    async fn flatten_keys_nested() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            items: li {
+                name: $element | attrs() | take(key: "data-name");
+                attrs: $element | attrs();
+                empty: $element | text() | split(on: "SPLIT") | [item: $item | eq(to: "")];
+            }*;
+            flat: ul {
+                items: $items;
+            } | flatten_keys();
+            slashes: ul {
+                first: $items | nth(i: 0);
+            } | flatten_keys(sep: "/");
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        let flat = serde_json::to_value(&output.0["flat"])?;
+        assert_eq!(flat["items[0].name"], json!("cat"));
+        assert_eq!(flat["items[0].attrs.data-price"], json!("5"));
+        assert_eq!(flat["items[3].name"], json!("bird"));
+        assert_eq!(flat["items[3].empty"], json!([]));
+
+        assert_eq!(
+            serde_json::to_value(&output.0["slashes"])?,
+            json!({
+                "first/name": "cat",
+                "first/attrs/data-name": "cat",
+                "first/attrs/data-price": "5",
+                "first/empty": [],
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn flatten_keys_collision_errors() {
+        let output = interpret_string_harness(
+            r#"
+            flat: ul {
+                a: li {
+                    b: 1;
+                };
+                a-b: 2;
+            } | flatten_keys(sep: "-");
+            "#,
+            ITEMS,
+        )
+        .await;
+
+        let err = format!("{:#}", output.expect_err("flattening should fail"));
+        assert!(err.contains("more than once"), "got {err}");
+    }
+
+    #[tokio::test]
+    async fn try_returns_default_on_error() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            prices: li {
+                price: $element | attrs() | take(key: "data-price") | try(filter: "float");
+            }*;
+            ints: "1 two 3" | split() | try(filter: "int", default: -1)*;
+            forwarded: "a,b" | try(filter: "split", on: ",");
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["prices"])?,
+            json!([{ "price": 5.0 }, { "price": 3.5 }, { "price": null }, { "price": 3.5 }])
+        );
+        assert_eq!(serde_json::to_value(&output.0["ints"])?, json!([1, -1, 3]));
+        assert_eq!(
+            serde_json::to_value(&output.0["forwarded"])?,
+            json!(["a", "b"])
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn try_unknown_filter_errors() {
+        let output = interpret_string_harness(r#"x: 1 | try(filter: "kitty");"#, ITEMS).await;
+
+        assert!(output.is_err(), "got {output:?}");
+    }
+
+    #[tokio::test]
+    async fn warn_collects_diagnostics() -> anyhow::Result<()> {
+        use scrapelect_filter_types::Linked;
+
+        use crate::interpreter::Interpreter;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            items: li {
+                price: $element | attrs() | take(key: "data-price") | warn(msg: "price");
+            }*;
+            missing: "oops" | try(filter: "int");
+            "#,
+        )
+        .parse()?;
+        let html = scraper::Html::parse_document(ITEMS);
+        let interpreter = Interpreter::new();
+
+        interpreter
+            .interpret_block(
+                &program.statements,
+                Linked::new(
+                    html.root_element(),
+                    None,
+                    "file:///tmp/inmemory.html".parse()?,
+                )
+                .with_diagnostics(&interpreter.diagnostics),
+                None,
+                None,
+            )
+            .await?;
+
+        let diagnostics = interpreter.take_diagnostics();
+        let messages: Vec<_> = diagnostics
+            .iter()
+            .map(|x| (&*x.element, &*x.message))
+            .collect();
+
+        assert_eq!(
+            messages[..4],
+            [
+                ("li", r#"price: "5""#),
+                ("li", r#"price: "3.5""#),
+                ("li", "price: null"),
+                ("li", r#"price: "3.5""#),
+            ]
+        );
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[4].0, "html");
+        assert!(messages[4].1.starts_with("filter `int` failed"));
+
+        assert!(interpreter.take_diagnostics().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sleep_waits() -> anyhow::Result<()> {
+        let start = std::time::Instant::now();
+        let output = interpret_string_harness(r#"x: "hi" | sleep(ms: 100);"#, ITEMS).await?;
+
+        assert!(start.elapsed() >= std::time::Duration::from_millis(100));
+        assert_eq!(serde_json::to_value(&output.0["x"])?, json!("hi"));
+
+        let output = interpret_string_harness(r#"x: "hi" | sleep(ms: -1);"#, ITEMS).await;
+        assert!(output.is_err(), "got {output:?}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn async_filters_run_concurrently() -> anyhow::Result<()> {
+        let start = std::time::Instant::now();
+        let output = interpret_string_harness(
+            r#"
+            items: li {
+                name: $element | attrs() | take(key: "data-name") | sleep(ms: 200);
+            }*;
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        // each of the four blocks sleeps at the same time
+        assert!(start.elapsed() < std::time::Duration::from_millis(600));
+        assert_eq!(
+            serde_json::to_value(&output.0["items"])?,
+            json!([{ "name": "cat" }, { "name": "dog" }, { "name": "fish" }, { "name": "bird" }])
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn encode_query_escapes_special_characters() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            query: ul {
+                q: "fish & chips = 100%";
+                page: 2;
+                lang: "français";
+            } | encode_query();
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["query"])?,
+            json!("lang=fran%C3%A7ais&page=2&q=fish+%26+chips+%3D+100%25")
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn encode_query_repeats_list_keys() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            names: "cat dog fish" | split();
+            query: ul {
+                name: $names;
+                sort: "asc";
+            } | encode_query();
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["query"])?,
+            json!("name=cat&name=dog&name=fish&sort=asc")
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn encode_query_nested_errors() {
+        let output = interpret_string_harness(
+            r#"
+            inner: ul {
+                x: 1;
+            };
+            query: ul {
+                inner: $inner;
+            } | encode_query();
+            "#,
+            ITEMS,
+        )
+        .await;
+
+        let err = format!(
+            "{:#}",
+            output.expect_err("nested structure should not encode")
+        );
+        assert!(err.contains("at key `inner`"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn decode_query_flags_and_lists() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            query: "?flag&tag=a&x=1&tag=b&tag=c" | decode_query();
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["query"])?,
+            json!({ "flag": "", "tag": ["a", "b", "c"], "x": "1" })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn decode_query_percent_decodes() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            query: "q=fish+%26+chips+%3D+100%25&lang=fran%C3%A7ais" | decode_query();
+            roundtrip: $query | encode_query() | decode_query();
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        let query = json!({ "lang": "français", "q": "fish & chips = 100%" });
+        assert_eq!(serde_json::to_value(&output.0["query"])?, query);
+        assert_eq!(serde_json::to_value(&output.0["roundtrip"])?, query);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resolve_url_defaults_to_page_url() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            page: "other.html" | resolve_url();
+            base: "/about" | resolve_url(base: "https://example.com/blog/");
+            absolute: "https://a.com/" | resolve_url(base: "https://example.com/");
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "page": "file:///tmp/other.html",
+                "base": "https://example.com/about",
+                "absolute": "https://a.com/",
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resolve_url_uses_base_url_directive() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            @base_url: "https://example.com/blog/";
+            page: "post.html" | resolve_url();
+            nested: li {
+                url: $element | attrs() | take(key: "data-name") | resolve_url();
+            }*;
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["page"])?,
+            json!("https://example.com/blog/post.html")
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["nested"])?[0]["url"],
+            json!("https://example.com/blog/cat")
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn strip_tags_blocks_and_breaks() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            paragraphs: "<p>Fish &amp;   <b>chips</b></p>\n<p>Peas</p>" | strip_tags();
+            breaks: "one<br>two<br/>  three" | strip_tags();
+            attrs: "<div class='x' data-y=\"1 &lt; 2\">Hello, <a href='/w'>world</a>!<script>alert(1)</script></div>" | strip_tags();
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "paragraphs": "Fish & chips\nPeas",
+                "breaks": "one\ntwo\nthree",
+                "attrs": "Hello, world!",
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn one_returns_first_or_null() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            names: li {
+                name: $element | attrs() | take(key: "data-name");
+            }* | [item: $item | take(key: "name") | eq(to: "dog")] | one();
+            empty: "" | split(on: "x") | [item: $item | eq(to: "y")] | one();
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({ "names": { "name": "dog" }, "empty": null })
+        );
+
+        let output = interpret_string_harness(
+            r#"empty: "" | split(on: "x") | [item: $item | eq(to: "y")] | nth(i: 0);"#,
+            ITEMS,
+        )
+        .await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn count_matches_non_overlapping() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            yes: 1 | eq(to: 1);
+            substring: "aaaa" | count_matches(pattern: "aa");
+            regex: "aaaa" | count_matches(pattern: "a{2}", regex: $yes);
+            words: "the cat and the hat" | count_matches(pattern: "\\bthe\\b", regex: $yes);
+            literal: "a.b.c" | count_matches(pattern: ".");
+            none: "fish" | count_matches(pattern: "[0-9]", regex: $yes);
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({ "yes": true, "substring": 2, "regex": 2, "words": 2, "literal": 2, "none": 0 })
+        );
+
+        let output = interpret_string_harness(
+            r#"x: "fish" | count_matches(pattern: "(", regex: 1 | eq(to: 1));"#,
+            ITEMS,
+        )
+        .await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replace_map_longest_key_first() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            map: ul {
+                cat: "dog";
+                catalog: "list";
+                dog: "cat";
+            };
+            overlapping: "catalog of cats and dogs" | replace_map(map: $map);
+            missing: "fish" | replace_map(map: $map);
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["overlapping"])?,
+            json!("list of dogs and cats")
+        );
+        assert_eq!(serde_json::to_value(&output.0["missing"])?, json!("fish"));
+
+        let output = interpret_string_harness(
+            r#"
+            map: ul {
+                cat: 1;
+            };
+            x: "cat" | replace_map(map: $map);
+            "#,
+            ITEMS,
+        )
+        .await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn idiv_and_mod_round_down() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            a: 7 | idiv(n: 2);
+            b: -7 | idiv(n: 2);
+            c: 7 | idiv(n: -2);
+            d: -7 | idiv(n: -2);
+            e: 6 | idiv(n: -2);
+            f: 7 | mod(n: 3);
+            g: -7 | mod(n: 3);
+            h: 7 | mod(n: -3);
+            i: -7 | mod(n: -3);
+            j: -6 | mod(n: 3);
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "a": 3,
+                "b": -4,
+                "c": -4,
+                "d": 3,
+                "e": -3,
+                "f": 1,
+                "g": 2,
+                "h": -2,
+                "i": -1,
+                "j": 0,
+            })
+        );
+
+        for program in ["x: 1 | idiv(n: 0);", "x: 1 | mod(n: 0);"] {
+            assert!(interpret_string_harness(program, ITEMS).await.is_err());
         }
-        .into_iter()
-        .collect()
-    });
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn range_directions() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            default: "" | range(end: 3);
+            from_value: 2 | range(end: 5);
+            stepped: 2 | range(start: 1, end: 10, step: 4);
+            descending: "" | range(start: 3, end: 0);
+            negative_step: "" | range(start: 10, end: 1, step: -3);
+            empty: "" | range(start: 3, end: 3);
+            wrong_way: "" | range(start: 0, end: 3, step: -1);
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "default": [0, 1, 2],
+                "from_value": [2, 3, 4],
+                "stepped": [1, 5, 9],
+                "descending": [3, 2, 1],
+                "negative_step": [10, 7, 4],
+                "empty": [],
+                "wrong_way": [],
+            })
+        );
+
+        let output = interpret_string_harness(r#"x: "" | range(end: 3, step: 0);"#, ITEMS).await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn diff_mixed_numbers() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            lists: $element | jsonld() | nth(i: 0);
+            ints: $lists | take(key: "ints") | diff();
+            mixed: $lists | take(key: "mixed") | diff();
+            one: $lists | take(key: "one") | diff();
+            empty: $lists | take(key: "empty") | diff();
+            "#,
+            r#"<script type="application/ld+json">
+                { "ints": [1, 4, 9, 16], "mixed": [1, 1.5, 3, 2.5], "one": [5], "empty": [] }
+            </script>"#,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "lists": {
+                    "ints": [1, 4, 9, 16],
+                    "mixed": [1, 1.5, 3, 2.5],
+                    "one": [5],
+                    "empty": [],
+                },
+                "ints": [3, 5, 7],
+                "mixed": [0.5, 1.5, -0.5],
+                "one": [],
+                "empty": [],
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn head_tail_sample() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            list: "" | range(end: 10);
+            head: $list | head(n: 3);
+            tail: $list | tail(n: 3);
+            head_all: $list | head(n: 20);
+            tail_all: $list | tail(n: 20);
+            none: $list | head(n: 0);
+            sample: $list | sample(n: 4, seed: 7);
+            same_sample: $list | sample(n: 4, seed: 7);
+            sample_all: $list | sample(n: 20);
+            "#,
+            ITEMS,
+        )
+        .await?;
+        let output = serde_json::to_value(&output.0)?;
+
+        assert_eq!(output["head"], json!([0, 1, 2]));
+        assert_eq!(output["tail"], json!([7, 8, 9]));
+        assert_eq!(output["head_all"], output["list"]);
+        assert_eq!(output["tail_all"], output["list"]);
+        assert_eq!(output["none"], json!([]));
+        assert_eq!(output["sample_all"], output["list"]);
+
+        let sample = output["sample"].as_array().expect("sample is a list");
+        assert_eq!(sample.len(), 4);
+        assert!(sample.windows(2).all(|x| x[0].as_i64() < x[1].as_i64()));
+        assert_eq!(output["sample"], output["same_sample"]);
+
+        let output =
+            interpret_string_harness(r#"x: "" | range(end: 3) | head(n: -1);"#, ITEMS).await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn zip_with_keys_lengths() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            keys: "name,price,color" | split(on: ",");
+            yes: 1 | eq(to: 1);
+            values: "cat,5" | split(on: ",");
+            more_values: "cat,5,black,extra" | split(on: ",");
+            short: $values | zip_with_keys(keys: $keys);
+            long: $more_values | zip_with_keys(keys: $keys);
+            exact: $more_values | zip_with_keys(keys: $more_values, strict: $yes);
+            "#,
+            ITEMS,
+        )
+        .await?;
+        let output = serde_json::to_value(&output.0)?;
+
+        assert_eq!(output["short"], json!({ "name": "cat", "price": "5" }));
+        assert_eq!(
+            output["long"],
+            json!({ "name": "cat", "price": "5", "color": "black" })
+        );
+        assert_eq!(
+            output["exact"],
+            json!({ "cat": "cat", "5": "5", "black": "black", "extra": "extra" })
+        );
+
+        for program in [
+            r#"
+            keys: "name,price,color" | split(on: ",");
+            yes: 1 | eq(to: 1);
+            x: "cat,5" | split(on: ",") | zip_with_keys(keys: $keys, strict: $yes);
+            "#,
+            r#"
+            keys: "name,price,color" | split(on: ",");
+            yes: 1 | eq(to: 1);
+            x: "cat,5,black,extra" | split(on: ",") | zip_with_keys(keys: $keys, strict: $yes);
+            "#,
+        ] {
+            assert!(interpret_string_harness(program, ITEMS).await.is_err());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn transpose_rows() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            items: li {
+                name: $element | attrs() | take(key: "data-name");
+                price: $element | attrs() | take(key: "data-price") | float()?;
+            }*;
+            columns: $items | transpose();
+            rows: $element | jsonld() | nth(i: 0) | transpose();
+            ragged: $element | jsonld() | nth(i: 1) | transpose();
+            ragged_structures: $element | jsonld() | nth(i: 2) | transpose();
+            "#,
+            r#"
+            <ul>
+                <li data-name="cat" data-price="5">cat</li>
+                <li data-name="fish">fish</li>
+            </ul>
+            <script type="application/ld+json">[[1, 2], [3, 4], [5, 6]]</script>
+            <script type="application/ld+json">[[1], [2, 3]]</script>
+            <script type="application/ld+json">[{ "a": 1, "b": 2 }, { "a": 3 }, { "c": 4 }]</script>
+            "#,
+        )
+        .await?;
+        let output = serde_json::to_value(&output.0)?;
+
+        assert_eq!(
+            output["columns"],
+            json!({ "name": ["cat", "fish"], "price": [5.0, null] })
+        );
+        assert_eq!(output["rows"], json!([[1, 3, 5], [2, 4, 6]]));
+        assert_eq!(output["ragged"], json!([[1, 2], [null, 3]]));
+        assert_eq!(
+            output["ragged_structures"],
+            json!({ "a": [1, 3, null], "b": [2, null, null], "c": [null, null, 4] })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn to_number_lists() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            yes: 1 | eq(to: 1);
+            mixed: $element | jsonld() | nth(i: 0);
+            invalid: $element | jsonld() | nth(i: 1);
+            ints: $mixed | to_int_list();
+            floats: $mixed | to_float_list();
+            skipped_ints: $invalid | to_int_list(skip_invalid: $yes);
+            skipped_floats: $invalid | to_float_list(skip_invalid: $yes);
+            "#,
+            r#"
+            <script type="application/ld+json">["1", 2, 3.5, "-4"]</script>
+            <script type="application/ld+json">["1.5", "two", 3, null]</script>
+            "#,
+        )
+        .await?;
+        let output = serde_json::to_value(&output.0)?;
+
+        assert_eq!(output["ints"], json!([1, 2, 3, -4]));
+        assert_eq!(output["floats"], json!([1.0, 2.0, 3.5, -4.0]));
+        assert_eq!(output["skipped_ints"], json!([3]));
+        assert_eq!(output["skipped_floats"], json!([1.5, 3.0]));
+
+        for program in [
+            r#"x: $element | jsonld() | nth(i: 0) | to_int_list();"#,
+            r#"x: $element | jsonld() | nth(i: 0) | to_float_list();"#,
+        ] {
+            let output = interpret_string_harness(
+                program,
+                r#"<script type="application/ld+json">["1", "two"]</script>"#,
+            )
+            .await;
+            assert!(output.is_err(), "{program}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn type_predicates() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            values: $element | jsonld() | nth(i: 0);
+            empty_string: "" | is_empty();
+            blank_string: " " | is_empty();
+            stripped_string: " " | strip() | is_empty();
+            string: "hi" | is_empty();
+            empty_list: $values | take(key: "empty_list") | is_empty();
+            list: $values | take(key: "list") | is_empty();
+            empty_structure: $values | take(key: "empty_structure") | is_empty();
+            structure: $values | take(key: "structure") | is_empty();
+            null: $values | take(key: "null") | is_null();
+            not_null: $values | take(key: "list") | is_null();
+            int: 1 | is_number();
+            float: 1.5 | is_number();
+            numeric_string: "1" | is_number();
+            null_number: $values | take(key: "null") | is_number();
+            "#,
+            r#"<script type="application/ld+json">
+                { "empty_list": [], "list": [null], "empty_structure": {}, "structure": { "a": null }, "null": null }
+            </script>"#,
+        )
+        .await?;
+        let output = serde_json::to_value(&output.0)?;
+
+        for (key, expected) in [
+            ("empty_string", true),
+            ("blank_string", false),
+            ("stripped_string", true),
+            ("string", false),
+            ("empty_list", true),
+            ("list", false),
+            ("empty_structure", true),
+            ("structure", false),
+            ("null", true),
+            ("not_null", false),
+            ("int", true),
+            ("float", true),
+            ("numeric_string", false),
+            ("null_number", false),
+        ] {
+            assert_eq!(output[key], json!(expected), "{key}");
+        }
+
+        let output = interpret_string_harness("x: 0 | is_empty();", ITEMS).await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pipe_chains_filters() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            numbers: "strip split to_int_list diff" | split();
+            chained: " 1 4 9 " | pipe(filters: $numbers);
+            "#,
+            ITEMS,
+        )
+        .await?;
+        assert_eq!(serde_json::to_value(&output.0["chained"])?, json!([3, 5]));
+
+        for program in [
+            r#"
+            filters: "strip kitty" | split();
+            x: " hi " | pipe(filters: $filters);
+            "#,
+            // `take` has a required argument
+            r#"
+            filters: "strip take" | split();
+            x: " hi " | pipe(filters: $filters);
+            "#,
+        ] {
+            assert!(interpret_string_harness(program, ITEMS).await.is_err());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn lines_and_unlines() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            // scrp strings have no `\r` escape, so this has a literal CRLF
+            concat!(
+                "crlf: \"a\r\nb\r\n\r\nc\" | lines();",
+                r#"
+            trailing: "a\nb\n" | lines();
+            empty: "" | lines();
+            joined: "a\nb\n" | lines() | unlines();
+            "#
+            ),
+            ITEMS,
+        )
+        .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0["crlf"])?,
+            json!(["a", "b", "", "c"])
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["trailing"])?,
+            json!(["a", "b"])
+        );
+        assert_eq!(serde_json::to_value(&output.0["empty"])?, json!([]));
+        assert_eq!(serde_json::to_value(&output.0["joined"])?, json!("a\nb"));
+
+        assert!(
+            interpret_string_harness(r#"x: "" | range(end: 2) | unlines();"#, ITEMS)
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn strip_affixes() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            yes: 1 | eq(to: 1);
+            price: "$$4.99" | strip_prefix(fix: "$");
+            no_price: "4.99" | strip_prefix(fix: "$");
+            count: "12 items" | strip_suffix(fix: " items", required: $yes);
+            no_count: "12" | strip_suffix(fix: " items");
+            "#,
+            ITEMS,
+        )
+        .await?;
+        assert_eq!(serde_json::to_value(&output.0["price"])?, json!("$4.99"));
+        assert_eq!(serde_json::to_value(&output.0["no_price"])?, json!("4.99"));
+        assert_eq!(serde_json::to_value(&output.0["count"])?, json!("12"));
+        assert_eq!(serde_json::to_value(&output.0["no_count"])?, json!("12"));
+
+        for program in [
+            r#"yes: 1 | eq(to: 1); x: "4.99" | strip_prefix(fix: "$", required: $yes);"#,
+            r#"yes: 1 | eq(to: 1); x: "12" | strip_suffix(fix: " items", required: $yes);"#,
+        ] {
+            assert!(interpret_string_harness(program, ITEMS).await.is_err());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn capitalize_first_letter() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            words: "hello World" | capitalize();
+            multibyte: "élan vital" | capitalize();
+            expands: "ßtraße" | capitalize();
+            digit: "1st place" | capitalize();
+            empty: "" | capitalize();
+            "#,
+            ITEMS,
+        )
+        .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0["words"])?,
+            json!("Hello World")
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["multibyte"])?,
+            json!("Élan vital")
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["expands"])?,
+            json!("SStraße")
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["digit"])?,
+            json!("1st place")
+        );
+        assert_eq!(serde_json::to_value(&output.0["empty"])?, json!(""));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn number_format_separators() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            large: 1234567890 | number_format();
+            small: 999 | number_format();
+            negative: -1234.567 | number_format(decimals: 2);
+            negative_int: -1234567 | number_format(decimals: 1);
+            rounded_to_zero: -0.001 | number_format(decimals: 2);
+            float: 12345.25 | number_format();
+            european: 1234567.5 | number_format(decimals: 2, thousands_sep: ".", decimal_sep: ",");
+            no_sep: 1234567 | number_format(thousands_sep: "");
+            "#,
+            ITEMS,
+        )
+        .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "large": "1,234,567,890",
+                "small": "999",
+                "negative": "-1,234.57",
+                "negative_int": "-1,234,567.0",
+                "rounded_to_zero": "0.00",
+                "float": "12,345.25",
+                "european": "1.234.567,50",
+                "no_sep": "1234567",
+            })
+        );
+
+        assert!(
+            interpret_string_harness("x: 1 | number_format(decimals: -1);", ITEMS)
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn when_present_errors() -> anyhow::Result<()> {
+        for program in [
+            r#"x: $element | when_present(selector: "li[", then: "attrs");"#,
+            r#"x: $element | when_present(selector: "li", then: "kitty");"#,
+            r#"x: $element | when_present(selector: "li", then: "attrs", name: "a");"#,
+        ] {
+            assert!(interpret_string_harness(program, ITEMS).await.is_err());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dedent_common_indentation() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            // scrp strings have no `\t` escape, so these have literal tabs
+            concat!(
+                "mixed: \"\t\ta\\n\t  b\" | dedent();",
+                "unrelated: \"\ta\\n    b\" | dedent();",
+                r#"
+            nested: "    fn main() {\n        hi();\n\n    }\n" | dedent();
+            blank_lines: "\n  a\n      \n    b" | dedent();
+            none: "a\n  b" | dedent();
+            "#
+            ),
+            ITEMS,
+        )
+        .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "nested": "fn main() {\n    hi();\n\n}\n",
+                "blank_lines": "\na\n\n  b",
+                "mixed": "\ta\n  b",
+                "unrelated": "\ta\n    b",
+                "none": "a\n  b",
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn assert_conditions() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            yes: 1 | eq(to: 1);
+            last: $element | texts(selector: "li") | nth(i: 3);
+            bird: $last | eq(to: "bird");
+            names: $element | texts(selector: "li") | assert(cond: $bird, msg: "no bird");
+            name: "cat" | assert(non_null: $yes);
+            "#,
+            ITEMS,
+        )
+        .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0["names"])?,
+            json!(["cat", "dog", "fish", "bird"])
+        );
+        assert_eq!(serde_json::to_value(&output.0["name"])?, json!("cat"));
+
+        let failures = [
+            (
+                r#"no: 1 | eq(to: 2); x: 1 | assert(cond: $no, msg: "expected 2 items");"#,
+                "expected 2 items",
+            ),
+            (
+                r#"yes: 1 | eq(to: 1); x: $element | when_present(selector: "b", then: "attrs")? | assert(non_null: $yes);"#,
+                "expected a value, found null",
+            ),
+            (r#"x: 1 | assert(cond: "");"#, "assertion failed"),
+            (r#"x: 1 | assert();"#, "expected `cond` or `non_null`"),
+        ];
+        for (program, message) in failures {
+            let err = interpret_string_harness(program, ITEMS)
+                .await
+                .expect_err(program);
+            assert!(format!("{err:#}").contains(message), "{err:#}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn case_conversions() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            snake: "Some Field Name" | to_snake_case();
+            camel: "Some Field Name" | to_camel_case();
+            kebab: "Some Field Name" | to_kebab_case();
+            acronym: "HTMLParser" | to_snake_case();
+            trailing_acronym: "userID" | to_camel_case();
+            digits: "page2Title" | to_kebab_case();
+            separators: "--a__b  c--" | to_snake_case();
+            row: ul {
+                ProductName: "cat";
+                unitPrice: 5;
+            } | rekey(case: "snake");
+            "#,
+            ITEMS,
+        )
+        .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "snake": "some_field_name",
+                "camel": "someFieldName",
+                "kebab": "some-field-name",
+                "acronym": "html_parser",
+                "trailing_acronym": "userId",
+                "digits": "page2-title",
+                "separators": "a_b_c",
+                "row": { "product_name": "cat", "unit_price": 5 },
+            })
+        );
+
+        for program in [
+            r#"x: ul { a: 1; } | rekey(case: "shouting");"#,
+            r#"x: ul { a_b: 1; aB: 2; } | rekey(case: "snake");"#,
+        ] {
+            assert!(interpret_string_harness(program, ITEMS).await.is_err());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn merge_all_overrides_and_deep() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            yes: 1 | eq(to: 1);
+            items: li {
+                name: $element | text();
+                attrs: $element | attrs();
+            }*;
+            shallow: $items | merge_all();
+            parts: $element | jsonld() | nth(i: 0);
+            replaced: $parts | merge_all();
+            deep: $parts | merge_all(deep: $yes);
+            "#,
+            r#"
+            <ul>
+                <li data-name="cat" data-price="5">cat</li>
+                <li data-name="fish">fish</li>
+            </ul>
+            <script type="application/ld+json">
+                [
+                    { "a": { "x": 1, "y": 1 }, "b": [1], "c": "first" },
+                    { "a": { "y": 2, "z": { "n": 2 } }, "b": [2] }
+                ]
+            </script>
+            "#,
+        )
+        .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0["shallow"])?,
+            json!({ "name": "fish", "attrs": { "data-name": "fish" } })
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["replaced"])?,
+            json!({ "a": { "y": 2, "z": { "n": 2 } }, "b": [2], "c": "first" })
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["deep"])?,
+            json!({ "a": { "x": 1, "y": 2, "z": { "n": 2 } }, "b": [2], "c": "first" })
+        );
+
+        assert!(
+            interpret_string_harness(r#"x: "a b" | split() | merge_all();"#, ITEMS)
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn index_by_collisions_and_missing() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            yes: 1 | eq(to: 1);
+            items: li {
+                name: $element | attrs() | take(key: "data-name");
+                price: $element | attrs() | take(key: "data-price") | float()?;
+            }*;
+            by_name: $items | index_by(key: "name") | keys();
+            last: $items | index_by(key: "price");
+            first: $items | index_by(key: "price", first: $yes);
+            "#,
+            ITEMS,
+        )
+        .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0["by_name"])?,
+            json!(["bird", "cat", "dog", "fish"])
+        );
+        // `fish` has no price, and `dog` and `bird` have the same one
+        assert_eq!(
+            serde_json::to_value(&output.0["last"])?,
+            json!({
+                "5": { "name": "cat", "price": 5.0 },
+                "3.5": { "name": "bird", "price": 3.5 },
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["first"])?["3.5"],
+            json!({ "name": "dog", "price": 3.5 })
+        );
+
+        for program in [
+            r#"x: "a b" | split() | index_by(key: "id");"#,
+            r#"x: li { attrs: $element | attrs(); }* | index_by(key: "attrs");"#,
+        ] {
+            assert!(interpret_string_harness(program, ITEMS).await.is_err());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn truthy_and_not() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            yes: 1 | eq(to: 1);
+            no: 1 | eq(to: 2);
+            values: ul {
+                true: $yes | truthy();
+                false: $no | truthy();
+                zero: 0 | truthy();
+                int: -3 | truthy();
+                zero_float: 0.0 | truthy();
+                float: 0.5 | truthy();
+                empty_string: "" | truthy();
+                string: "0" | truthy();
+                empty_list: "" | split() | truthy();
+                list: "a" | split() | truthy();
+                empty_structure: li { } | truthy();
+                structure: $element | attrs() | truthy();
+                null: $element | attrs() | take(key: "kitty") | truthy();
+                elem: $element | truthy();
+            };
+            not: ul {
+                true: $yes | not();
+                false: $no | not();
+                empty_string: "" | not();
+                list: "a" | split() | not();
+                null: $element | attrs() | take(key: "kitty") | not();
+            };
+            "#,
+            r#"<ul class="x"><li>item</li></ul>"#,
+        )
+        .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0["values"])?,
+            json!({
+                "true": true,
+                "false": false,
+                "zero": false,
+                "int": true,
+                "zero_float": false,
+                "float": true,
+                "empty_string": false,
+                "string": true,
+                "empty_list": false,
+                "list": true,
+                "empty_structure": false,
+                "structure": true,
+                "null": false,
+                "elem": true,
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["not"])?,
+            json!({
+                "true": false,
+                "false": true,
+                "empty_string": true,
+                "list": false,
+                "null": true,
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn and_or_truthiness() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            pets: "cat dog" | split();
+            has_cat: "cat" | is_in(list: $pets);
+            has_fish: "fish" | is_in(list: $pets);
+            cat_and_dog: "dog" | is_in(list: $pets) | and(with: $has_cat);
+            cat_and_fish: $has_cat | and(with: $has_fish);
+            cat_or_fish: $has_fish | or(with: $has_cat);
+            neither: $has_fish | or(with: "");
+            values: "hi" | and(with: $pets);
+            "#,
+            ITEMS,
+        )
+        .await?;
+        assert_eq!(serde_json::to_value(&output.0["cat_and_dog"])?, json!(true));
+        assert_eq!(
+            serde_json::to_value(&output.0["cat_and_fish"])?,
+            json!(false)
+        );
+        assert_eq!(serde_json::to_value(&output.0["cat_or_fish"])?, json!(true));
+        assert_eq!(serde_json::to_value(&output.0["neither"])?, json!(false));
+        assert_eq!(serde_json::to_value(&output.0["values"])?, json!(true));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn regex_all_matches() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            prices: "a1 b22 c" | regex_all(pattern: "([a-z])([0-9]+)?");
+            versions: "v1.2, v3" | regex_all(pattern: "v(?<major>[0-9])(\\.(?<minor>[0-9]))?");
+            none: "fish" | regex_all(pattern: "[0-9]");
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "prices": [
+                    { "match": "a1", "groups": ["a", "1"] },
+                    { "match": "b22", "groups": ["b", "22"] },
+                    { "match": "c", "groups": ["c", null] },
+                ],
+                "versions": [
+                    { "match": "v1.2", "groups": ["1", ".2", "2"], "major": "1", "minor": "2" },
+                    { "match": "v3", "groups": ["3", null, null], "major": "3", "minor": null },
+                ],
+                "none": [],
+            })
+        );
+
+        let output =
+            interpret_string_harness(r#"x: "fish" | regex_all(pattern: "(");"#, ITEMS).await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn split_once_pairs() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            middle: "color: red: dark" | split_once(sep: ": ");
+            start: ": red" | split_once(sep: ": ");
+            end: "color: " | split_once(sep: ": ");
+            absent: "red" | split_once(sep: ": ");
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "middle": ["color", "red: dark"],
+                "start": ["", "red"],
+                "end": ["color", ""],
+                "absent": ["red", null],
+            })
+        );
+
+        for program in [
+            r#"x: "red" | split_once(sep: ": ", required: 1 | eq(to: 1));"#,
+            r#"x: "red" | split_once(sep: "");"#,
+        ] {
+            let output = interpret_string_harness(program, ITEMS).await;
+            assert!(output.is_err(), "{program}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn normalize_url_equivalent() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            a: "HTTPS://Example.COM:443/shop/./items/../list?sort=price&page=2#top" | normalize_url();
+            b: "https://example.com/shop/list?page=2&sort=price" | normalize_url();
+            repeated: "http://a.com:8080/?b=2&a=1&b=1&" | normalize_url();
+            empty_query: "https://a.com/x?" | normalize_url();
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "a": "https://example.com/shop/list?page=2&sort=price",
+                "b": "https://example.com/shop/list?page=2&sort=price",
+                "repeated": "http://a.com:8080/?a=1&b=2&b=1",
+                "empty_query": "https://a.com/x",
+            })
+        );
+
+        let output = interpret_string_harness(r#"x: "/relative" | normalize_url();"#, ITEMS).await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pluck_fields() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            yes: 1 | eq(to: 1);
+            attrs: li { attrs: $element | attrs(); }* | pluck(key: "attrs");
+            names: $attrs | pluck(key: "data-name");
+            prices: $attrs | pluck(key: "data-price");
+            present: $attrs | pluck(key: "data-price", skip_missing: $yes);
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["names"])?,
+            json!(["cat", "dog", "fish", "bird"])
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["prices"])?,
+            json!(["5", "3.5", null, "3.5"])
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["present"])?,
+            json!(["5", "3.5", "3.5"])
+        );
+
+        let output =
+            interpret_string_harness(r#"x: "a b" | split() | pluck(key: "id");"#, ITEMS).await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rename_keys() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            item: li {
+                name: $element | attrs() | take(key: "data-name");
+                price: $element | attrs() | take(key: "data-price");
+            };
+            names: ul {
+                name: "title";
+                price: "name";
+                missing: "other";
+            };
+            renamed: $item | rename(map: $names);
+            "#,
+            ITEMS,
+        )
+        .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0["renamed"])?,
+            json!({ "title": "cat", "name": "5" })
+        );
+
+        let output = interpret_string_harness(
+            r#"
+            item: li {
+                name: $element | attrs() | take(key: "data-name");
+                price: $element | attrs() | take(key: "data-price");
+            };
+            names: ul {
+                price: "name";
+            };
+            renamed: $item | rename(map: $names);
+            "#,
+            ITEMS,
+        )
+        .await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_path_missing_and_null() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            items: li {
+                name: $element | attrs() | take(key: "data-name");
+                price: $element | attrs() | take(key: "data-price");
+            }*;
+            shop: ul {
+                items: $items;
+                owner: $element | attrs() | take(key: "data-owner");
+            };
+            name: $shop | get_path(path: "items.1.name");
+            null: $shop | get_path(path: "owner", default: "nobody");
+            missing_key: $shop | get_path(path: "items.2.color", default: "free");
+            null_price: $shop | get_path(path: "items.2.price", default: "free");
+            missing_index: $shop | get_path(path: "items.9.name", default: "none");
+            no_default: $shop | get_path(path: "items.0.color");
+            through_string: $shop | get_path(path: "items.0.name.x", default: 0);
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        let output = serde_json::to_value(&output.0)?;
+        assert_eq!(output["name"], json!("dog"));
+        // `owner` is there but `null`, so `default` isn't used
+        assert_eq!(output["null"], json!(null));
+        assert_eq!(output["missing_key"], json!("free"));
+        assert_eq!(output["null_price"], json!(null));
+        assert_eq!(output["missing_index"], json!("none"));
+        assert_eq!(output["no_default"], json!(null));
+        assert_eq!(output["through_string"], json!(0));
+
+        let output =
+            interpret_string_harness(r#"x: li { }* | get_path(path: "0..a");"#, ITEMS).await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unzip_pairs() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            pairs: li {
+                name: $element | attrs() | take(key: "data-name");
+                price: $element | attrs() | take(key: "data-price");
+            }* | transpose() | values() | transpose();
+            unzipped: $pairs | unzip();
+            empty: "" | split() | unzip();
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["unzipped"])?,
+            json!([["cat", "dog", "fish", "bird"], ["5", "3.5", null, "3.5"]])
+        );
+        assert_eq!(serde_json::to_value(&output.0["empty"])?, json!([[], []]));
+
+        for program in [
+            r#"x: "a b" | split() | unzip();"#,
+            r#"x: li { }* | transpose() | unzip();"#,
+        ] {
+            let output = interpret_string_harness(program, ITEMS).await;
+            assert!(output.is_err(), "{program}");
+        }
+
+        // each of the lists has four elements
+        let output = interpret_string_harness(
+            r#"x: li { a: 1; }* | transpose() | values() | unzip();"#,
+            ITEMS,
+        )
+        .await;
+        let message = format!("{:?}", output.expect_err("not pairs"));
+        assert!(message.contains("at list index 0"), "{message}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fill_null_shallow_and_deep() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            yes: 1 | eq(to: 1);
+            prices: li { attrs: $element | attrs(); }* | pluck(key: "attrs")
+                | pluck(key: "data-price") | fill_null(with: 0);
+            shop: ul {
+                owner: $element | attrs() | take(key: "data-owner");
+                item: li {
+                    price: $element | attrs() | take(key: "data-missing");
+                };
+            };
+            shallow: $shop | fill_null(with: "none");
+            deep: $shop | fill_null(with: "none", deep: $yes);
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["prices"])?,
+            json!(["5", "3.5", 0, "3.5"])
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["shallow"])?,
+            json!({ "owner": "none", "item": { "price": null } })
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["deep"])?,
+            json!({ "owner": "none", "item": { "price": "none" } })
+        );
+
+        let output = interpret_string_harness(r#"x: "a" | fill_null(with: 0);"#, ITEMS).await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scale_range_and_constant() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            yes: 1 | eq(to: 1);
+            prices: li { attrs: $element | attrs(); }* | pluck(key: "attrs")
+                | pluck(key: "data-price");
+            scaled: $prices | to_float_list(skip_invalid: $yes) | scale();
+            skipped: $prices | scale(skip_invalid: $yes);
+            range: "10 15 20" | split() | to_int_list() | scale();
+            constant: "2 2 2" | split() | to_int_list() | scale();
+            one: "2 2" | split() | to_int_list() | scale(constant: 1);
+            empty: "" | split() | scale();
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["scaled"])?,
+            json!([1.0, 0.0, 0.0])
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["range"])?,
+            json!([0.0, 0.5, 1.0])
+        );
+        // the prices are strings, so they are all left out
+        assert_eq!(serde_json::to_value(&output.0["skipped"])?, json!([]));
+        assert_eq!(
+            serde_json::to_value(&output.0["constant"])?,
+            json!([0.0, 0.0, 0.0])
+        );
+        assert_eq!(serde_json::to_value(&output.0["one"])?, json!([1.0, 1.0]));
+        assert_eq!(serde_json::to_value(&output.0["empty"])?, json!([]));
+
+        let output = interpret_string_harness(r#"x: "1 2" | split() | scale();"#, ITEMS).await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn normalize_keys_collisions() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            no: 1 | eq(to: 2);
+            names: ul { a: "  Unit  Price "; b: "unit price"; c: "Name"; };
+            row: ul { a: 1; b: 2; c: "cat"; } | rename(map: $names);
+            normalized: $row | normalize_keys();
+            cased: $row | normalize_keys(lower: $no);
+            untrimmed: $row | normalize_keys(trim: $no);
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        // `"unit price"` sorts after `"  Unit  Price "`, so its value is kept
+        assert_eq!(
+            serde_json::to_value(&output.0["normalized"])?,
+            json!({ "unit price": 2, "name": "cat" })
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["cased"])?,
+            json!({ "Unit Price": 1, "unit price": 2, "Name": "cat" })
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["untrimmed"])?,
+            json!({ " unit price ": 1, "unit price": 2, "name": "cat" })
+        );
+
+        let output = interpret_string_harness(
+            r#"
+            names: ul { a: "Name"; b: "name "; };
+            x: ul { a: 1; b: 2; } | rename(map: $names) | normalize_keys(strict: 1 | eq(to: 1));
+            "#,
+            ITEMS,
+        )
+        .await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn redact_matches() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r##"
+            email: "mail a.b@c.com or d@e.org" | redact(pattern: "[\\w.]+@[\\w.]+");
+            mask: "token=abc123" | redact(pattern: "[0-9]", mask: "#");
+            literal: "id 42" | redact(pattern: "([0-9]+)", mask: "<$1>");
+            none: "fish" | redact(pattern: "[0-9]");
+            "##,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "email": "mail *** or ***",
+                "mask": "token=abc###",
+                "literal": "id <$1>",
+                "none": "fish",
+            })
+        );
+
+        let output = interpret_string_harness(r#"x: "fish" | redact(pattern: "(");"#, ITEMS).await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn to_number_strips_symbols() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            price: "$1,299.00" | to_number();
+            euros: "1.299,00 €" | to_number(decimal_sep: ",");
+            int: " - 1 299 " | to_number();
+            units: "12 kg" | to_number(strip: "kg");
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({ "price": 1299.0, "euros": 1299.0, "int": -1299, "units": 12 })
+        );
+
+        for invalid in [
+            r#"x: "free" | to_number();"#,
+            r#"x: "$" | to_number();"#,
+            r#"x: "12 kg" | to_number();"#,
+            r#"x: "1.5" | to_number(decimal_sep: "");"#,
+        ] {
+            let output = interpret_string_harness(invalid, ITEMS).await;
+            assert!(output.is_err(), "{invalid}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tee_append_accumulates() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            a: 1 | tee_append(into: "seen");
+            b: "two" | tee_append(into: "seen");
+            c: 3 | tee_append(into: "seen") | tee_append(into: "seen");
+            all: $seen;
+            items: li {
+                name: $element | attrs() | take(key: "data-name") | tee_append(into: "seen");
+            }* | pluck(key: "name");
+            after: $seen;
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(serde_json::to_value(&output.0["c"])?, json!(3));
+        assert_eq!(
+            serde_json::to_value(&output.0["all"])?,
+            json!([1, "two", 3, 3])
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["items"])?,
+            json!(["cat", "dog", "fish", "bird"])
+        );
+        // appends inside the block reach the outer `$seen`
+        assert_eq!(
+            serde_json::to_value(&output.0["after"])?,
+            json!([1, "two", 3, 3, "cat", "dog", "fish", "bird"])
+        );
+        assert_eq!(output.0["after"], output.0["seen"]);
+
+        let output = interpret_string_harness(
+            r#"x: 1 | tee(into: "one") | tee_append(into: "one");"#,
+            ITEMS,
+        )
+        .await;
+        assert!(output.is_err());
+
+        let output = interpret_string_harness(
+            r#"
+            x: 1 | tee_append(into: "seen");
+            items: li { e: $element | tee_append(into: "seen"); }*;
+            "#,
+            ITEMS,
+        )
+        .await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sort_by_keys() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            key_price: "data-price desc" | split_once(sep: " ") | tee_append(into: "keys");
+            key_name: "data-name" | tee_append(into: "keys");
+            attrs: li { attrs: $element | attrs(); }* | pluck(key: "attrs");
+            by_price: $attrs | sort_by(keys: $keys) | pluck(key: "data-name");
+            name_keys: $keys | tail(n: 1);
+            by_name: $attrs | sort_by(keys: $name_keys) | pluck(key: "data-name");
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["by_price"])?,
+            json!(["cat", "bird", "dog", "fish"])
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["by_name"])?,
+            json!(["bird", "cat", "dog", "fish"])
+        );
+
+        for program in [
+            r#"keys: "data-name up" | split_once(sep: " ") | tee_append(into: "k"); x: li { a: $element | attrs(); }* | pluck(key: "a") | sort_by(keys: $k);"#,
+            r#"k: "data-name" | tee_append(into: "keys"); none: $keys | head(n: 0); x: li { a: $element | attrs(); }* | pluck(key: "a") | sort_by(keys: $none);"#,
+            r#"k: 1 | tee_append(into: "keys"); x: li { a: $element | attrs(); }* | pluck(key: "a") | sort_by(keys: $keys);"#,
+        ] {
+            let output = interpret_string_harness(program, ITEMS).await;
+            assert!(output.is_err(), "{program}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn each_binds_index() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            names: li { name: $element | attrs() | take(key: "data-name"); }* | pluck(key: "name");
+            indices: $names | each(filter: "var", name: "index");
+            renamed: $names | each(filter: "var", index: "i", name: "i");
+            same: $names | each(filter: "var", name: "names");
+            index: "outer";
+            inner: $names | each(filter: "var", name: "index");
+            after: $index;
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["indices"])?,
+            json!([0, 1, 2, 3])
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["renamed"])?,
+            json!([0, 1, 2, 3])
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["same"])?,
+            json!(vec![["cat", "dog", "fish", "bird"]; 4])
+        );
+        assert_eq!(serde_json::to_value(&output.0["after"])?, json!("outer"));
+        assert!(!output.0.contains_key("i"));
+
+        for program in [
+            r#"x: "a" | split_once(sep: "b") | each(filter: "kitty");"#,
+            r#"x: "a" | split_once(sep: "b") | each(filter: "int");"#,
+            r#"x: "a" | split_once(sep: "b") | each(filter: "var", index: "element", name: "x");"#,
+            r#"x: 1 | var(name: "kitty");"#,
+        ] {
+            let output = interpret_string_harness(program, ITEMS).await;
+            assert!(output.is_err(), "{program}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn validate_schema() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            item: li {
+                name: $element | attrs() | take(key: "data-name");
+                price: $element | attrs() | get_path(path: "data-price") | try(filter: "float");
+                tags: $element | attrs() | values();
+            }* | nth(i: 0);
+            schema: body { name: "string"; price: "number"; tags: "list"; color: "string?"; };
+            name: $item | validate(schema: $schema) | take(key: "name");
+            "#,
+            ITEMS,
+        )
+        .await?;
+        assert_eq!(serde_json::to_value(&output.0["name"])?, json!("cat"));
+
+        for (schema, message) in [
+            (
+                r#"schema: body { name: "string"; color: "string"; };"#,
+                "missing required key `color`",
+            ),
+            (
+                r#"schema: body { name: "int"; price: "number"; };"#,
+                "expected key `name` to have type `int`, got `string`",
+            ),
+            (
+                r#"schema: body { name: "kitty"; };"#,
+                "unknown type `kitty`",
+            ),
+        ] {
+            let program = format!(
+                r#"
+                item: li {{ name: $element | attrs() | take(key: "data-name"); }}* | nth(i: 0);
+                {schema}
+                x: $item | validate(schema: $schema);
+                "#
+            );
+            let program: &'static str = Box::leak(program.into_boxed_str());
+            let error = interpret_string_harness(program, ITEMS)
+                .await
+                .expect_err("invalid structure");
+            assert!(format!("{error:#}").contains(message), "{error:#}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memoize_keeps_impure_filters() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            @memoize: true;
+            first: 1 | tee_append(into: "seen");
+            second: 1 | tee_append(into: "seen");
+            all: $seen;
+            items: li {
+                name: $element | attrs() | take(key: "data-name");
+                again: $element | attrs() | take(key: "data-name");
+            }*;
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(serde_json::to_value(&output.0["all"])?, json!([1, 1]));
+        assert_eq!(
+            serde_json::to_value(&output.0["items"])?,
+            json!([
+                { "name": "cat", "again": "cat" },
+                { "name": "dog", "again": "dog" },
+                { "name": "fish", "again": "fish" },
+                { "name": "bird", "again": "bird" },
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn if_match_filters() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            date: "2024-01-31" | if_match(pattern: "^\\d{4}-\\d{2}-\\d{2}$");
+            not_date: "soon" | if_match(pattern: "^\\d{4}-\\d{2}-\\d{2}$");
+            default: "soon" | if_match(pattern: "[0-9]", default: "unknown");
+            partial: "order 12" | if_match(pattern: "[0-9]+", default: 0);
+            prices: li {
+                price: $element | attrs() | take(key: "data-price") | if_match(pattern: "\\.")?;
+            }* | pluck(key: "price");
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "date": "2024-01-31",
+                "not_date": null,
+                "default": "unknown",
+                "partial": "order 12",
+                "prices": [null, "3.5", null, "3.5"],
+            })
+        );
+
+        let output =
+            interpret_string_harness(r#"x: "fish" | if_match(pattern: "(");"#, ITEMS).await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn group_consecutive_runs() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            repeated: "a b b c c c b" | split();
+            distinct: "a b c" | split();
+            runs: $repeated | group_consecutive();
+            none: $distinct | group_consecutive();
+            priced: li {
+                name: $element | attrs() | take(key: "data-name");
+                priced: $element | attrs() | take(key: "data-price") | truthy();
+            }* | group_consecutive(by: "priced") | each(filter: "pluck", key: "name");
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["runs"])?,
+            json!([["a"], ["b", "b"], ["c", "c", "c"], ["b"]])
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["none"])?,
+            json!([["a"], ["b"], ["c"]])
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["priced"])?,
+            json!([["cat", "dog"], ["fish"], ["bird"]])
+        );
+
+        let output =
+            interpret_string_harness(r#"x: "a b" | split() | group_consecutive(by: "k");"#, ITEMS)
+                .await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn to_csv_row_quotes_fields() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            row: ul {
+                name: "cat, the";
+                quote: "say \"hi\"";
+                lines: "one\ntwo";
+                price: 5;
+                none: $element | attrs() | take(key: "missing");
+            } | to_csv_row();
+            order: "price name age" | split();
+            ordered: ul {
+                name: "cat";
+                price: 3.5;
+            } | to_csv_row(columns: $order);
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["row"])?,
+            json!("\"one\ntwo\",\"cat, the\",,5,\"say \"\"hi\"\"\"")
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["ordered"])?,
+            json!("3.5,cat,")
+        );
+
+        let output = interpret_string_harness(
+            r#"
+            names: "cat dog" | split();
+            row: ul { names: $names; } | to_csv_row();
+            "#,
+            ITEMS,
+        )
+        .await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn coerce_to_each_type() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            int: "12" | coerce(to: "int");
+            truncated: 3.9 | coerce(to: "int");
+            float: "3.5" | coerce(to: "float");
+            from_int: 2 | coerce(to: "float");
+            string: 3.5 | coerce(to: "string");
+            same: "cat" | coerce(to: "string");
+            yes: "cat" | coerce(to: "bool");
+            no: "" | coerce(to: "bool");
+            bool_string: "" | coerce(to: "bool") | coerce(to: "string");
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "int": 12,
+                "truncated": 3,
+                "float": 3.5,
+                "from_int": 2.0,
+                "string": "3.5",
+                "same": "cat",
+                "yes": true,
+                "no": false,
+                "bool_string": "false",
+            })
+        );
+
+        for program in [
+            r#"x: "12" | coerce(to: "date");"#,
+            r#"x: "twelve" | coerce(to: "int");"#,
+            r#"x: "cat dog" | split() | coerce(to: "string");"#,
+        ] {
+            assert!(interpret_string_harness(program, ITEMS).await.is_err());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn distinct_by_path() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            plain: "a b a c b" | split() | distinct();
+            items: li {
+                name: $element | attrs() | take(key: "data-name");
+                offer: ul {
+                    price: $element | attrs() | take(key: "data-price") | try(filter: "float");
+                };
+            }*;
+            by_price: $items | distinct(by: "offer.price") | pluck(key: "name");
+            "#,
+            r#"
+            <li data-name="cat"><ul data-price="5"></ul></li>
+            <li data-name="dog"><ul data-price="3.5"></ul></li>
+            <li data-name="fish"><ul></ul></li>
+            <li data-name="bird"><ul data-price="3.50"></ul></li>
+            <li data-name="frog"><ul></ul></li>
+            "#,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0["plain"])?,
+            json!(["a", "b", "c"])
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["by_price"])?,
+            json!(["cat", "dog", "fish", "frog"])
+        );
+
+        let output =
+            interpret_string_harness(r#"x: "a b" | split() | distinct(by: "a..b");"#, ITEMS).await;
+        assert!(output.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn parse_duration_formats() -> anyhow::Result<()> {
+        let output = interpret_string_harness(
+            r#"
+            colon: "1:30:00" | parse_duration();
+            short: " 4:05 " | parse_duration();
+            units: "1h 30m" | parse_duration();
+            words: "2 Hours, 5 min 10 seconds" | parse_duration();
+            packed: "1d2h3m4s" | parse_duration();
+            iso: "PT1H30M" | parse_duration();
+            iso_days: "P1DT2H" | parse_duration();
+            iso_weeks: "p1w" | parse_duration();
+            "#,
+            ITEMS,
+        )
+        .await?;
+
+        assert_eq!(
+            serde_json::to_value(&output.0)?,
+            json!({
+                "colon": 5400,
+                "short": 245,
+                "units": 5400,
+                "words": 7510,
+                "packed": 93784,
+                "iso": 5400,
+                "iso_days": 93600,
+                "iso_weeks": 604_800,
+            })
+        );
+
+        for duration in [
+            "soon", "", "90", "1:75", "1:2:3:4", "1.5h", "3 weeks", "P", "PT", "P1Y", "PT1M1H",
+            "PT1H1H", "P1H",
+        ] {
+            let program = format!(r#"x: "{duration}" | parse_duration();"#);
+            let program = Box::leak(program.into_boxed_str());
+            assert!(
+                interpret_string_harness(program, ITEMS).await.is_err(),
+                "`{duration}` should be invalid"
+            );
+        }
+
+        Ok(())
+    }
+}
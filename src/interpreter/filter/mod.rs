@@ -2,17 +2,108 @@ pub mod builtin;
 
 use std::collections::BTreeMap;
 
-use scrapelect_filter_types::{bail, ElementContextView};
+use scrapelect_filter_types::{bail, ElementContextView, FilterCall};
 pub use scrapelect_filter_types::{EValue, ElementContext, Filter, FilterDyn, PValue, Result};
 
-pub fn dispatch_filter<'ast, 'doc, E: ElementContextView<'ast, 'doc>>(
-    name: &str,
+pub async fn dispatch_filter<'ast, 'doc, E: ElementContextView<'ast, 'doc>>(
+    name: &'ast str,
     value: PValue<'doc>,
     args: BTreeMap<&'ast str, EValue<'doc>>,
     ctx: &mut E,
 ) -> Result<PValue<'doc>> {
     match builtin::FILTERS.get(name) {
-        Some(filter) => filter.apply(value, args, ctx),
+        Some(filter) => apply_memoized(&**filter, name, value, args, ctx).await,
         None => bail!("unrecognized filter `{name}`"),
     }
 }
+
+/// Applies `filter`, named `name`, to `value`.  If `filter` is pure and `ctx`
+/// memoizes, an equal earlier call in `ctx` is reused instead of calling the
+/// filter again.
+async fn apply_memoized<'ast, 'doc, E: ElementContextView<'ast, 'doc>>(
+    filter: &(dyn FilterDyn + Send + Sync),
+    name: &'ast str,
+    value: PValue<'doc>,
+    args: BTreeMap<&'ast str, EValue<'doc>>,
+    ctx: &mut E,
+) -> Result<PValue<'doc>> {
+    if !filter.is_pure() || !ctx.memoizes() {
+        return filter.apply(value, args, ctx).await;
+    }
+
+    let value: EValue = value.into();
+    if let Some(result) = ctx.memoized(name, &value, &args) {
+        return Ok(result.into());
+    }
+
+    let result: EValue = filter
+        .apply(value.clone().into(), args.clone(), ctx)
+        .await?
+        .into();
+    ctx.memoize(FilterCall { name, value, args }, result.clone());
+    Ok(result.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use scrapelect_filter_types::{
+        filter_fn, ElementContext, ElementContextView, Linked, PValue, Result, Value,
+    };
+
+    use super::{apply_memoized, FilterDyn};
+
+    static PURE_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static IMPURE_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    #[filter_fn]
+    fn counted<'doc>(value: i64) -> Result<PValue<'doc>> {
+        PURE_CALLS.fetch_add(1, Ordering::SeqCst);
+        Ok(Value::Int(value * 2))
+    }
+
+    #[filter_fn(impure)]
+    fn counted_impure<'doc>(value: i64) -> Result<PValue<'doc>> {
+        IMPURE_CALLS.fetch_add(1, Ordering::SeqCst);
+        Ok(Value::Int(value * 2))
+    }
+
+    async fn call_twice(
+        filter: &(dyn FilterDyn + Send + Sync),
+        ctx: &mut Linked<'_, '_>,
+    ) -> Result<()> {
+        for _ in 0..2 {
+            let result =
+                apply_memoized(filter, "counted", Value::Int(2), BTreeMap::new(), ctx).await?;
+            assert!(matches!(result, Value::Int(4)));
+        }
+
+        let result = apply_memoized(filter, "counted", Value::Int(3), BTreeMap::new(), ctx).await?;
+        assert!(matches!(result, Value::Int(6)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn memoizes_pure_filters() -> anyhow::Result<()> {
+        let html = scraper::Html::parse_document("<p>hi</p>");
+        let url = "file:///tmp/inmemory.html".parse()?;
+        let mut ctx = Linked::new(html.root_element(), None, url).with_memo(true);
+
+        call_twice(&counted(), &mut ctx).await?;
+        assert_eq!(PURE_CALLS.load(Ordering::SeqCst), 2);
+
+        call_twice(&counted_impure(), &mut ctx).await?;
+        assert_eq!(IMPURE_CALLS.load(Ordering::SeqCst), 3);
+
+        let mut nested = ctx.nest(None, ctx.element());
+        assert!(nested.memoizes());
+        call_twice(&counted(), &mut nested).await?;
+        assert_eq!(PURE_CALLS.load(Ordering::SeqCst), 4);
+
+        Ok(())
+    }
+}
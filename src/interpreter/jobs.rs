@@ -0,0 +1,133 @@
+//! Running a program on a list of pages, like with `--jobs-file`, and collecting
+//! the results of every page into one output.
+
+use std::{borrow::Cow, collections::BTreeMap};
+
+use reqwest::Url;
+use scrapelect_filter_types::{bail, Bindings, MessageExt as _, WrapExt as _};
+
+use crate::frontend::ast::Statement;
+
+use super::{Interpreter, Result};
+
+/// A page to run a program on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    /// The URL of the page to start at.
+    pub url: Url,
+    /// The key of this page's results in the output.  If `None`, it is the URL.
+    pub key: Option<String>,
+}
+
+impl Job {
+    /// The key of this page's results in the output.
+    #[must_use]
+    pub fn key(&self) -> &str {
+        self.key.as_deref().unwrap_or(self.url.as_str())
+    }
+
+    /// Parses a jobs file, which has one job on each line: a URL, optionally
+    /// followed by whitespace and the key to put its results at.  Empty lines
+    /// and lines starting with `#` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if a line has an invalid URL or more than two parts, or
+    /// if two jobs have the same key.
+    pub fn parse_list(text: &str) -> Result<Vec<Self>> {
+        let mut jobs: Vec<Self> = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut parts = line.split_whitespace();
+            let url = parts.next().expect("the line is not empty");
+            let job = Self {
+                url: url
+                    .parse()
+                    .with_msg(|| format!("`{url}` on line {} is not a valid URL", i + 1))?,
+                key: parts.next().map(str::to_owned),
+            };
+            if parts.next().is_some() {
+                bail!("expected a URL and an optional key on line {}", i + 1);
+            }
+            if jobs.iter().any(|x| x.key() == job.key()) {
+                bail!(
+                    "the key `{}` on line {} is used more than once",
+                    job.key(),
+                    i + 1
+                );
+            }
+
+            jobs.push(job);
+        }
+
+        Ok(jobs)
+    }
+}
+
+impl Interpreter {
+    /// Runs `statements` on the page of each job, like [`interpret`](Self::interpret),
+    /// and returns the results of every page at the key of its job.
+    ///
+    /// The jobs run at the same time, so their requests share the limits of this
+    /// interpreter's client, like `@concurrency`.  Items repeated across jobs
+    /// with `@dedup_key` are dropped once they all finish, in the order of
+    /// `jobs`, so the earliest job keeps them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if running the program on any of the pages fails.
+    pub async fn interpret_jobs<'ast>(
+        &self,
+        statements: &[Statement<'ast>],
+        jobs: &[Job],
+    ) -> Result<Bindings<'ast>> {
+        let results = futures::future::try_join_all(jobs.iter().map(|job| async move {
+            self.interpret_run(statements, job.url.clone())
+                .await
+                .wrap_with(|| format!("note: occurred in the job for `{}`", job.url))
+        }))
+        .await?;
+
+        Ok(Bindings(
+            jobs.iter()
+                .zip(results)
+                .map(|(job, bindings)| {
+                    (
+                        Cow::Owned(job.key().to_owned()),
+                        self.dedup_items(bindings).into_value(),
+                    )
+                })
+                .collect::<BTreeMap<_, _>>(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Job;
+
+    #[test]
+    fn parse_jobs() {
+        let jobs =
+            Job::parse_list("# pages to scrape\nhttps://a.com/1\n\n  https://a.com/2   second\n")
+                .expect("invalid jobs");
+        assert_eq!(
+            jobs.iter().map(Job::key).collect::<Vec<_>>(),
+            ["https://a.com/1", "second"]
+        );
+
+        for invalid in [
+            "/relative",
+            "https://a.com/ key extra",
+            "https://a.com/ a\nhttps://b.com/ a",
+            "https://a.com/\nhttps://a.com/",
+        ] {
+            assert!(Job::parse_list(invalid).is_err(), "{invalid}");
+        }
+    }
+}
@@ -1,88 +1,387 @@
-use std::{borrow::Cow, collections::BTreeMap, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{
+        atomic::{self, AtomicU64},
+        Arc, Mutex, PoisonError,
+    },
+    time::Duration,
+};
 
 use execution_mode::ExecutionMode;
+use futures::future::LocalBoxFuture;
 use reqwest::Url;
 use scrapelect_filter_types::{
-    bail, Bindings, EValue, ElementContext, ElementContextView as _, Linked, ListIter, PValue,
-    Value,
+    bail, Bindings, Diagnostic, Diagnostics, EValue, ElementContext, ElementContextView as _,
+    Fetch, FetchFuture, Linked, ListIter, PValue, Seeds, Value,
 };
+use tokio::sync::OnceCell;
+use tracing::Instrument as _;
 
 use crate::frontend::ast::{self, Element, Inline, Leaf, Qualifier, RValue, Statement};
+use documents::Documents;
 
+pub mod check;
+pub mod client;
+pub mod config;
+mod documents;
 mod execution_mode;
 pub mod filter;
+mod jobs;
 mod repl;
+mod xml;
 
 pub use scrapelect_filter_types::{Error, MessageExt, Result, WrapExt};
 
+pub use client::{HttpClient, ProgressHook, Stats};
+pub use config::{Config, ParseMode};
+pub use jobs::Job;
 pub use repl::Repl;
 
+/// The text of each page fetched so far, keyed by its [normalized](normalize_url) URL.
+type PageCache = Mutex<HashMap<Url, Arc<OnceCell<Arc<str>>>>>;
+
 #[derive(Debug)]
 pub struct Interpreter {
-    client: reqwest::Client,
+    client: HttpClient,
+    /// `None` if requests are not deduplicated.
+    pages: Option<PageCache>,
+    diagnostics: Diagnostics,
+    /// Set by `@base_url`.
+    base_url: Option<Url>,
+    /// Set by `@limit`.
+    limit: Option<usize>,
+    /// Set by `@dedup_key`.
+    dedup_key: Option<String>,
+    /// The `(binding, key)` of each item kept in a top-level list by earlier
+    /// runs, with the key serialized as JSON.
+    seen_items: Mutex<HashSet<(String, String)>>,
+    /// Set by `@memoize`.
+    memoize: bool,
+    /// Set by `@parser`.
+    parser: ParseMode,
+    /// Set by `@cookies`, and sent to the host of the starting URL.
+    cookies: Vec<(String, String)>,
+    /// Set by `--quiet`.
+    quiet: bool,
+    /// Set by `--seed`.
+    seeds: Option<Seeds>,
+    /// Set by `--retries-on-empty`.
+    retries_on_empty: u32,
+    /// Set by `--max-depth`.
+    max_depth: Option<usize>,
+    /// How many pages were reused from `pages`.
+    cache_hits: AtomicU64,
+    /// The parsed JSON of each JSON page, keyed by its normalized URL, which
+    /// is bound to `$document` on that page.
+    json_documents: Mutex<HashMap<Url, Value>>,
+}
+
+impl Default for Interpreter {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     #[must_use]
     #[inline]
     pub fn new() -> Self {
-        Self::with_client(
-            reqwest::Client::builder()
-                .user_agent(concat!(
-                    env!("CARGO_PKG_NAME"),
-                    " v",
-                    env!("CARGO_PKG_VERSION")
-                ))
-                .build()
-                .expect("Default client is invalid"),
-        )
+        Self::with_config(&Config::default()).expect("Default client is invalid")
+    }
+
+    /// Creates an interpreter that uses the default client, configured with
+    /// the options in `config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the client can't be built with `config`, like if
+    /// its proxy can't be used.
+    pub fn with_config(config: &Config) -> Result<Self> {
+        let client = HttpClient::configure(HttpClient::default_builder(), config)?
+            .build()
+            .msg("building the HTTP client failed")?;
+
+        Ok(Self {
+            client: HttpClient::new(client, config),
+            pages: config
+                .dedup_requests
+                .unwrap_or_default()
+                .then(PageCache::default),
+            diagnostics: Diagnostics::new(),
+            base_url: config.base_url.clone(),
+            limit: config.limit.map(usize::from),
+            dedup_key: config.dedup_key.clone(),
+            seen_items: Mutex::default(),
+            memoize: config.memoize.unwrap_or_default(),
+            parser: config.parser.unwrap_or_default(),
+            cookies: config.cookies.clone().unwrap_or_default(),
+            quiet: config.quiet.unwrap_or_default(),
+            seeds: config.seed.map(Seeds::new),
+            retries_on_empty: config.retries_on_empty.unwrap_or_default(),
+            max_depth: config.max_depth,
+            cache_hits: AtomicU64::new(0),
+            json_documents: Mutex::default(),
+        })
     }
 
     #[must_use]
     #[inline]
-    pub const fn with_client(client: reqwest::Client) -> Self {
-        Self { client }
+    pub fn with_client(client: reqwest::Client) -> Self {
+        Self {
+            client: HttpClient::new(client, &Config::default()),
+            pages: None,
+            diagnostics: Diagnostics::new(),
+            base_url: None,
+            limit: None,
+            dedup_key: None,
+            seen_items: Mutex::default(),
+            memoize: false,
+            parser: ParseMode::default(),
+            cookies: Vec::new(),
+            quiet: false,
+            seeds: None,
+            retries_on_empty: 0,
+            max_depth: None,
+            cache_hits: AtomicU64::new(0),
+            json_documents: Mutex::default(),
+        }
     }
 
+    /// How long to wait before fetching the starting page again, with
+    /// `--retries-on-empty`.
+    pub const RETRY_ON_EMPTY_DELAY: Duration = Duration::from_millis(500);
+
     #[inline]
     pub async fn interpret<'ast>(
         &self,
         statements: &[Statement<'ast>],
         root_url: Url,
     ) -> Result<Bindings<'ast>> {
+        let bindings = self.interpret_run(statements, root_url).await?;
+        Ok(self.dedup_items(bindings))
+    }
+
+    /// Runs `statements` on the page at `root_url`, fetching it again while the
+    /// result is empty with `--retries-on-empty`.  Repeated items are dropped
+    /// within the run, but not yet against the earlier runs of this interpreter.
+    async fn interpret_run<'ast>(
+        &self,
+        statements: &[Statement<'ast>],
+        root_url: Url,
+    ) -> Result<Bindings<'ast>> {
+        for (name, value) in &self.cookies {
+            self.client.set_cookie(&root_url, name, value);
+        }
+
+        let mut retries = self.retries_on_empty;
+        loop {
+            let bindings = self.interpret_page(statements, root_url.clone()).await?;
+            if retries == 0 || !is_empty(&bindings) {
+                return Ok(bindings);
+            }
+
+            retries -= 1;
+            tracing::info!(url = %root_url, retries, "result is empty, fetching again");
+            if let Some(pages) = &self.pages {
+                pages
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .remove(&normalize_url(&root_url));
+            }
+            tokio::time::sleep(Self::RETRY_ON_EMPTY_DELAY).await;
+        }
+    }
+
+    /// Runs `statements` once on the page at `root_url`.
+    async fn interpret_page<'ast>(
+        &self,
+        statements: &[Statement<'ast>],
+        root_url: Url,
+    ) -> Result<Bindings<'ast>> {
+        // declared before `html` so that fetched documents outlive the contexts
+        let session = Session {
+            interpreter: self,
+            documents: Documents::new(),
+        };
         let html = self.get_html(&root_url).await?;
 
-        self.interpret_block(statements, Linked::new(html.root_element(), None, root_url))
+        let span = tracing::info_span!("interpret", url = %root_url);
+        let ctx = Linked::new(html.root_element(), None, root_url)
+            .with_diagnostics(&self.diagnostics)
+            .with_fetcher(&session)
+            .with_quiet(self.quiet);
+        let seen = Mutex::default();
+        let dedup = self.dedup_key.as_deref().map(|key| Dedup {
+            key,
+            seen: &seen,
+            page: true,
+        });
+        self.interpret_block(statements, self.with_options(ctx), self.limit, dedup)
+            .instrument(span)
             .await
     }
 
+    /// Drops the items of each top-level list in `bindings` whose `@dedup_key`
+    /// field has the same value as an item kept in that list on any page this
+    /// interpreter ran on before.  Repeated items within one run are already
+    /// dropped while it runs.
+    fn dedup_items<'ast>(&self, mut bindings: Bindings<'ast>) -> Bindings<'ast> {
+        let Some(key) = &self.dedup_key else {
+            return bindings;
+        };
+        let mut seen = self
+            .seen_items
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        for (name, value) in &mut bindings.0 {
+            if let Value::List(items) = value {
+                retain_unseen(name, key, items, &mut seen);
+            }
+        }
+
+        bindings
+    }
+
+    /// Attaches the `@base_url` and the `--seed` seeds for its URL, if they were
+    /// set, `@memoize`, and `--max-depth` to the top-level `ctx`.
+    fn with_options<'ast, 'ctx>(&'ctx self, mut ctx: Linked<'ast, 'ctx>) -> Linked<'ast, 'ctx> {
+        ctx = ctx.with_memo(self.memoize).with_max_depth(self.max_depth);
+        if let Some(base_url) = &self.base_url {
+            ctx = ctx.with_base_url(base_url);
+        }
+        if let Some(seeds) = self.seeds {
+            let seeds = seeds.derive(ctx.url.as_str());
+            ctx = ctx.with_seeds(seeds);
+        }
+        ctx
+    }
+
+    /// Calls `hook` with the totals of the requests sent so far after each page
+    /// is fetched, like [`HttpClient::with_progress`].  Pages reused from the
+    /// cache don't call `hook`.
+    #[must_use]
+    pub fn with_progress(mut self, hook: ProgressHook) -> Self {
+        self.client = self.client.with_progress(hook);
+        self
+    }
+
+    /// Removes and returns the warnings reported by filters (like `warn`) in
+    /// the programs run by this interpreter so far, in the order they were
+    /// reported.
+    pub fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.take()
+    }
+
+    /// Returns the totals of the requests sent and the pages reused from the
+    /// cache by this interpreter so far.
+    #[must_use]
+    pub fn stats(&self) -> Stats {
+        Stats {
+            cache_hits: self.cache_hits.load(atomic::Ordering::Relaxed),
+            ..self.client.stats()
+        }
+    }
+
     async fn get_html(&self, url: &Url) -> Result<scraper::Html> {
-        let text = match url.scheme() {
-            "http" | "https" => self
-                .client
-                .get(url.clone())
-                .send()
-                .await
-                .with_msg(|| format!("request to `{url}` failed"))?
-                .text()
-                .await
-                .with_msg(|| format!("retrieving body from `{url} failed"))?,
-            "file" => tokio::fs::read_to_string(url.path())
-                .await
-                .with_msg(|| format!("reading from file `{}` failed", url.path()))?,
-            other => bail!("unknown URL scheme `{other}`"),
+        let Some(pages) = &self.pages else {
+            return self.parse(url, &self.get_text(url, &[]).await?);
         };
 
-        Ok(scraper::Html::parse_document(&text))
+        let cell = Arc::clone(
+            pages
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .entry(normalize_url(url))
+                .or_default(),
+        );
+
+        let mut fetched = false;
+        let text = cell
+            .get_or_try_init(|| async {
+                fetched = true;
+                self.get_text(url, &[]).await.map(Arc::from)
+            })
+            .await?;
+
+        if !fetched {
+            tracing::info!(url = %url, "cache hit");
+            self.cache_hits.fetch_add(1, atomic::Ordering::Relaxed);
+        }
+
+        self.parse(url, text)
+    }
+
+    /// Like `get_html`, but sends the extra request `headers`.  Pages fetched
+    /// with headers are never reused, because the headers may change the page.
+    async fn get_html_with_headers(
+        &self,
+        url: &Url,
+        headers: &[(String, String)],
+    ) -> Result<scraper::Html> {
+        if headers.is_empty() {
+            self.get_html(url).await
+        } else {
+            self.parse(url, &self.get_text(url, headers).await?)
+        }
+    }
+
+    /// Parses the page `text` fetched from `url` into a document, as HTML, XML,
+    /// or JSON depending on `@parser`, or if it is `auto` (the default), on the
+    /// page's `Content-Type`.  A JSON page has an empty document, and its value
+    /// is kept to bind to `$document`.
+    fn parse(&self, url: &Url, text: &str) -> Result<scraper::Html> {
+        let mode = match self.parser {
+            ParseMode::Auto => {
+                ParseMode::for_content_type(self.client.content_type(url).as_deref())
+            }
+            mode => mode,
+        };
+
+        match mode {
+            ParseMode::Auto | ParseMode::Html => Ok(scraper::Html::parse_document(text)),
+            ParseMode::Xml => xml::parse_document(text),
+            ParseMode::Json => {
+                let value: serde_json::Value = serde_json::from_str(text)
+                    .with_msg(|| format!("`{url}` is not a valid JSON document"))?;
+                self.json_documents
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner)
+                    .insert(normalize_url(url), value.into());
+                Ok(scraper::Html::parse_document(""))
+            }
+        }
     }
 
+    async fn get_text(&self, url: &Url, headers: &[(String, String)]) -> Result<String> {
+        match url.scheme() {
+            "http" | "https" => self.client.get_with_headers(url, headers).await,
+            "file" => {
+                tracing::info!(path = url.path(), "reading file");
+                tokio::fs::read(url.path())
+                    .await
+                    .map(|bytes| self.client.decode(&bytes, None))
+                    .with_msg(|| format!("reading from file `{}` failed", url.path()))
+            }
+            other => bail!("unknown URL scheme `{other}`"),
+        }
+    }
+
+    /// Interprets `statements` in `ctx`.  Each element block in `statements`
+    /// selects at most `limit` elements, if it is set.  With `dedup`, repeated
+    /// items are dropped from the lists on this page and the pages it follows.
     async fn interpret_block<'ast, 'ctx, E: ElementContext<'ast, 'ctx>>(
         &self,
         statements: &[Statement<'ast>],
         mut ctx: E,
+        limit: Option<usize>,
+        dedup: Option<Dedup<'_>>,
     ) -> Result<Bindings<'ast>> {
         for statement in statements {
-            self.interpret_statement(statement, &mut ctx).await?;
+            self.interpret_statement(statement, &mut ctx, limit, dedup)
+                .await?;
         }
 
         Ok(ctx.into_bindings())
@@ -92,14 +391,28 @@ impl Interpreter {
         &self,
         statement: &Statement<'ast>,
         ctx: &mut E,
+        limit: Option<usize>,
+        dedup: Option<Dedup<'_>>,
     ) -> Result<()> {
         let inner = || async move {
             let value = match &statement.value {
                 RValue::Leaf(l) => leaf_to_value(ctx, l)?,
-                RValue::Element(e) => Value::from_data(self.interpret_element(e, ctx).await?),
+                RValue::Element(e) => {
+                    Value::from_data(self.interpret_element(e, ctx, limit, dedup).await?)
+                }
             };
 
-            let value = self.apply_filters(value, statement.filters.iter(), ctx)?;
+            let mut value = self
+                .apply_filters(value, statement.filters.iter(), ctx)
+                .await?;
+            if let (Some(dedup), Value::List(items)) = (dedup.filter(|x| x.page), &mut value) {
+                retain_unseen(
+                    statement.id,
+                    dedup.key,
+                    items,
+                    &mut dedup.seen.lock().unwrap_or_else(PoisonError::into_inner),
+                );
+            }
             ctx.set(Cow::Borrowed(statement.id), value)?;
 
             Ok(())
@@ -117,20 +430,24 @@ impl Interpreter {
         &self,
         element: &Element<'ast>,
         ctx: &mut E,
+        limit: Option<usize>,
+        dedup: Option<Dedup<'_>>,
     ) -> Result<Value> {
         let inner = || async move {
             let html;
 
             let (root_element, url) = if let Some(url) = &element.url {
-                let url: Arc<str> = self.eval_inline(url, ctx)?.try_unwrap()?;
-                let url: Url = match url.parse() {
-                    Ok(url) => url,
-                    Err(url::ParseError::RelativeUrlWithoutBase) => ctx
-                        .url()
-                        .join(&url)
-                        .with_msg(|| format!("`{url} is not a valid relative URL"))?,
-                    Err(e) => bail!(@e, "`{url}` is not a valid URL"),
-                };
+                let url: Arc<str> = self.eval_inline(url, ctx).await?.try_unwrap()?;
+                let url = resolve_url(ctx.base_url(), &url)?;
+                if let Some(max) = ctx.max_depth().filter(|&max| ctx.depth() >= max) {
+                    ctx.warn(&format!(
+                        "skipping `{url}`: it is deeper than the maximum depth of {max}"
+                    ));
+                    return Ok(match element.qualifier {
+                        Qualifier::Collection => Value::List(Vec::new()),
+                        _ => Value::Null,
+                    });
+                }
                 html = self.get_html(&url).await?;
                 (html.root_element(), Some(url))
             } else {
@@ -138,15 +455,40 @@ impl Interpreter {
             };
 
             let selector = element.selector.to_scraper();
-            let selection = root_element.select(&selector);
+            // stop selecting at the limit, so we don't do work on (or fetch pages
+            // for) elements that would be dropped
+            let selection = root_element
+                .select(&selector)
+                .take(limit.unwrap_or(usize::MAX));
 
             let element_refs = ExecutionMode::hinted_from_iter(element.qualifier, selection)?;
 
-            let values =
-                futures::future::try_join_all(element_refs.into_iter().map(|element_ref| {
-                    self.interpret_block(&element.statements, ctx.nest(url.clone(), element_ref))
-                }))
-                .await?;
+            let dedup = dedup.map(|x| Dedup {
+                page: url.is_some(),
+                ..x
+            });
+            let blocks = element_refs.into_iter().map(|element_ref| {
+                // this is boxed because it is recursive
+                let block: LocalBoxFuture<'_, Result<Bindings<'ast>>> =
+                    Box::pin(self.interpret_block(
+                        &element.statements,
+                        ctx.nest(url.clone(), element_ref),
+                        None,
+                        dedup,
+                    ));
+                block
+            });
+            // the blocks' pages drop repeated items in the order they finish, so
+            // run them one at a time to keep the earlier ones
+            let values = if dedup.is_some() && follows_pages(element) {
+                let mut values = Vec::new();
+                for block in blocks {
+                    values.push(block.await?);
+                }
+                values
+            } else {
+                futures::future::try_join_all(blocks).await?
+            };
 
             Ok(ExecutionMode::hinted_from_iter(
                 element.qualifier,
@@ -163,81 +505,222 @@ impl Interpreter {
         })
     }
 
-    fn apply_filters<'a, 'ast: 'a, 'ctx, E: ElementContext<'ast, 'ctx>>(
+    async fn apply_filters<'a, 'ast: 'a, 'ctx, E: ElementContext<'ast, 'ctx>>(
         &self,
         value: EValue<'ctx>,
-        mut filters: impl Iterator<Item = &'a ast::Filter<'ast>>,
+        filters: impl Iterator<Item = &'a ast::Filter<'ast>>,
         ctx: &mut E,
     ) -> Result<EValue<'ctx>> {
-        filters
-            .try_fold(value.into(), |value, filter| match &filter.filter {
-                ast::FilterType::Call(call) => {
-                    let args = call
-                        .args
-                        .iter()
-                        .map(|arg| Ok((arg.id, self.eval_inline(&arg.value, ctx)?)))
-                        .collect::<Result<BTreeMap<_, _>>>()?;
-                    qualify(filter.qualifier, value, |value| {
-                        filter::dispatch_filter(call.id, value, args.clone(), ctx)
-                    })
+        let mut value: PValue = value.into();
+
+        for filter in filters {
+            // evaluate the arguments once, even if the filter is called on every
+            // item of a list
+            let mut args = BTreeMap::new();
+            if let ast::FilterType::Call(call) = &filter.filter {
+                for arg in &call.args {
+                    args.insert(arg.id, self.eval_inline(&arg.value, ctx).await?);
                 }
-                ast::FilterType::Select(select) => qualify(filter.qualifier, value, |value| {
-                    let ls: ListIter = value.try_unwrap()?;
-
-                    let mut inner_scope = ctx.nest(None, ctx.element());
-
-                    Ok(Value::List(
-                        ls.map(|value| {
-                            let value = EValue::from(value);
-                            inner_scope.set(select.name.into(), value.clone())?;
-
-                            let keep: bool = self
-                                .eval_inline(&select.value, &mut inner_scope)?
-                                .try_unwrap()?;
-
-                            Ok(keep.then(|| value.into()))
-                        })
-                        .filter_map(Result::transpose)
-                        .collect::<Result<_>>()?,
-                    ))
-                }),
-            })
-            .map(EValue::from)
+            }
+
+            value = match filter.qualifier {
+                Qualifier::One => self.apply_filter(&filter.filter, value, &args, ctx).await?,
+                Qualifier::Optional if matches!(value, Value::Null) => Value::Null,
+                Qualifier::Optional => self.apply_filter(&filter.filter, value, &args, ctx).await?,
+                Qualifier::Collection => {
+                    let mut list = Vec::new();
+                    for item in value.try_unwrap::<ListIter>()? {
+                        list.push(self.apply_filter(&filter.filter, item, &args, ctx).await?);
+                    }
+                    Value::List(list)
+                }
+            };
+        }
+
+        Ok(value.into())
     }
 
-    fn eval_inline<'ast, 'ctx, E: ElementContext<'ast, 'ctx>>(
+    /// Applies a single filter to `value`, with the already-evaluated `args`
+    /// if it is a filter call.
+    async fn apply_filter<'ast, 'ctx, E: ElementContext<'ast, 'ctx>>(
         &self,
-        inline: &Inline<'ast>,
+        filter: &ast::FilterType<'ast>,
+        value: PValue<'ctx>,
+        args: &BTreeMap<&'ast str, EValue<'ctx>>,
         ctx: &mut E,
-    ) -> Result<EValue<'ctx>> {
-        self.apply_filters(
-            leaf_to_value(ctx, &inline.value)?,
-            inline.filters.iter(),
-            ctx,
-        )
+    ) -> Result<PValue<'ctx>> {
+        match filter {
+            ast::FilterType::Call(call) => {
+                filter::dispatch_filter(call.id, value, args.clone(), ctx).await
+            }
+            ast::FilterType::Select(select) => {
+                let ls: ListIter = value.try_unwrap()?;
+
+                let mut inner_scope = ctx.nest(None, ctx.element());
+                let mut kept = Vec::new();
+
+                for value in ls {
+                    let value = EValue::from(value);
+                    inner_scope.set(select.name.into(), value.clone())?;
+
+                    let keep: bool = self
+                        .eval_inline(&select.value, &mut inner_scope)
+                        .await?
+                        .try_unwrap()?;
+
+                    if keep {
+                        kept.push(value.into());
+                    }
+                }
+
+                Ok(Value::List(kept))
+            }
+        }
+    }
+
+    fn eval_inline<'s, 'ast: 's, 'ctx: 's, E: ElementContext<'ast, 'ctx>>(
+        &'s self,
+        inline: &'s Inline<'ast>,
+        ctx: &'s mut E,
+    ) -> LocalBoxFuture<'s, Result<EValue<'ctx>>> {
+        // this is boxed because it is recursive through `apply_filters`
+        Box::pin(async move {
+            let value = leaf_to_value(ctx, &inline.value)?;
+            self.apply_filters(value, inline.filters.iter(), ctx).await
+        })
     }
 }
 
-fn qualify<'doc, F>(
-    qualifier: Qualifier,
-    value: PValue<'doc>,
-    mut action: F,
-) -> Result<PValue<'doc>>
-where
-    F: FnMut(PValue<'doc>) -> Result<PValue<'doc>>,
-{
-    match qualifier {
-        Qualifier::One => action(value),
-        Qualifier::Optional if matches!(value, Value::Null) => Ok(Value::Null),
-        Qualifier::Optional => action(value),
-        Qualifier::Collection => value
-            .try_unwrap::<ListIter>()?
-            .map(action)
-            .collect::<Result<Vec<_>>>()
-            .map(Value::List),
+/// How a block drops repeated items in the lists of one run, with `@dedup_key`.
+#[derive(Debug, Clone, Copy)]
+struct Dedup<'r> {
+    /// The field that identifies an item.
+    key: &'r str,
+    /// The `(binding, key)` of each item kept in the run so far, with the key
+    /// serialized as JSON.
+    seen: &'r Mutex<HashSet<(String, String)>>,
+    /// Whether the block is the root of a page, either the starting page or a
+    /// followed one, so that its lists are deduplicated.
+    page: bool,
+}
+
+/// Drops the `items` of the list bound to `name` whose `key` field has the same
+/// value as an item in `seen`, and adds the kept ones to `seen`.  Items that are
+/// not structures, or don't have the field (or have it set to `null` or an
+/// element), are kept.
+fn retain_unseen<X: Clone>(
+    name: &str,
+    key: &str,
+    items: &mut Vec<Value<X>>,
+    seen: &mut HashSet<(String, String)>,
+) {
+    items.retain(|item| match item {
+        Value::Structure(item) => match item.get(key).cloned().and_then(Value::into_data) {
+            None | Some(Value::Null) => true,
+            Some(x) => seen.insert((
+                name.to_owned(),
+                serde_json::to_string(&x).expect("values serialize to JSON"),
+            )),
+        },
+        _ => true,
+    });
+}
+
+/// Whether the blocks of `element` follow any pages, either because `element`
+/// has a URL or one of the element blocks in it does.
+fn follows_pages(element: &Element<'_>) -> bool {
+    element.url.is_some()
+        || element
+            .statements
+            .iter()
+            .any(|statement| match &statement.value {
+                RValue::Element(element) => follows_pages(element),
+                RValue::Leaf(_) => false,
+            })
+}
+
+/// The state for a single run of a program, which fetches documents for
+/// filters like `fetch` and keeps them alive until the run is over.
+#[derive(Debug)]
+struct Session<'i> {
+    interpreter: &'i Interpreter,
+    documents: Documents,
+}
+
+impl Fetch for Session<'_> {
+    fn fetch(&self, url: Url, headers: Vec<(String, String)>) -> FetchFuture<'_> {
+        Box::pin(async move {
+            let html = self
+                .interpreter
+                .get_html_with_headers(&url, &headers)
+                .await?;
+            Ok(self.documents.push(html).root_element())
+        })
+    }
+
+    fn response_cookies(&self, url: &Url) -> Option<Vec<(String, String)>> {
+        self.interpreter.client.response_cookies(url)
+    }
+
+    fn content_type(&self, url: &Url) -> Option<String> {
+        self.interpreter.client.content_type(url)
+    }
+
+    fn document(&self, url: &Url) -> Option<Value> {
+        self.interpreter
+            .json_documents
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(&normalize_url(url))
+            .cloned()
+    }
+}
+
+/// Returns whether every binding in `bindings` is `null`, or an empty list,
+/// structure, or string, like when a page served an interstitial instead of
+/// its content.
+fn is_empty(bindings: &Bindings<'_>) -> bool {
+    bindings.0.values().all(|value| match value {
+        Value::Null => true,
+        Value::List(x) => x.is_empty(),
+        Value::Structure(x) => x.is_empty(),
+        Value::String(x) => x.is_empty(),
+        _ => false,
+    })
+}
+
+/// Parses `url`, resolving it against `base` if it is a relative URL.
+///
+/// # Errors
+///
+/// Returns an `Err` if `url` is not a valid absolute or relative URL.
+pub fn resolve_url(base: &Url, url: &str) -> Result<Url> {
+    match url.parse() {
+        Ok(url) => Ok(url),
+        Err(url::ParseError::RelativeUrlWithoutBase) => base
+            .join(url)
+            .with_msg(|| format!("`{url}` is not a valid relative URL")),
+        Err(e) => bail!(@e, "`{url}` is not a valid URL"),
     }
 }
 
+/// Normalizes `url` so that URLs that refer to the same page compare equal:
+/// the fragment is removed, and the query parameters are sorted.
+fn normalize_url(url: &Url) -> Url {
+    let mut url = url.clone();
+    url.set_fragment(None);
+
+    let mut pairs: Vec<_> = url.query_pairs().into_owned().collect();
+    if pairs.is_empty() {
+        url.set_query(None);
+    } else {
+        pairs.sort();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+
+    url
+}
+
 pub fn leaf_to_value<'ast, 'ctx, E: ElementContext<'ast, 'ctx>>(
     ctx: &E,
     value: &Leaf<'ast>,
@@ -257,19 +740,22 @@ pub async fn interpret_string_harness(
 ) -> anyhow::Result<Bindings<'static>> {
     use anyhow::Context;
 
-    let statements = crate::frontend::Parser::new(program).parse()?;
+    let program = crate::frontend::Parser::new(program).parse()?;
     let html = scraper::Html::parse_document(html);
-    let statements = Box::leak(Box::new(statements));
-    let interpreter = Interpreter::new();
-    interpreter
+    let program = Box::leak(Box::new(program));
+    let interpreter = Interpreter::with_config(&Config::from_head(&program.head)?)?;
+    let ctx = Linked::new(
+        html.root_element(),
+        None,
         // TODO: url hack
+        "file:///tmp/inmemory.html".parse().expect("URL parse"),
+    );
+    interpreter
         .interpret_block(
-            statements,
-            Linked::new(
-                html.root_element(),
-                None,
-                "file:///tmp/inmemory.html".parse().expect("URL parse"),
-            ),
+            &program.statements,
+            interpreter.with_options(ctx),
+            interpreter.limit,
+            None,
         )
         .await
         .context("Error running interpreter")
@@ -285,26 +771,31 @@ mod tests {
         let input = std::fs::read_to_string(format!("examples/inputs/{filename}.html"))?;
         let script = std::fs::read_to_string(format!("examples/scrps/{filename}.scrp"))?;
 
-        let ast = crate::frontend::Parser::new(&script)
+        let program = crate::frontend::Parser::new(&script)
             .parse()
             .expect("parse error");
 
-        let html = scraper::Html::parse_document(&input);
+        // examples like `recurser` fetch real sites, so don't make them
+        // depend on those sites' `robots.txt` too.
+        let interpreter = super::Interpreter::with_config(&super::Config {
+            ignore_robots: Some(true),
+            ..super::Config::from_head(&program.head)?
+        })?;
+        let url = format!(
+            "file://{}/examples/inputs/{}",
+            std::env::current_dir().expect("get current dir").display(),
+            filename,
+        )
+        .parse()
+        .expect("parse URL failed");
+        let html = interpreter.parse(&url, &input)?;
 
-        let result = super::Interpreter::new()
+        let result = interpreter
             .interpret_block(
-                &ast,
-                Linked::new(
-                    html.root_element(),
-                    None,
-                    format!(
-                        "file://{}/examples/inputs/{}",
-                        std::env::current_dir().expect("get current dir").display(),
-                        filename,
-                    )
-                    .parse()
-                    .expect("parse URL failed"),
-                ),
+                &program.statements,
+                interpreter.with_options(Linked::new(html.root_element(), None, url)),
+                None,
+                None,
             )
             .await?
             .0;
@@ -393,6 +884,871 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_normalize_url() {
+        let normalize = |url: &str| super::normalize_url(&url.parse().expect("invalid URL"));
+
+        assert_eq!(
+            normalize("https://a.com/page?b=2&a=1&b=1#top"),
+            normalize("https://a.com/page?a=1&b=1&b=2"),
+        );
+        assert_eq!(
+            normalize("https://a.com/page?#top").as_str(),
+            "https://a.com/page"
+        );
+        assert_ne!(normalize("https://a.com/page"), normalize("https://a.com/"));
+    }
+
+    #[tokio::test]
+    async fn test_dedup_requests() -> anyhow::Result<()> {
+        use super::client::mock::{MockServer, Response};
+
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            _ => Response::html("<h1>hello</h1>"),
+        })
+        .await;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            @dedup_requests: true;
+            a: <"page.html?x=1&y=2"> h1 {
+                text: $element | text();
+            };
+            b: <"/page.html?y=2&x=1#title"> h1 {
+                text: $element | text();
+            };
+            "#,
+        )
+        .parse()?;
+
+        let interpreter =
+            super::Interpreter::with_config(&super::Config::from_head(&program.head)?)?;
+        let output = interpreter
+            .interpret(&program.statements, server.url("/index.html"))
+            .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0["a"])?,
+            serde_json::to_value(&output.0["b"])?
+        );
+
+        let paths: Vec<_> = server
+            .requests()
+            .into_iter()
+            .map(|x| x.path)
+            .filter(|x| x.starts_with("/page.html"))
+            .collect();
+        assert_eq!(paths, ["/page.html?x=1&y=2"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stats() -> anyhow::Result<()> {
+        use super::client::mock::{MockServer, Response};
+
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            _ => Response::html("<h1>hello</h1>"),
+        })
+        .await;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            @dedup_requests: true;
+            a: <"page.html"> h1 {
+                text: $element | text();
+            };
+            b: <"page.html"> h1 {
+                text: $element | text();
+            };
+            c: <"other.html"> h1 {
+                text: $element | text();
+            };
+            "#,
+        )
+        .parse()?;
+
+        let interpreter =
+            super::Interpreter::with_config(&super::Config::from_head(&program.head)?)?;
+        interpreter
+            .interpret(&program.statements, server.url("/index.html"))
+            .await?;
+
+        let stats = interpreter.stats();
+        assert_eq!(stats.requests, server.requests().len() as u64);
+        assert_eq!(stats.requests, 4);
+        assert_eq!(stats.cache_hits, 1);
+        assert_eq!(stats.failed, 0);
+        assert_eq!(stats.bytes, 3 * "<h1>hello</h1>".len() as u64);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_filter() -> anyhow::Result<()> {
+        use super::client::mock::{MockServer, Response};
+
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            "/index.html" => Response::html(r#"<a href="detail.html">Detail</a>"#),
+            _ => Response::html(r#"<meta property="og:title" content="The detail page">"#),
+        })
+        .await;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            link: a {
+                href: $element | attrs() | take(key: "href");
+                title: $href | fetch() | meta() | take(key: "og:title");
+            };
+            "#,
+        )
+        .parse()?;
+
+        let output = super::Interpreter::new()
+            .interpret(&program.statements, server.url("/index.html"))
+            .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0["link"])?,
+            serde_json::json!({ "href": "detail.html", "title": "The detail page" })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_all_filter() -> anyhow::Result<()> {
+        use super::client::mock::{MockServer, Response};
+
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" | "/missing" => Response::status(404),
+            "/index.html" => Response::html(
+                r#"<a href="a">A</a><a href="/b">B</a><a href="c?page=2">C</a><a href="missing">X</a>"#,
+            ),
+            path => Response::html(format!("<h1>page {}</h1><p>text</p>", &path[1..])),
+        })
+        .await;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            links: a { href: $element | attrs() | take(key: "href"); }* | pluck(key: "href");
+            found: $links | head(n: 3);
+            missing: $links | tail(n: 1);
+            titles: $found | fetch_all() | each(filter: "texts", selector: "h1");
+            nulls: $links | fetch_all(on_error: "null") | each(filter: "truthy");
+            records: $missing | fetch_all(on_error: "record");
+            "#,
+        )
+        .parse()?;
+
+        let interpreter = super::Interpreter::new();
+        let output = interpreter
+            .interpret(&program.statements, server.url("/index.html"))
+            .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0["titles"])?,
+            serde_json::json!([["page a"], ["page b"], ["page c?page=2"]])
+        );
+        assert_eq!(
+            serde_json::to_value(&output.0["nulls"])?,
+            serde_json::json!([true, true, true, false])
+        );
+        let records = serde_json::to_value(&output.0["records"])?;
+        assert_eq!(records[0]["url"], serde_json::json!("missing"));
+        assert!(records[0]["error"]
+            .as_str()
+            .is_some_and(|x| x.contains("404")));
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            links: a { href: $element | attrs() | take(key: "href"); }* | pluck(key: "href");
+            pages: $links | fetch_all();
+            "#,
+        )
+        .parse()?;
+        assert!(interpreter
+            .interpret(&program.statements, server.url("/index.html"))
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_jobs() -> anyhow::Result<()> {
+        use super::client::mock::{MockServer, Response};
+
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            path => Response::html(format!("<h1>{}</h1>", &path[1..])),
+        })
+        .await;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            @concurrency: 1;
+            title: h1 { text: $element | text(); } | take(key: "text");
+            "#,
+        )
+        .parse()?;
+        let jobs = super::Job::parse_list(&format!(
+            "{}\n{} second\n",
+            server.url("/first"),
+            server.url("/second")
+        ))?;
+
+        let interpreter =
+            super::Interpreter::with_config(&super::Config::from_head(&program.head)?)?;
+        let output = interpreter
+            .interpret_jobs(&program.statements, &jobs)
+            .await?;
+        assert_eq!(
+            serde_json::to_value(&output)?,
+            serde_json::json!({
+                server.url("/first").as_str(): { "title": "first" },
+                "second": { "title": "second" },
+            })
+        );
+        assert_eq!(server.max_in_flight(), 1);
+
+        let missing = super::Job::parse_list(server.url("/missing").as_str())?;
+        let program = crate::frontend::Parser::new("x: h2 { a: 1; };").parse()?;
+        assert!(interpreter
+            .interpret_jobs(&program.statements, &missing)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_content_type_dispatch() -> anyhow::Result<()> {
+        use super::client::mock::{MockServer, Response};
+
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            "/api" => Response {
+                headers: vec![("content-type".into(), "application/json".into())],
+                ..Response::html(r#"{ "items": [{ "name": "cat" }, { "name": "dog" }] }"#)
+            },
+            _ => Response::html("<h1>hi</h1>"),
+        })
+        .await;
+
+        let run = |program: &'static str, path: &'static str| {
+            let url = server.url(path);
+            async move {
+                let program = crate::frontend::Parser::new(program).parse()?;
+                let interpreter =
+                    super::Interpreter::with_config(&super::Config::from_head(&program.head)?)?;
+                anyhow::Ok(serde_json::to_value(
+                    interpreter.interpret(&program.statements, url).await?,
+                )?)
+            }
+        };
+
+        let output = run(
+            r#"
+            title: h1 { t: $element | text(); } | take(key: "t");
+            type: $element | content_type();
+            api: <"/api"> body {
+                names: $document | take(key: "items") | pluck(key: "name");
+            };
+            api_type: "/api" | content_type();
+            "#,
+            "/",
+        )
+        .await?;
+        assert_eq!(
+            output,
+            serde_json::json!({
+                "title": "hi",
+                "type": "text/html; charset=utf-8",
+                "api": { "names": ["cat", "dog"] },
+                "api_type": "application/json",
+            })
+        );
+
+        let output = run(
+            r#"
+            first: $document | get_path(path: "items.0.name");
+            headings: h1 { }*;
+            "#,
+            "/api",
+        )
+        .await?;
+        assert_eq!(output["first"], serde_json::json!("cat"));
+        // the document of a JSON page has no elements
+        assert_eq!(output["headings"], serde_json::json!([]));
+
+        // `$document` is only bound on JSON pages
+        assert!(run("x: $document;", "/").await.is_err());
+        assert!(run("@parser: \"html\";\nx: $document;", "/api")
+            .await
+            .is_err());
+        assert!(run("@parser: \"json\";\nx: $element;", "/").await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_seed() -> anyhow::Result<()> {
+        use super::client::mock::{MockServer, Response};
+
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            _ => Response::html(
+                (1..=20)
+                    .map(|i| format!("<li>{i}</li>"))
+                    .collect::<Vec<_>>()
+                    .concat(),
+            ),
+        })
+        .await;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            items: li { text: $element | text(); }* | pluck(key: "text");
+            first: $items | sample(n: 5);
+            second: $items | sample(n: 5);
+            "#,
+        )
+        .parse()?;
+
+        let run = |seed| {
+            let program = &program;
+            let url = server.url("/");
+            async move {
+                let interpreter = super::Interpreter::with_config(&super::Config {
+                    seed: Some(seed),
+                    ..super::Config::default()
+                })?;
+                anyhow::Ok(serde_json::to_value(
+                    interpreter.interpret(&program.statements, url).await?,
+                )?)
+            }
+        };
+
+        let output = run(7).await?;
+        assert_eq!(output, run(7).await?);
+        // each call gets its own seed
+        assert_ne!(output["first"], output["second"]);
+        assert_ne!(output, run(8).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_seed_ignores_latency() -> anyhow::Result<()> {
+        use std::{
+            sync::{
+                atomic::{AtomicBool, Ordering},
+                Arc,
+            },
+            time::Duration,
+        };
+
+        use super::client::mock::{MockServer, Response};
+
+        // which of the pages is slow, so that the other one finishes first
+        let slow_first = Arc::new(AtomicBool::new(true));
+        let server =
+            MockServer::start({
+                let slow_first = Arc::clone(&slow_first);
+                move |req| {
+                    let items = (1..=20)
+                        .map(|i| format!("<li>{i}</li>"))
+                        .collect::<Vec<_>>()
+                        .concat();
+                    let delay = |slow| Duration::from_millis(if slow { 100 } else { 0 });
+                    match &*req.path {
+                        "/robots.txt" => Response::status(404),
+                        "/" => Response::html(r#"<a href="/1"></a><a href="/2"></a>"#),
+                        "/1" => Response::html(items)
+                            .with_delay(delay(slow_first.load(Ordering::Relaxed))),
+                        _ => Response::html(items)
+                            .with_delay(delay(!slow_first.load(Ordering::Relaxed))),
+                    }
+                }
+            })
+            .await;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            pages: a {
+                href: $element | attrs() | take(key: "href");
+                page: <$href> body {
+                    chosen: li { text: $element | text(); }* | pluck(key: "text") | sample(n: 5);
+                };
+            }*;
+            "#,
+        )
+        .parse()?;
+        let config = super::Config {
+            seed: Some(7),
+            ..super::Config::default()
+        };
+
+        let (program, config) = (&program, &config);
+        let run = |url| async move {
+            let interpreter = super::Interpreter::with_config(config)?;
+            anyhow::Ok(serde_json::to_value(
+                interpreter.interpret(&program.statements, url).await?,
+            )?)
+        };
+        let run_jobs = |jobs| async move {
+            let interpreter = super::Interpreter::with_config(config)?;
+            anyhow::Ok(serde_json::to_value(
+                interpreter
+                    .interpret_jobs(&program.statements, jobs)
+                    .await?,
+            )?)
+        };
+
+        let output = run(server.url("/")).await?;
+        let jobs = super::Job::parse_list(&format!(
+            "{} first\n{}?again second\n",
+            server.url("/"),
+            server.url("/")
+        ))?;
+        let jobs_output = run_jobs(&jobs).await?;
+
+        slow_first.store(false, Ordering::Relaxed);
+        // the pages get the same seeds, whichever finishes first
+        assert_eq!(output, run(server.url("/")).await?);
+        assert_ne!(output["pages"][0], output["pages"][1]);
+        // and each job gets the seeds it would get on its own
+        assert_eq!(jobs_output, run_jobs(&jobs).await?);
+        assert_eq!(jobs_output["first"], output);
+        assert_ne!(jobs_output["first"], jobs_output["second"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_append_in_document_order() -> anyhow::Result<()> {
+        use std::time::Duration;
+
+        use super::client::mock::{MockServer, Response};
+
+        // the first page is slow, so the second one finishes first
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            "/" => Response::html(r#"<a href="/1"></a><a href="/2"></a>"#),
+            "/1" => Response::html("<li>a</li><li>b</li>").with_delay(Duration::from_millis(100)),
+            _ => Response::html("<li>c</li><li>d</li>"),
+        })
+        .await;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            first: "start" | tee_append(into: "seen");
+            pages: a {
+                href: $element | attrs() | take(key: "href");
+                page: <$href> body {
+                    items: li { text: $element | text() | tee_append(into: "seen"); }*;
+                };
+                done: $href | tee_append(into: "seen");
+            }*;
+            last: "end" | tee_append(into: "seen");
+            "#,
+        )
+        .parse()?;
+
+        let output = super::Interpreter::default()
+            .interpret(&program.statements, server.url("/"))
+            .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0["seen"])?,
+            serde_json::json!(["start", "a", "b", "/1", "c", "d", "/2", "end"])
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_headers() -> anyhow::Result<()> {
+        use super::client::mock::{MockServer, Response};
+
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            "/index.html" => Response::html("<h1>index</h1>"),
+            "/login" => Response::html("").with_header("set-cookie", "token=abc; Path=/"),
+            _ => Response::html(format!(
+                "<p>{}</p>",
+                req.header("authorization").unwrap_or("anonymous")
+            )),
+        })
+        .await;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            login: "/login" | fetch();
+            token: "/login" | cookies_from_response() | take(key: "token");
+            headers: h1 {
+                authorization: $token;
+            };
+            account: "/account" | fetch(headers: $headers) | texts(selector: "p") | nth(i: 0);
+            anonymous: "/account" | fetch() | texts(selector: "p") | nth(i: 0);
+            "#,
+        )
+        .parse()?;
+
+        let output = super::Interpreter::new()
+            .interpret(&program.statements, server.url("/index.html"))
+            .await?;
+        assert_eq!(serde_json::to_value(&output.0["token"])?, "abc");
+        assert_eq!(serde_json::to_value(&output.0["account"])?, "abc");
+        assert_eq!(serde_json::to_value(&output.0["anonymous"])?, "anonymous");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cookies() -> anyhow::Result<()> {
+        use super::client::mock::{MockServer, Response};
+
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            "/index.html" => Response::html("<h1>index</h1>").with_header("set-cookie", "page=1"),
+            _ => Response::html("<h1>next</h1>"),
+        })
+        .await;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            @cookies: "session=abc";
+            next: <"next.html"> h1 {
+                text: $element | text();
+            };
+            "#,
+        )
+        .parse()?;
+
+        let interpreter =
+            super::Interpreter::with_config(&super::Config::from_head(&program.head)?)?;
+        interpreter
+            .interpret(&program.statements, server.url("/index.html"))
+            .await?;
+
+        let cookies: Vec<_> = server
+            .requests()
+            .iter()
+            .filter(|x| x.path != "/robots.txt")
+            .map(|x| (x.path.clone(), x.header("cookie").map(str::to_owned)))
+            .collect();
+        assert_eq!(
+            cookies,
+            [
+                ("/index.html".to_owned(), Some("session=abc".to_owned())),
+                (
+                    "/next.html".to_owned(),
+                    Some("session=abc; page=1".to_owned())
+                ),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_limit() -> anyhow::Result<()> {
+        use super::client::mock::{MockServer, Response};
+
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            "/index.html" => Response::html(
+                (1..=5)
+                    .map(|i| format!(r#"<a href="page{i}.html">{i}</a>"#))
+                    .collect::<std::string::String>(),
+            ),
+            path => Response::html(format!("<h1>{path}</h1>")),
+        })
+        .await;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            @limit: 2;
+            pages: a {
+                href: $element | attrs() | take(key: "href");
+                title: <$href> h1 {
+                    text: $element | text();
+                } | take(key: "text");
+            }*;
+            "#,
+        )
+        .parse()?;
+
+        let interpreter =
+            super::Interpreter::with_config(&super::Config::from_head(&program.head)?)?;
+        let output = interpreter
+            .interpret(&program.statements, server.url("/index.html"))
+            .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0["pages"])?,
+            serde_json::json!([
+                { "href": "page1.html", "title": "/page1.html" },
+                { "href": "page2.html", "title": "/page2.html" },
+            ])
+        );
+
+        let mut paths: Vec<_> = server
+            .requests()
+            .into_iter()
+            .map(|x| x.path)
+            .filter(|x| x.starts_with("/page"))
+            .collect();
+        paths.sort();
+        assert_eq!(paths, ["/page1.html", "/page2.html"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dedup_key() -> anyhow::Result<()> {
+        use super::client::mock::{MockServer, Response};
+
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            "/1" => Response::html(
+                r#"<li data-id="a">1</li><li data-id="b">2</li><li data-id="a">3</li><li>4</li>"#,
+            ),
+            _ => Response::html(r#"<li data-id="b">5</li><li data-id="c">6</li><li>7</li>"#),
+        })
+        .await;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            @dedup_key: "id";
+            items: li {
+                id: $element | attrs() | take(key: "data-id");
+                text: $element | text();
+            }* | pluck(key: "text");
+            raw: li {
+                id: $element | attrs() | take(key: "data-id");
+                text: $element | text();
+            }*;
+            "#,
+        )
+        .parse()?;
+
+        let interpreter =
+            super::Interpreter::with_config(&super::Config::from_head(&program.head)?)?;
+        let texts = |output: &super::Bindings<'_>| -> anyhow::Result<_> {
+            Ok(serde_json::to_value(&output.0["raw"])?
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|x| x["text"].clone())
+                .collect::<Vec<_>>())
+        };
+
+        let first = interpreter
+            .interpret(&program.statements, server.url("/1"))
+            .await?;
+        assert_eq!(texts(&first)?, ["1", "2", "4"]);
+        // filters run before deduplication, so `items` is not a list of structures
+        assert_eq!(
+            serde_json::to_value(&first.0["items"])?,
+            serde_json::json!(["1", "2", "3", "4"])
+        );
+
+        let second = interpreter
+            .interpret(&program.statements, server.url("/2"))
+            .await?;
+        assert_eq!(texts(&second)?, ["6", "7"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dedup_key_followed_pages() -> anyhow::Result<()> {
+        use super::client::mock::{MockServer, Response};
+
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            "/1" => Response::html(
+                r#"<li data-id="a">1</li><li data-id="b">2</li><a href="/2">next</a>"#,
+            ),
+            "/2" => Response::html(
+                r#"<li data-id="b">3</li><li data-id="c">4</li><a href="/3">next</a>"#,
+            ),
+            _ => Response::html("<p>the end</p>"),
+        })
+        .await;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            @dedup_key: "id";
+            items: li {
+                id: $element | attrs() | take(key: "data-id");
+                text: $element | text();
+            }*;
+            link: a { href: $element | attrs() | take(key: "href"); } | take(key: "href");
+            next: <$link> body {
+                items: li {
+                    id: $element | attrs() | take(key: "data-id");
+                    text: $element | text();
+                }*;
+            };
+            "#,
+        )
+        .parse()?;
+        let texts = |items: &serde_json::Value| {
+            items
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|x| x["text"].clone())
+                .collect::<Vec<_>>()
+        };
+
+        let interpreter =
+            super::Interpreter::with_config(&super::Config::from_head(&program.head)?)?;
+        let output = serde_json::to_value(
+            &interpreter
+                .interpret(&program.statements, server.url("/1"))
+                .await?,
+        )?;
+        assert_eq!(texts(&output["items"]), ["1", "2"]);
+        // `b` was already kept on the first page
+        assert_eq!(texts(&output["next"]["items"]), ["4"]);
+
+        // the earlier job keeps repeated items, whichever finishes first
+        let interpreter =
+            super::Interpreter::with_config(&super::Config::from_head(&program.head)?)?;
+        let jobs = super::Job::parse_list(&format!(
+            "{} second\n{} first\n",
+            server.url("/2"),
+            server.url("/1")
+        ))?;
+        let output = serde_json::to_value(
+            &interpreter
+                .interpret_jobs(&program.statements, &jobs)
+                .await?,
+        )?;
+        assert_eq!(texts(&output["second"]["items"]), ["3", "4"]);
+        assert_eq!(texts(&output["first"]["items"]), ["1"]);
+        assert_eq!(texts(&output["first"]["next"]["items"]), ["4"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retries_on_empty() -> anyhow::Result<()> {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        use super::client::mock::{MockServer, Response};
+
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let server = MockServer::start({
+            let fetches = Arc::clone(&fetches);
+            move |req| match &*req.path {
+                "/robots.txt" => Response::status(404),
+                "/empty" => Response::html("<p>please wait</p>"),
+                _ if fetches.fetch_add(1, Ordering::Relaxed) == 0 => {
+                    Response::html("<p>please wait</p>")
+                }
+                _ => Response::html("<li>cat</li><li>dog</li>"),
+            }
+        })
+        .await;
+
+        let program = crate::frontend::Parser::new(
+            "items: li { text: $element | text(); }* | pluck(key: \"text\");",
+        )
+        .parse()?;
+        let interpreter = super::Interpreter::with_config(&super::Config {
+            retries_on_empty: Some(2),
+            ..super::Config::default()
+        })?;
+
+        let output = interpreter
+            .interpret(&program.statements, server.url("/"))
+            .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0["items"])?,
+            serde_json::json!(["cat", "dog"])
+        );
+        assert_eq!(fetches.load(Ordering::Relaxed), 2);
+
+        // an empty result is returned once the retries run out
+        let output = interpreter
+            .interpret(&program.statements, server.url("/empty"))
+            .await?;
+        assert_eq!(
+            serde_json::to_value(&output.0["items"])?,
+            serde_json::json!([])
+        );
+        let empty = server
+            .requests()
+            .into_iter()
+            .filter(|x| x.path == "/empty")
+            .count();
+        assert_eq!(empty, 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_depth() -> anyhow::Result<()> {
+        use super::client::mock::{MockServer, Response};
+
+        let server = MockServer::start(|req| match &*req.path {
+            "/robots.txt" => Response::status(404),
+            path => {
+                let page: u32 = path[1..].parse().unwrap_or_default();
+                Response::html(format!(
+                    r#"<h1>page {page}</h1><a href="/{}">next</a>"#,
+                    page + 1
+                ))
+            }
+        })
+        .await;
+
+        let program = crate::frontend::Parser::new(
+            r#"
+            link: a { href: $element | attrs() | take(key: "href"); } | take(key: "href");
+            next: <$link> body {
+                link: a { href: $element | attrs() | take(key: "href"); } | take(key: "href");
+                next: <$link> body {
+                    title: h1 { text: $element | text(); } | take(key: "text");
+                    link: a { href: $element | attrs() | take(key: "href"); } | take(key: "href");
+                    fetched: $link | try(filter: "fetch", default: "skipped");
+                    next: <$link> h1 { text: $element | text(); }*;
+                };
+            };
+            "#,
+        )
+        .parse()?;
+        let interpreter = super::Interpreter::with_config(&super::Config {
+            max_depth: Some(2),
+            ..super::Config::default()
+        })?;
+
+        let output = interpreter
+            .interpret(&program.statements, server.url("/0"))
+            .await?;
+        let deepest = &serde_json::to_value(&output.0["next"])?["next"];
+        assert_eq!(deepest["title"], serde_json::json!("page 2"));
+        assert_eq!(deepest["fetched"], serde_json::json!("skipped"));
+        assert_eq!(deepest["next"], serde_json::json!([]));
+
+        assert!(server.requests().iter().all(|x| x.path != "/3"));
+        let url = server.url("/3");
+        assert!(interpreter
+            .take_diagnostics()
+            .iter()
+            .any(|x| x.message
+                == format!("skipping `{url}`: it is deeper than the maximum depth of 2")));
+
+        Ok(())
+    }
+
     integration_test! {
         abc,
         attr,
@@ -400,5 +1756,19 @@ mod tests {
         relative,
         recurser,
         filter_select,
+        meta,
+        jsonld,
+        table,
+        rss,
+        texts,
+        links,
+        when_present,
+        closest,
+        data_attrs,
+        select_nth,
+        to_structure,
+        first_selector,
+        abs_links,
+        cell,
     }
 }
@@ -3,6 +3,10 @@
 //! To run the loop, see [`Repl::rep`] and [`Repl::repl`].  This module is primarily
 //! for use in the `scrapelect` binary, but
 
+// `ElementArc` holds non-`Send` element references, but the REPL is single-threaded,
+// so the `Arc`s are only used for shared ownership.
+#![allow(clippy::arc_with_non_send_sync)]
+
 use std::{
     borrow::Cow,
     io::{self, BufRead, StdinLock, StdoutLock, Write},
@@ -18,7 +22,7 @@ use url::Url;
 
 use crate::frontend::{ast, Parser, Token};
 
-use super::Interpreter;
+use super::{HttpClient, Interpreter};
 
 #[derive(Debug)]
 pub enum ElementParent {
@@ -108,6 +112,11 @@ impl<'a, 'b> ElementContextView<'b, 'a> for Context<'a> {
         Ok(())
     }
 
+    #[inline]
+    fn remove_inner(&mut self, name: &str) -> Option<EValue<'a>> {
+        self.ledger.0.remove(name)
+    }
+
     fn get_inner(&self, id: &str) -> scrapelect_filter_types::Result<EValue<'a>> {
         if let Some(item) = self.ledger.0.get(id) {
             Ok(item.clone())
@@ -182,12 +191,7 @@ impl Repl {
     #[must_use]
     pub fn new() -> Self {
         Self {
-            client: reqwest::Client::builder()
-                .user_agent(concat!(
-                    env!("CARGO_PKG_NAME"),
-                    " v",
-                    env!("CARGO_PKG_VERSION")
-                ))
+            client: HttpClient::default_builder()
                 .build()
                 .expect("Default client is invalid"),
             stack: Vec::new(),
@@ -345,7 +349,7 @@ impl<R: BufRead, W: Write> Repl<R, W> {
                 let inline = parser.parse_value()?;
                 let mut ctx = Context::new_as_error(&self.stack)?;
 
-                let value = interpreter.eval_inline(&inline, &mut ctx)?;
+                let value = interpreter.eval_inline(&inline, &mut ctx).await?;
                 outputln!(self, "{value}")?;
                 let ledger = into_data(ctx);
                 self.pop_off(ledger)?;
@@ -392,7 +396,7 @@ impl<R: BufRead, W: Write> Repl<R, W> {
             let statement = parser.parse_statement()?;
 
             interpreter
-                .interpret_statement(&statement, &mut ctx)
+                .interpret_statement(&statement, &mut ctx, None, None)
                 .await?;
 
             let ledger = into_data(ctx);
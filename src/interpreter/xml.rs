@@ -0,0 +1,266 @@
+//! A lenient XML parser for documents like RSS and Atom feeds, which builds the
+//! same [`Html`] tree that `scraper` builds for HTML documents.
+//!
+//! Unlike the HTML parser, tag names keep their case, every element can contain
+//! text (the HTML parser treats `<link>` as a void element, for example), and
+//! `<![CDATA[...]]>` sections are read as text.  Namespace prefixes are kept
+//! separately from the name, so `<dc:creator>` is selected by `creator`.
+
+use html5ever::{
+    interface::{ElementFlags, NodeOrText, TreeSink},
+    tendril::StrTendril,
+    Attribute, LocalName, Namespace, Prefix, QualName,
+};
+use scrapelect_filter_types::bail;
+use scraper::Html;
+
+use super::Result;
+
+type NodeId = <Html as TreeSink>::Handle;
+
+/// Parses `text` as an XML document.  Malformed markup is skipped or closed
+/// where it is found, instead of raising an error.
+///
+/// # Errors
+///
+/// Returns an `Err` if the document has no root element.
+pub fn parse_document(text: &str) -> Result<Html> {
+    let mut html = Html::new_document();
+    let document = html.get_document();
+
+    // the open elements, innermost last, and their names
+    let mut stack = vec![(document, String::new())];
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let parent = stack.last().expect("the document is never closed").0;
+
+        let Some(start) = rest.find('<') else {
+            append_text(&mut html, parent, &decode_entities(rest));
+            break;
+        };
+
+        if start > 0 {
+            append_text(&mut html, parent, &decode_entities(&rest[..start]));
+            rest = &rest[start..];
+        }
+
+        if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let (cdata, after) = after.split_once("]]>").unwrap_or((after, ""));
+            append_text(&mut html, parent, cdata);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.split_once("-->").map_or("", |x| x.1);
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            // the XML declaration, processing instructions, and doctypes
+            rest = skip_declaration(rest);
+        } else if let Some(after) = rest.strip_prefix("</") {
+            let (name, after) = after.split_once('>').unwrap_or((after, ""));
+            let name = name.trim();
+            // close the innermost element with this name, and any unclosed
+            // elements inside it
+            if let Some(i) = stack.iter().rposition(|(_, open)| open == name) {
+                stack.truncate(i.max(1));
+            }
+            rest = after;
+        } else {
+            let Some((tag, self_closing, after)) = split_tag(&rest[1..]) else {
+                // a stray `<`, which is not a tag
+                append_text(&mut html, parent, "<");
+                rest = &rest[1..];
+                continue;
+            };
+
+            let (name, attrs) = parse_tag(tag);
+            let element = html.create_element(
+                qualify(&name),
+                attrs
+                    .into_iter()
+                    .map(|(name, value)| Attribute {
+                        name: qualify(&name),
+                        value: StrTendril::from(value),
+                    })
+                    .collect(),
+                ElementFlags::default(),
+            );
+            html.append(&parent, NodeOrText::AppendNode(element));
+
+            if !self_closing {
+                stack.push((element, name));
+            }
+            rest = after;
+        }
+    }
+
+    if !html.tree.root().children().any(|x| x.value().is_element()) {
+        bail!("XML document has no root element");
+    }
+
+    Ok(html)
+}
+
+fn append_text(html: &mut Html, parent: NodeId, text: &str) {
+    if !text.is_empty() {
+        html.append(&parent, NodeOrText::AppendText(StrTendril::from(text)));
+    }
+}
+
+/// Splits a `prefix:name` into a name without a namespace.
+fn qualify(name: &str) -> QualName {
+    let (prefix, local) = match name.split_once(':') {
+        Some((prefix, local)) => (Some(Prefix::from(prefix)), local),
+        None => (None, name),
+    };
+
+    QualName::new(prefix, Namespace::from(""), LocalName::from(local))
+}
+
+/// Skips a `<?...?>` or `<!...>` declaration at the start of `rest`, including
+/// a doctype's `[...]` internal subset.
+fn skip_declaration(rest: &str) -> &str {
+    let mut depth = 0_usize;
+    for (i, c) in rest.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            '>' if depth == 0 => return &rest[i + 1..],
+            _ => (),
+        }
+    }
+
+    ""
+}
+
+/// Splits the inside of a start tag from the text after it, ignoring any `>` in
+/// quoted attribute values.  Returns `None` if `rest` does not start with a tag name.
+fn split_tag(rest: &str) -> Option<(&str, bool, &str)> {
+    if !rest.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        return None;
+    }
+
+    let mut quote = None;
+    for (i, c) in rest.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => {
+                let tag = &rest[..i];
+                return Some(match tag.strip_suffix('/') {
+                    Some(tag) => (tag, true, &rest[i + 1..]),
+                    None => (tag, false, &rest[i + 1..]),
+                });
+            }
+            _ => (),
+        }
+    }
+
+    // an unterminated tag takes up the rest of the document
+    Some((rest, false, ""))
+}
+
+/// Parses the name and attributes of a start tag like `item id="1"`.
+fn parse_tag(tag: &str) -> (String, Vec<(String, String)>) {
+    let (name, mut rest) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+    let mut attrs = Vec::new();
+
+    loop {
+        rest = rest.trim_start();
+        let Some(eq) = rest.find('=') else {
+            break;
+        };
+
+        let key = rest[..eq].trim().to_owned();
+        let value = rest[eq + 1..].trim_start();
+        let Some(quote) = value.chars().next().filter(|c| matches!(c, '"' | '\'')) else {
+            break;
+        };
+
+        let (value, after) = value[1..].split_once(quote).unwrap_or((&value[1..], ""));
+        attrs.push((key, decode_entities(value)));
+        rest = after;
+    }
+
+    (name.to_owned(), attrs)
+}
+
+/// Decodes the predefined XML entities and numeric character references in
+/// `text`.  Unknown entities are kept as they are.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let decoded = rest[1..].split_once(';').and_then(|(entity, _)| {
+            let c = match entity {
+                "lt" => '<',
+                "gt" => '>',
+                "amp" => '&',
+                "quot" => '"',
+                "apos" => '\'',
+                _ => {
+                    let code = match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => entity.strip_prefix('#')?.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, entity.len() + 2))
+        });
+
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Selector;
+
+    #[test]
+    fn parses_feed_elements() {
+        let html = super::parse_document(
+            r#"<?xml version="1.0"?>
+            <!DOCTYPE rss [ <!ENTITY x "y"> ]>
+            <rss><channel>
+                <link>https://example.com/</link>
+                <!-- a <comment> -->
+                <item id='1'><title>Fish &amp; chips &#x263A;</title><br/>
+                <description><![CDATA[<p>hi</p>]]></description><dc:creator>Me</dc:creator></item>
+            </channel></rss>"#,
+        )
+        .expect("XML parse error");
+
+        let select = |selector: &str| {
+            let selector = Selector::parse(selector).expect("invalid selector");
+            html.select(&selector)
+                .map(|x| x.text().collect::<String>())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(select("channel > link"), ["https://example.com/"]);
+        assert_eq!(select("item > title"), ["Fish & chips ☺"]);
+        assert_eq!(select("item[id='1'] > description"), ["<p>hi</p>"]);
+        assert_eq!(select("creator"), ["Me"]);
+        assert_eq!(select("br"), [""]);
+    }
+
+    #[test]
+    fn no_root_element_errors() {
+        assert!(super::parse_document("<?xml version=\"1.0\"?> text").is_err());
+    }
+}
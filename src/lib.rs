@@ -1,4 +1,31 @@
 #![forbid(unsafe_code)]
 
+pub mod error;
 pub mod frontend;
 pub mod interpreter;
+pub mod logging;
+pub mod progress;
+pub mod yaml;
+
+pub use error::ScrapeError;
+
+use reqwest::Url;
+use scrapelect_filter_types::Bindings;
+
+/// Parses `program` and runs it on the page at `url`, with the options set in
+/// its head, and returns its results.
+///
+/// # Errors
+///
+/// Returns a [`ScrapeError`] sorted by what failed: parsing the program or
+/// its head, fetching a page, a request timing out, or running a statement.
+pub async fn run(program: &str, url: Url) -> Result<Bindings<'_>, ScrapeError> {
+    let program = frontend::Parser::new(program).parse()?;
+    let config = interpreter::Config::from_head(&program.head).map_err(ScrapeError::Parse)?;
+
+    interpreter::Interpreter::with_config(&config)
+        .map_err(ScrapeError::Parse)?
+        .interpret(&program.statements, url)
+        .await
+        .map_err(ScrapeError::from)
+}
@@ -0,0 +1,240 @@
+//! A minimal [`tracing`] subscriber that prints the requests and other events
+//! of a run, for `--verbose`.
+//!
+//! Each event is printed on one line, after the names and fields of the spans
+//! it is in, like `INFO interpret{url=https://a.com/}: fetched url=... status=200`.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{self, Write as _},
+    io::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Level, Metadata, Subscriber,
+};
+
+thread_local! {
+    /// The spans entered on this thread, innermost last.
+    static ENTERED: RefCell<Vec<span::Id>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Debug)]
+struct SpanData {
+    /// `name{field=value ...}`, as it is printed.
+    label: String,
+    /// How many handles to this span are open.
+    refs: usize,
+}
+
+/// Prints events at or above a level to a writer, like standard error.
+pub struct Logger {
+    level: Level,
+    writer: Mutex<Box<dyn Write + Send>>,
+    spans: Mutex<HashMap<u64, SpanData>>,
+    next_id: AtomicU64,
+}
+
+impl fmt::Debug for Logger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Logger")
+            .field("level", &self.level)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Logger {
+    /// Creates a logger that prints events at or above `level` to `writer`.
+    #[must_use]
+    pub fn new(level: Level, writer: impl Write + Send + 'static) -> Self {
+        Self {
+            level,
+            writer: Mutex::new(Box::new(writer)),
+            spans: Mutex::default(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Creates a logger that prints events at or above `level` to standard error.
+    #[must_use]
+    pub fn stderr(level: Level) -> Self {
+        Self::new(level, std::io::stderr())
+    }
+
+    /// Sets this logger as the subscriber for the whole program.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if a global subscriber was already set.
+    pub fn init(self) -> Result<(), tracing::subscriber::SetGlobalDefaultError> {
+        tracing::subscriber::set_global_default(self)
+    }
+
+    fn spans(&self) -> std::sync::MutexGuard<'_, HashMap<u64, SpanData>> {
+        self.spans
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// Formats the fields of a span or event as ` key=value` pairs, putting the
+/// `message` field first.
+#[derive(Default)]
+struct Fields {
+    message: String,
+    rest: String,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.rest, " {}={value:?}", field.name());
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.rest, " {}={value}", field.name());
+        }
+    }
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= self.level
+    }
+
+    fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+
+        let label = match fields.rest.strip_prefix(' ') {
+            Some(rest) => format!("{}{{{rest}}}", attrs.metadata().name()),
+            None => attrs.metadata().name().to_owned(),
+        };
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.spans().insert(id, SpanData { label, refs: 1 });
+        span::Id::from_u64(id)
+    }
+
+    fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        let mut line = format!("{:>5} ", event.metadata().level());
+        {
+            let spans = self.spans();
+            ENTERED.with_borrow(|entered| {
+                for id in entered {
+                    if let Some(span) = spans.get(&id.into_u64()) {
+                        line.push_str(&span.label);
+                        line.push_str(": ");
+                    }
+                }
+            });
+        }
+        line.push_str(&fields.message);
+        line.push_str(&fields.rest);
+
+        let mut writer = self
+            .writer
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        // there's nowhere to report a failure to log
+        let _ = writeln!(writer, "{line}");
+    }
+
+    fn enter(&self, span: &span::Id) {
+        ENTERED.with_borrow_mut(|entered| entered.push(span.clone()));
+    }
+
+    fn exit(&self, span: &span::Id) {
+        ENTERED.with_borrow_mut(|entered| {
+            if let Some(i) = entered.iter().rposition(|x| x == span) {
+                entered.remove(i);
+            }
+        });
+    }
+
+    fn clone_span(&self, id: &span::Id) -> span::Id {
+        if let Some(span) = self.spans().get_mut(&id.into_u64()) {
+            span.refs += 1;
+        }
+        id.clone()
+    }
+
+    fn try_close(&self, id: span::Id) -> bool {
+        let mut spans = self.spans();
+        let Some(span) = spans.get_mut(&id.into_u64()) else {
+            return false;
+        };
+
+        span.refs -= 1;
+        if span.refs == 0 {
+            spans.remove(&id.into_u64());
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    use super::Logger;
+
+    /// A writer that keeps everything written to it, to inspect in tests.
+    #[derive(Debug, Clone, Default)]
+    pub struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Captured {
+        pub fn text(&self) -> String {
+            String::from_utf8_lossy(&self.0.lock().expect("lock poisoned")).into_owned()
+        }
+    }
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().expect("lock poisoned").write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn events_are_printed_in_spans() {
+        let captured = Captured::default();
+        let logger = Logger::new(tracing::Level::INFO, captured.clone());
+
+        tracing::subscriber::with_default(logger, || {
+            let span = tracing::info_span!("outer", id = 1);
+            let _guard = span.enter();
+            tracing::info!(status = 200, "fetched");
+            tracing::debug!("hidden");
+        });
+
+        assert_eq!(captured.text(), " INFO outer{id=1}: fetched status=200\n");
+    }
+}
@@ -1,12 +1,22 @@
 #![forbid(unsafe_code)]
-use std::path::PathBuf;
+use std::{
+    io::IsTerminal as _,
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use clap::Parser as _;
+use encoding_rs::Encoding;
 use scrapelect::{
     frontend::Parser,
-    interpreter::{Interpreter, Repl},
+    interpreter::{check, Config, HttpClient, Interpreter, Job, Repl},
+    logging::Logger,
+    progress::Progress,
 };
+use tracing::Level;
 use url::Url;
 
 #[derive(Debug, clap::Parser)]
@@ -25,7 +35,183 @@ struct RunArgs {
     /// The `.scrp` file describing how to convert the web page into structured data
     file: PathBuf,
     /// The URL of the web page to start scraping at.
-    url: Url,
+    #[arg(required_unless_present_any = ["dump_ast", "check", "jobs_file"])]
+    url: Option<Url>,
+    /// Run the program on each URL in this file instead of on `url`, and print
+    /// the results of every page, keyed by its URL.
+    ///
+    /// Each line of the file is a URL, optionally followed by whitespace and the
+    /// key to use for its results instead.  Empty lines and lines starting with
+    /// `#` are ignored.
+    #[arg(long, value_name = "PATH", conflicts_with = "url")]
+    jobs_file: Option<PathBuf>,
+    /// The maximum number of requests to have in flight at once [default: 4].
+    ///
+    /// Overrides `@concurrency` in the program's head.
+    #[arg(long)]
+    concurrency: Option<NonZeroUsize>,
+    /// The minimum number of milliseconds to wait between requests to the same host [default: 0].
+    ///
+    /// Overrides `@delay_ms` in the program's head.
+    #[arg(long)]
+    delay_ms: Option<u64>,
+    /// The maximum number of elements that each top-level element block selects.
+    ///
+    /// Overrides `@limit` in the program's head.
+    #[arg(long)]
+    limit: Option<NonZeroUsize>,
+    /// The encoding to decode every page with (like `windows-1252` or `shift_jis`),
+    /// instead of the encoding that each page declares.
+    #[arg(long, value_parser = parse_encoding)]
+    input_encoding: Option<&'static Encoding>,
+    /// A `name=value` cookie to send with requests to the host of `url`.  Can be
+    /// passed more than once.
+    ///
+    /// Overrides `@cookies` in the program's head.
+    #[arg(long = "cookie", value_name = "NAME=VALUE", value_parser = parse_cookie)]
+    cookies: Vec<(String, String)>,
+    /// An `http`, `https`, `socks5`, or `socks5h` proxy to send every request through.
+    ///
+    /// SOCKS proxies need `scrapelect` to be built with the `socks` feature.
+    ///
+    /// Overrides `@proxy` in the program's head.
+    #[arg(long, value_parser = parse_proxy)]
+    proxy: Option<Url>,
+    /// The maximum size of a response body, in bytes.  Fetching a larger page
+    /// raises an error [default: unlimited].
+    #[arg(long, value_name = "BYTES")]
+    max_response_size: Option<u64>,
+    /// A status code outside of `2xx` whose responses are still read as pages,
+    /// instead of raising an error.  Can be passed more than once.
+    ///
+    /// Overrides `@allow_status` in the program's head.
+    #[arg(long, value_name = "CODE", value_parser = clap::value_parser!(u16).range(100..600))]
+    allow_status: Vec<u16>,
+    /// Seed the random choices of filters like `sample`, so that runs on the
+    /// same pages give the same output.
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+    /// If every top-level binding of the result is empty (like `null` or `[]`),
+    /// wait and fetch the starting page again, up to this many times.
+    #[arg(long, value_name = "N")]
+    retries_on_empty: Option<u32>,
+    /// Don't follow element blocks with a URL, or `fetch` pages, more than this
+    /// many pages deep from the starting page, which is at depth 0.
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+    /// Print the number of requests, cache hits, bytes fetched, and the total
+    /// time of the run to standard error when it finishes.
+    #[arg(long)]
+    stats: bool,
+    /// Fetch pages even if a site's `robots.txt` disallows it.
+    #[arg(long)]
+    ignore_robots: bool,
+    /// Accept invalid TLS certificates, like self-signed ones.  This is
+    /// insecure: anyone between you and the site can read and change the pages.
+    #[arg(long)]
+    allow_insecure: bool,
+    /// Show the number of requests sent so far on standard error, while the
+    /// program runs.  Only shown if standard error is a terminal, and not with
+    /// `--quiet`.
+    #[arg(long)]
+    progress: bool,
+    /// Don't print the output of `dbg` or any warnings.
+    #[arg(long, short)]
+    quiet: bool,
+    /// Print each request, with its status code and how long it took, and
+    /// when a page is reused from the cache.
+    #[arg(long, short, conflicts_with = "quiet")]
+    verbose: bool,
+    /// Print how the program was parsed and exit, without fetching anything.
+    #[arg(long)]
+    dump_ast: bool,
+    /// Check the program for unknown filters, invalid selectors, and invalid
+    /// head directives, print each problem, and exit without fetching anything.
+    #[arg(long, conflicts_with = "dump_ast")]
+    check: bool,
+    /// Write the output to this file, replacing it if it exists, instead of
+    /// printing it.
+    #[arg(long, short, value_name = "PATH")]
+    output: Option<PathBuf>,
+    /// The format to print the output in.
+    #[arg(long, value_enum, default_value_t = Format::Json)]
+    format: Format,
+    /// Print the output as indented, multi-line JSON [default when printing to a terminal].
+    #[arg(long, conflicts_with = "compact")]
+    pretty: bool,
+    /// Print the output as single-line JSON [default when piping the output].
+    #[arg(long)]
+    compact: bool,
+}
+
+/// Parses an encoding label, like `utf-8` or `latin1`.
+fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| format!("unknown encoding `{label}`"))
+}
+
+/// Parses the URL of an `http`, `https`, `socks5`, or `socks5h` proxy.
+fn parse_proxy(url: &str) -> Result<Url, String> {
+    HttpClient::parse_proxy(url).map_err(|e| e.to_string())
+}
+
+/// Parses a `name=value` cookie.
+fn parse_cookie(cookie: &str) -> Result<(String, String), String> {
+    cookie
+        .split_once('=')
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .ok_or_else(|| format!("expected `name=value`, got `{cookie}`"))
+}
+
+/// The format to print the output of a program in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    Json,
+    Yaml,
+}
+
+/// How to print the output of a program as JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Pretty,
+    Compact,
+}
+
+impl RunArgs {
+    /// Returns the options set by command-line flags.
+    fn config(&self) -> Config {
+        Config {
+            concurrency: self.concurrency,
+            delay: self.delay_ms.map(Duration::from_millis),
+            ignore_robots: self.ignore_robots.then_some(true),
+            dedup_requests: None,
+            base_url: None,
+            dedup_key: None,
+            memoize: None,
+            limit: self.limit,
+            parser: None,
+            input_encoding: self.input_encoding,
+            cookies: (!self.cookies.is_empty()).then(|| self.cookies.clone()),
+            proxy: self.proxy.clone(),
+            quiet: self.quiet.then_some(true),
+            max_response_size: self.max_response_size,
+            allow_status: (!self.allow_status.is_empty()).then(|| self.allow_status.clone()),
+            seed: self.seed,
+            retries_on_empty: self.retries_on_empty,
+            max_depth: self.max_depth,
+            allow_insecure: self.allow_insecure.then_some(true),
+        }
+    }
+
+    /// Returns the output style chosen by the flags, or if neither is set,
+    /// pretty if the output is a terminal and compact otherwise.
+    const fn style(&self, is_terminal: bool) -> Style {
+        match (self.pretty, self.compact) {
+            (true, _) => Style::Pretty,
+            (_, true) => Style::Compact,
+            _ if is_terminal => Style::Pretty,
+            _ => Style::Compact,
+        }
+    }
 }
 
 #[derive(Debug, clap::Args)]
@@ -34,6 +220,8 @@ struct ReplArgs {
     url: Option<Url>,
 }
 
+// only one is ever parsed, so the size difference doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, clap::Subcommand)]
 enum Mode {
     Run(RunArgs),
@@ -46,20 +234,107 @@ async fn main() -> anyhow::Result<()> {
 
     match (args.mode, args.run) {
         (Some(Mode::Run(run_args)), None) | (None, Some(run_args)) => {
+            if run_args.verbose {
+                Logger::stderr(Level::INFO).init()?;
+            }
+
             let pgm = std::fs::read_to_string(&run_args.file)
                 .with_context(|| format!("error reading file {}", run_args.file.display()))?;
 
             let parser = Parser::new(&pgm);
 
-            let ast = parser
+            let program = parser
                 .parse()
                 .with_context(|| format!("parse error in {}:", run_args.file.display()))?;
 
-            let interpreter = Interpreter::new();
+            if run_args.dump_ast {
+                println!("{program:#?}");
+                return Ok(());
+            }
+
+            if run_args.check {
+                let problems = check::check(&program);
+                for problem in &problems {
+                    eprintln!("error: {problem}");
+                }
+                if !problems.is_empty() {
+                    bail!(
+                        "found {} problem(s) in {}",
+                        problems.len(),
+                        run_args.file.display()
+                    );
+                }
+                return Ok(());
+            }
 
-            let results = interpreter.interpret(&ast, run_args.url).await?;
+            let config = run_args.config().or(Config::from_head(&program.head)
+                .with_context(|| format!("invalid head in {}:", run_args.file.display()))?);
 
-            println!("{}", serde_json::to_string_pretty(&results)?);
+            let format = run_args.format;
+            // a file is never a terminal, so the output is compact by default
+            let style =
+                run_args.style(run_args.output.is_none() && std::io::stdout().is_terminal());
+            if config.allow_insecure.unwrap_or_default() {
+                eprintln!(
+                    "warning: TLS certificates are not verified (`--allow-insecure`), so \
+                    anyone on the network can read and change the pages"
+                );
+            }
+            let progress =
+                (run_args.progress && !run_args.quiet && std::io::stderr().is_terminal())
+                    .then(|| Arc::new(Progress::stderr()));
+            let mut interpreter = Interpreter::with_config(&config)?;
+            if let Some(progress) = &progress {
+                interpreter = interpreter.with_progress(progress.hook());
+            }
+
+            let jobs = match &run_args.jobs_file {
+                Some(path) => Some(Job::parse_list(
+                    &std::fs::read_to_string(path)
+                        .with_context(|| format!("error reading file {}", path.display()))?,
+                )?),
+                None => None,
+            };
+
+            let start = Instant::now();
+            let results = match (&jobs, run_args.url.clone()) {
+                (Some(jobs), _) => interpreter.interpret_jobs(&program.statements, jobs).await,
+                (None, Some(url)) => interpreter.interpret(&program.statements, url).await,
+                (None, None) => {
+                    unreachable!(
+                        "clap requires a URL without `--dump-ast`, `--check`, or `--jobs-file`"
+                    )
+                }
+            };
+            let elapsed = start.elapsed();
+            if let Some(progress) = &progress {
+                progress.finish();
+            }
+
+            let diagnostics = interpreter.take_diagnostics();
+            if !run_args.quiet {
+                for diagnostic in diagnostics {
+                    eprintln!("{diagnostic}");
+                }
+            }
+
+            if run_args.stats {
+                eprintln!("stats: {}, in {elapsed:.2?}", interpreter.stats());
+            }
+
+            let results = results?;
+
+            let output = match (format, style) {
+                (Format::Json, Style::Pretty) => serde_json::to_string_pretty(&results)? + "\n",
+                (Format::Json, Style::Compact) => serde_json::to_string(&results)? + "\n",
+                (Format::Yaml, _) => scrapelect::yaml::to_string(&results)?,
+            };
+
+            match &run_args.output {
+                Some(path) => std::fs::write(path, output)
+                    .with_context(|| format!("error writing output to {}", path.display()))?,
+                None => print!("{output}"),
+            }
         }
         (Some(Mode::Repl(ReplArgs { url: Some(url) })), None) => {
             Repl::open(url).await?.repl().await?;
@@ -80,3 +355,51 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser as _;
+
+    use super::{Interface, RunArgs, Style};
+
+    fn run_args(args: &[&str]) -> RunArgs {
+        let args = Interface::try_parse_from(
+            ["scrapelect", "a.scrp", "https://example.com"]
+                .iter()
+                .chain(args),
+        )
+        .expect("invalid arguments");
+
+        args.run.expect("no run arguments")
+    }
+
+    fn style(args: &[&str], is_terminal: bool) -> Style {
+        run_args(args).style(is_terminal)
+    }
+
+    #[test]
+    fn output_style() {
+        assert_eq!(style(&[], true), Style::Pretty);
+        assert_eq!(style(&[], false), Style::Compact);
+        assert_eq!(style(&["--pretty"], false), Style::Pretty);
+        assert_eq!(style(&["--compact"], true), Style::Compact);
+
+        assert!(Interface::try_parse_from([
+            "scrapelect",
+            "a.scrp",
+            "https://example.com",
+            "--pretty",
+            "--compact"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn allow_insecure() {
+        assert_eq!(run_args(&[]).config().allow_insecure, None);
+        assert_eq!(
+            run_args(&["--allow-insecure"]).config().allow_insecure,
+            Some(true)
+        );
+    }
+}
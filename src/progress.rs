@@ -0,0 +1,90 @@
+//! A one-line progress indicator on standard error, for `--progress`.
+
+use std::{io::Write, sync::Mutex};
+
+use crate::interpreter::{ProgressHook, Stats};
+
+/// Prints the totals of a run on one line, replacing the line each time it
+/// is updated.
+pub struct Progress {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Progress").finish_non_exhaustive()
+    }
+}
+
+impl Progress {
+    /// Creates an indicator that prints to `writer`, which should be a terminal.
+    #[must_use]
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+        }
+    }
+
+    /// Creates an indicator that prints to standard error.
+    #[must_use]
+    pub fn stderr() -> Self {
+        Self::new(std::io::stderr())
+    }
+
+    /// Returns a hook that updates this indicator, for
+    /// [`Interpreter::with_progress`](crate::interpreter::Interpreter::with_progress).
+    pub fn hook(self: &std::sync::Arc<Self>) -> ProgressHook {
+        let progress = std::sync::Arc::clone(self);
+        ProgressHook::new(move |stats| progress.update(stats))
+    }
+
+    /// Replaces the line with the totals in `stats`.
+    pub fn update(&self, stats: Stats) {
+        // `\x1b[2K` clears the whole line, in case the new one is shorter
+        self.write(&format!(
+            "\r\x1b[2K{} requests ({} failed), {} bytes fetched",
+            stats.requests, stats.failed, stats.bytes
+        ));
+    }
+
+    /// Clears the line, so that later output starts at the beginning of it.
+    pub fn finish(&self) {
+        self.write("\r\x1b[2K");
+    }
+
+    fn write(&self, text: &str) {
+        let mut writer = self
+            .writer
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        // progress is best-effort, so a closed terminal isn't an error
+        let _ = writer.write_all(text.as_bytes());
+        let _ = writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{interpreter::Stats, logging::tests::Captured};
+
+    use super::Progress;
+
+    #[test]
+    fn lines_are_replaced() {
+        let captured = Captured::default();
+        let progress = Progress::new(captured.clone());
+
+        progress.update(Stats {
+            requests: 2,
+            failed: 1,
+            bytes: 30,
+            cache_hits: 0,
+        });
+        progress.finish();
+
+        assert_eq!(
+            captured.text(),
+            "\r\x1b[2K2 requests (1 failed), 30 bytes fetched\r\x1b[2K"
+        );
+    }
+}
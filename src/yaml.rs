@@ -0,0 +1,150 @@
+//! A minimal YAML emitter for the output of a program.
+//!
+//! Values are serialized to JSON first, so they are converted the same way as
+//! in the JSON output, and then written in YAML's block style.
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Serializes `value` as a YAML document.
+///
+/// # Errors
+///
+/// Returns an `Err` if `value` cannot be serialized, like with
+/// [`serde_json::to_value`].
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<String> {
+    let value = serde_json::to_value(value)?;
+    let mut out = String::new();
+
+    if is_block(&value) {
+        write_block(&mut out, &value, 0);
+    } else {
+        out.push_str(&scalar(&value));
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+/// Whether `value` is written as an indented block, rather than on one line.
+fn is_block(value: &Value) -> bool {
+    match value {
+        Value::Array(list) => !list.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+        _ => false,
+    }
+}
+
+/// Writes the nonempty list or structure `value`, with each line indented by
+/// `indent` spaces.
+fn write_block(out: &mut String, value: &Value, indent: usize) {
+    match value {
+        Value::Array(list) => {
+            for item in list {
+                if is_block(item) {
+                    // write the item as if it were nested, then put the dash
+                    // in the indentation of its first line
+                    let start = out.len();
+                    write_block(out, item, indent + 2);
+                    out.replace_range(start..start + indent + 2, &format!("{:indent$}- ", ""));
+                } else {
+                    out.push_str(&format!("{:indent$}- {}\n", "", scalar(item)));
+                }
+            }
+        }
+        Value::Object(map) => {
+            for (key, value) in map {
+                let key = string(key);
+                if is_block(value) {
+                    out.push_str(&format!("{:indent$}{key}:\n", ""));
+                    write_block(out, value, indent + 2);
+                } else {
+                    out.push_str(&format!("{:indent$}{key}: {}\n", "", scalar(value)));
+                }
+            }
+        }
+        _ => unreachable!("scalars are not written as blocks"),
+    }
+}
+
+/// Formats a value that fits on one line: a scalar or an empty list or structure.
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_owned(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => string(s),
+        Value::Array(_) => "[]".to_owned(),
+        Value::Object(_) => "{}".to_owned(),
+    }
+}
+
+/// Formats a string, quoting it if it would otherwise be read as something
+/// other than the same string.
+fn string(s: &str) -> String {
+    const RESERVED: &[&str] = &[
+        "null", "~", "true", "false", "yes", "no", "on", "off", "y", "n",
+    ];
+
+    let plain = s
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '_' | '-' | '.' | '/' | '(' | ')'))
+        && s.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '/')
+        && !s.ends_with(' ')
+        && !RESERVED.iter().any(|x| x.eq_ignore_ascii_case(s));
+
+    if plain {
+        s.to_owned()
+    } else {
+        // a JSON string is also a valid double-quoted YAML string
+        serde_json::to_string(s).expect("serializing a string is infallible")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    #[test]
+    fn structure_matches_fixture() -> serde_json::Result<()> {
+        let value = json!({
+            "title": "Animals",
+            "count": 3,
+            "price": 3.5,
+            "missing": null,
+            "animals": [
+                { "name": "cat", "tags": ["small", "fluffy"] },
+                { "name": "dog", "tags": [] },
+                [1, 2],
+            ],
+            "empty": {},
+            "nested": { "a": { "b": true } },
+        });
+
+        assert_eq!(
+            super::to_string(&value)?,
+            include_str!("../examples/outputs/structure.yaml")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn ambiguous_strings_are_quoted() -> serde_json::Result<()> {
+        for (s, yaml) in [
+            ("hello world", "hello world\n"),
+            ("", "\"\"\n"),
+            ("true", "\"true\"\n"),
+            ("No", "\"No\"\n"),
+            ("12", "\"12\"\n"),
+            ("a: b", "\"a: b\"\n"),
+            ("- item", "\"- item\"\n"),
+            ("line\nbreak", "\"line\\nbreak\"\n"),
+            (" padded ", "\" padded \"\n"),
+        ] {
+            assert_eq!(super::to_string(s)?, yaml, "{s:?}");
+        }
+
+        Ok(())
+    }
+}
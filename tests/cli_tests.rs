@@ -0,0 +1,146 @@
+use std::{
+    path::PathBuf,
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::Context;
+
+/// Returns a new path in the temporary directory, since tests run in parallel.
+fn temp_path(name: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let i = NEXT.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("scrapelect-cli-{}-{i}-{name}", std::process::id()))
+}
+
+/// Runs the `scrapelect` binary on `program` and the page `input`, returning
+/// its output.
+fn run(program: &str, input: &str, args: &[&str]) -> anyhow::Result<std::process::Output> {
+    let dir = temp_path("run");
+    std::fs::create_dir_all(&dir)?;
+
+    let file = dir.join("program.scrp");
+    let page = dir.join("page.html");
+    std::fs::write(&file, program)?;
+    std::fs::write(&page, input)?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_scrapelect"))
+        .args(args)
+        .arg(&file)
+        .arg(format!("file://{}", page.display()))
+        .output()
+        .context("running scrapelect failed")?;
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(output)
+}
+
+/// Runs the `scrapelect` binary on `program` without a URL, returning its output.
+fn run_without_url(program: &str, args: &[&str]) -> anyhow::Result<std::process::Output> {
+    let file = temp_path("program.scrp");
+    std::fs::write(&file, program)?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_scrapelect"))
+        .args(args)
+        .arg(&file)
+        .output()
+        .context("running scrapelect failed")?;
+
+    std::fs::remove_file(&file)?;
+    Ok(output)
+}
+
+#[test]
+fn quiet_writes_nothing_to_stderr() -> anyhow::Result<()> {
+    let program = r#"
+        title: h1 { text: $element | text() | dbg(msg: "title"); };
+        empty: "" | warn(msg: "empty");
+    "#;
+    let input = "<h1>Hello</h1>";
+
+    let loud = run(program, input, &["--compact"])?;
+    assert!(loud.status.success(), "{loud:?}");
+    let stderr = String::from_utf8(loud.stderr)?;
+    assert!(stderr.contains("title"), "{stderr}");
+    assert!(stderr.contains("empty"), "{stderr}");
+
+    let quiet = run(program, input, &["--compact", "--quiet"])?;
+    assert!(quiet.status.success(), "{quiet:?}");
+    assert_eq!(String::from_utf8(quiet.stderr)?, "");
+    assert_eq!(
+        String::from_utf8(quiet.stdout)?,
+        String::from_utf8(loud.stdout)?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn dump_ast_prints_the_program() -> anyhow::Result<()> {
+    let output = run_without_url(
+        r#"heading: h1 { text: $element | text(); };"#,
+        &["--dump-ast"],
+    )?;
+    assert!(output.status.success(), "{output:?}");
+
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("id: \"heading\""), "{stdout}");
+    assert!(stdout.contains("Tag(\n"), "{stdout}");
+    assert!(stdout.contains("id: \"text\""), "{stdout}");
+
+    let missing_url = run_without_url("a: 1;", &[])?;
+    assert!(!missing_url.status.success(), "{missing_url:?}");
+
+    Ok(())
+}
+
+#[test]
+fn check_reports_problems() -> anyhow::Result<()> {
+    let output = run_without_url(
+        r#"
+        title: h1 { text: $element | txt(); };
+        items: $element | texts(selector: "li[");
+        "#,
+        &["--check"],
+    )?;
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("error: unrecognized filter `txt` (in `title.text`)"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("error: invalid selector `li[`"), "{stderr}");
+    assert!(stderr.contains("found 2 problem(s)"), "{stderr}");
+
+    let clean = run_without_url("title: h1 { text: $element | text(); };", &["--check"])?;
+    assert!(clean.status.success(), "{clean:?}");
+    assert_eq!(String::from_utf8(clean.stderr)?, "");
+
+    Ok(())
+}
+
+#[test]
+fn output_writes_to_file() -> anyhow::Result<()> {
+    let program = "title: h1 { text: $element | text(); };";
+    let input = "<h1>Hello</h1>";
+    let path = temp_path("output.yaml");
+    std::fs::write(&path, "old contents that should be replaced")?;
+
+    let output = run(
+        program,
+        input,
+        &["--format", "yaml", "--output", &path.display().to_string()],
+    )?;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8(output.stdout)?, "");
+
+    let written = std::fs::read_to_string(&path)?;
+    std::fs::remove_file(&path)?;
+    assert_eq!(
+        written,
+        String::from_utf8(run(program, input, &["--format", "yaml"])?.stdout)?
+    );
+    assert!(written.contains("Hello"), "{written}");
+
+    Ok(())
+}